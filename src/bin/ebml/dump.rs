@@ -0,0 +1,156 @@
+use super::{open, CliResult};
+use ebml::ebml::{ChildElement, ElementDetail, ElementPosition, MasterElement};
+use ebml::schema::{DefaultSchema, SchemaDict};
+use ebml::Decoder;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+// bytes of binary payload printed in hex
+const BINARY_PREVIEW: usize = 16;
+
+#[derive(StructOpt, Debug)]
+pub struct DumpOpt {
+    /// input file, `-` for stdin
+    #[structopt(parse(from_os_str))]
+    input: PathBuf,
+    /// elements deeper than this are not printed; top-level elements are depth 0
+    #[structopt(long = "max-depth")]
+    max_depth: Option<usize>,
+    /// hides the payloads of binary elements
+    #[structopt(long = "hide-binary")]
+    hide_binary: bool,
+    /// prints only the subtrees of the elements of this name (repeatable)
+    #[structopt(long = "select")]
+    select: Vec<String>,
+}
+
+struct Printer<'a, W: Write> {
+    opt: &'a DumpOpt,
+    schema: &'a DefaultSchema,
+    out: &'a mut W,
+    depth: usize,
+    // depth of the selected subtree being printed
+    selected: Option<usize>,
+}
+
+pub fn run<W: Write>(opt: &DumpOpt, out: &mut W) -> CliResult {
+    let schema = DefaultSchema::default();
+    let mut decoder = Decoder::new(&schema);
+    let mut reader = open(&opt.input)?;
+    let mut printer = Printer {
+        opt,
+        schema: &schema,
+        out,
+        depth: 0,
+        selected: None,
+    };
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let size = reader.read(&mut buf)?;
+        if size == 0 {
+            break;
+        }
+        for elm in decoder.decode(buf[..size].to_vec())? {
+            printer.print(elm)?;
+        }
+    }
+    // closes unknown-size masters and fails on truncated input
+    for elm in decoder.finish()? {
+        printer.print(elm)?;
+    }
+    Ok(())
+}
+
+impl<'a, W: Write> Printer<'a, W> {
+    fn print(&mut self, elm: ElementDetail) -> CliResult {
+        match elm {
+            ElementDetail::MasterElement((MasterElement::MasterStartElement(o), pos)) => {
+                if self.selected.is_none() && self.matches(&pos) {
+                    self.selected = Some(self.depth);
+                }
+                let size = if o.unknown_size {
+                    "unknown".to_string()
+                } else {
+                    pos.content_size.to_string()
+                };
+                self.line(&pos, &size, None)?;
+                self.depth += 1;
+            }
+            ElementDetail::MasterElement((MasterElement::MasterEndElement(_), _)) => {
+                self.depth = self.depth.saturating_sub(1);
+                if self.selected == Some(self.depth) {
+                    self.selected = None;
+                }
+            }
+            ElementDetail::ChildElement((o, pos)) => {
+                let selected = self.selected.is_some() || self.matches(&pos);
+                if selected {
+                    let value = self.value(o);
+                    self.line(&pos, &pos.content_size.to_string(), Some(&value))?;
+                }
+            }
+        }
+        Ok(())
+    }
+    fn matches(&self, pos: &ElementPosition) -> bool {
+        self.opt.select.is_empty() || self.opt.select.iter().any(|o| o == self.name(pos))
+    }
+    fn name(&self, pos: &ElementPosition) -> &'a str {
+        self.schema
+            .get(pos.ebml_id)
+            .map(|o| o.name())
+            .unwrap_or("Unknown")
+    }
+    fn line(&mut self, pos: &ElementPosition, size: &str, value: Option<&str>) -> CliResult {
+        if self.selected.is_none() && !self.matches(pos) {
+            return Ok(());
+        }
+        if self.opt.max_depth.map(|o| self.depth > o).unwrap_or(false) {
+            return Ok(());
+        }
+        write!(
+            self.out,
+            "{:indent$}{} [{:X}] offset={} header={} size={}",
+            "",
+            self.name(pos),
+            pos.ebml_id.0,
+            pos.tag_start,
+            pos.content_start - pos.tag_start,
+            size,
+            indent = self.depth * 2
+        )?;
+        if let Some(value) = value {
+            write!(self.out, ": {}", value)?;
+        }
+        writeln!(self.out)?;
+        Ok(())
+    }
+    fn value(&self, elm: ChildElement) -> String {
+        match elm {
+            ChildElement::UnsignedIntegerElement(o) => o.value.to_string(),
+            ChildElement::IntegerElement(o) => o.value.to_string(),
+            ChildElement::FloatElement(o) => o.value.to_string(),
+            ChildElement::StringElement(o) => format!("{:?}", String::from_utf8_lossy(&o.value)),
+            ChildElement::Utf8Element(o) => format!("{:?}", o.value),
+            ChildElement::DateElement(o) => o.value.to_rfc3339(),
+            ChildElement::BinaryElement(o) => {
+                if self.opt.hide_binary {
+                    format!("<{} bytes>", o.value.len())
+                } else {
+                    let mut hex = o
+                        .value
+                        .iter()
+                        .take(BINARY_PREVIEW)
+                        .map(|b| format!("{:02X}", b))
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    if o.value.len() > BINARY_PREVIEW {
+                        hex += " ...";
+                    }
+                    hex
+                }
+            }
+        }
+    }
+}
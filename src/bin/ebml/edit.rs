@@ -0,0 +1,63 @@
+use super::CliResult;
+use ebml::edit::{apply, plan, Edit};
+use ebml::schema::{DefaultSchema, SchemaDict};
+use serde_json::Value;
+use std::io::Write;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct EditOpt {
+    /// EBML file to modify in place
+    #[structopt(parse(from_os_str))]
+    input: PathBuf,
+    /// `PATH=VALUE` such as `Segment/Info/Title=foo` or `Segment/Tracks/TrackEntry[0]/Language=jpn`
+    #[structopt(long = "set", required = true, number_of_values = 1)]
+    set: Vec<String>,
+    /// prints the patches without writing them
+    #[structopt(long = "dry-run")]
+    dry_run: bool,
+}
+
+pub fn run<W: Write>(opt: &EditOpt, out: &mut W) -> CliResult {
+    let schema = DefaultSchema::default();
+    let edits = opt
+        .set
+        .iter()
+        .map(|o| parse_set(&schema, o))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(!opt.dry_run)
+        .open(&opt.input)?;
+    let patches = plan(&mut file, &schema, &edits)?;
+    if !opt.dry_run {
+        apply(&mut file, &patches)?;
+    }
+    for patch in patches {
+        writeln!(out, "offset={} length={}", patch.offset, patch.data.len())?;
+    }
+    Ok(())
+}
+
+/// numbers for `u` `i` `f` elements, strings for the others
+fn parse_set(schema: &DefaultSchema, set: &str) -> Result<Edit, String> {
+    let i = set
+        .find('=')
+        .ok_or_else(|| format!("expected PATH=VALUE: {}", set))?;
+    let (path, value) = (&set[..i], &set[i + 1..]);
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let name = name.split('[').next().unwrap_or(name);
+    let numeric = schema
+        .get_by_name(name)
+        .map(|(_, o)| "uif".contains(o.r#type()))
+        .unwrap_or(false);
+    let value = match serde_json::from_str::<Value>(value) {
+        Ok(o) if numeric && o.is_number() => o,
+        _ => Value::from(value),
+    };
+    Ok(Edit {
+        path: path.to_string(),
+        value,
+    })
+}
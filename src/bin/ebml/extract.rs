@@ -0,0 +1,210 @@
+use super::{open, CliResult};
+use ebml::matroska::{Demuxer, Packet, Track};
+use ebml::schema::DefaultSchema;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct ExtractOpt {
+    /// input Matroska / WebM file, `-` for stdin
+    #[structopt(parse(from_os_str))]
+    input: PathBuf,
+    /// directory to write the files into
+    #[structopt(
+        short = "d",
+        long = "output-dir",
+        parse(from_os_str),
+        default_value = "."
+    )]
+    output_dir: PathBuf,
+    /// writes the frames of the track into `track<N>.ivf` (VP8/VP9/AV1) or `track<N>.raw` (repeatable)
+    #[structopt(long = "track")]
+    tracks: Vec<u64>,
+    /// writes the frames as they are even for VP8/VP9/AV1
+    #[structopt(long = "raw")]
+    raw: bool,
+    /// writes CodecPrivate of the track into `track<N>.codec_private` (repeatable)
+    #[structopt(long = "codec-private")]
+    codec_private: Vec<u64>,
+    /// writes all attachments under their FileName
+    #[structopt(long = "attachments")]
+    attachments: bool,
+}
+
+/// IVF file of a track
+struct IvfWriter {
+    file: BufWriter<File>,
+    frames: u32,
+}
+
+enum TrackWriter {
+    Ivf(IvfWriter),
+    Raw(BufWriter<File>),
+}
+
+pub fn run<W: Write>(opt: &ExtractOpt, out: &mut W) -> CliResult {
+    let schema = DefaultSchema::default();
+    let mut demuxer = Demuxer::new(&schema);
+    let mut reader = open(&opt.input)?;
+    let mut writers: Vec<(u64, TrackWriter)> = vec![];
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let size = reader.read(&mut buf)?;
+        if size == 0 {
+            break;
+        }
+        for packet in demuxer.demux(buf[..size].to_vec())? {
+            if !opt.tracks.contains(&packet.track) {
+                continue;
+            }
+            if writers.iter().all(|(o, _)| *o != packet.track) {
+                let track = demuxer
+                    .track(packet.track)
+                    .ok_or_else(|| format!("track {} is not in Tracks", packet.track))?;
+                let writer = create_track_writer(opt, track, out)?;
+                writers.push((packet.track, writer));
+            }
+            let (_, writer) = writers
+                .iter_mut()
+                .find(|(o, _)| *o == packet.track)
+                .unwrap();
+            writer.write(&packet)?;
+        }
+    }
+    for (_, writer) in writers {
+        writer.finish()?;
+    }
+    for &number in opt.codec_private.iter() {
+        let codec_private = demuxer
+            .track(number)
+            .and_then(|o| o.codec_private.as_ref())
+            .ok_or_else(|| format!("track {} has no CodecPrivate", number))?;
+        let path = opt
+            .output_dir
+            .join(format!("track{}.codec_private", number));
+        std::fs::write(&path, codec_private)?;
+        writeln!(out, "{}", path.display())?;
+    }
+    if opt.attachments {
+        for attachment in demuxer.attachments() {
+            // FileName must not escape the output directory
+            let name = Path::new(&attachment.name)
+                .file_name()
+                .ok_or_else(|| format!("invalid FileName: {:?}", attachment.name))?;
+            let path = opt.output_dir.join(name);
+            std::fs::write(&path, &attachment.data)?;
+            writeln!(out, "{}", path.display())?;
+        }
+    }
+    Ok(())
+}
+
+fn create_track_writer<W: Write>(
+    opt: &ExtractOpt,
+    track: &Track,
+    out: &mut W,
+) -> std::io::Result<TrackWriter> {
+    let fourcc = match track.codec_id.as_str() {
+        "V_VP8" => Some(b"VP80"),
+        "V_VP9" => Some(b"VP90"),
+        "V_AV1" => Some(b"AV01"),
+        _ => None,
+    };
+    match fourcc {
+        Some(fourcc) if !opt.raw => {
+            let (width, height) = track
+                .video
+                .as_ref()
+                .map(|o| (o.pixel_width, o.pixel_height))
+                .unwrap_or((0, 0));
+            // the IVF header has 16 bits for each
+            let (width, height) = match (u16::try_from(width), u16::try_from(height)) {
+                (Ok(width), Ok(height)) => (width, height),
+                _ => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!(
+                            "track {}: {}x{} is too large for IVF",
+                            track.number, width, height
+                        ),
+                    ))
+                }
+            };
+            let path = opt.output_dir.join(format!("track{}.ivf", track.number));
+            writeln!(out, "{}", path.display())?;
+            let mut file = BufWriter::new(File::create(path)?);
+            // 32 bytes header; timebase is 1/1000 sec
+            file.write_all(b"DKIF")?;
+            file.write_all(&0u16.to_le_bytes())?;
+            file.write_all(&32u16.to_le_bytes())?;
+            file.write_all(fourcc)?;
+            file.write_all(&width.to_le_bytes())?;
+            file.write_all(&height.to_le_bytes())?;
+            file.write_all(&1000u32.to_le_bytes())?;
+            file.write_all(&1u32.to_le_bytes())?;
+            // frame count, written on finish
+            file.write_all(&0u32.to_le_bytes())?;
+            file.write_all(&0u32.to_le_bytes())?;
+            Ok(TrackWriter::Ivf(IvfWriter { file, frames: 0 }))
+        }
+        _ => {
+            let path = opt.output_dir.join(format!("track{}.raw", track.number));
+            writeln!(out, "{}", path.display())?;
+            Ok(TrackWriter::Raw(BufWriter::new(File::create(path)?)))
+        }
+    }
+}
+
+impl TrackWriter {
+    fn write(&mut self, packet: &Packet) -> std::io::Result<()> {
+        match self {
+            TrackWriter::Ivf(o) => {
+                o.file
+                    .write_all(&ivf_frame_header(packet.data.len(), packet.pts_ns)?)?;
+                o.file.write_all(&packet.data)?;
+                o.frames += 1;
+            }
+            TrackWriter::Raw(o) => o.write_all(&packet.data)?,
+        }
+        Ok(())
+    }
+    fn finish(self) -> std::io::Result<()> {
+        match self {
+            TrackWriter::Ivf(mut o) => {
+                o.file.seek(SeekFrom::Start(24))?;
+                o.file.write_all(&o.frames.to_le_bytes())?;
+                o.file.flush()
+            }
+            TrackWriter::Raw(mut o) => o.flush(),
+        }
+    }
+}
+
+/// 12 bytes header of an IVF frame; the frame size has 32 bits
+fn ivf_frame_header(len: usize, pts_ns: i64) -> std::io::Result<Vec<u8>> {
+    let len = u32::try_from(len).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{} bytes frame is too large for IVF", len),
+        )
+    })?;
+    let mut buf = len.to_le_bytes().to_vec();
+    buf.extend_from_slice(&(pts_ns / 1_000_000).to_le_bytes());
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ivf_frame_header;
+
+    #[test]
+    fn test_ivf_frame_header() {
+        let buf = ivf_frame_header(3, 40_000_000).unwrap();
+        assert_eq!(buf, vec![3, 0, 0, 0, 40, 0, 0, 0, 0, 0, 0, 0]);
+        let err = ivf_frame_header(1 << 32, 0).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,43 @@
+use super::{open, CliResult};
+use ebml::json::{ebml_to_json, json_to_ebml};
+use ebml::schema::DefaultSchema;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct ToJsonOpt {
+    /// input EBML file, `-` for stdin
+    #[structopt(parse(from_os_str))]
+    input: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct FromJsonOpt {
+    /// input JSON file, `-` for stdin
+    #[structopt(parse(from_os_str))]
+    input: PathBuf,
+    /// output EBML file; stdout when omitted
+    #[structopt(short = "o", long = "output", parse(from_os_str))]
+    output: Option<PathBuf>,
+}
+
+pub fn to_json<W: Write>(opt: &ToJsonOpt, out: &mut W) -> CliResult {
+    let schema = DefaultSchema::default();
+    let mut buf = vec![];
+    open(&opt.input)?.read_to_end(&mut buf)?;
+    writeln!(out, "{}", ebml_to_json(&schema, buf)?)?;
+    Ok(())
+}
+
+pub fn from_json<W: Write>(opt: &FromJsonOpt, out: &mut W) -> CliResult {
+    let schema = DefaultSchema::default();
+    let mut json = String::new();
+    open(&opt.input)?.read_to_string(&mut json)?;
+    let buf = json_to_ebml(&schema, &json)?;
+    match opt.output.as_ref() {
+        Some(path) => std::fs::write(path, buf)?,
+        None => out.write_all(&buf)?,
+    }
+    Ok(())
+}
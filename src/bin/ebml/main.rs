@@ -0,0 +1,61 @@
+//! `ebml` command-line tool
+use std::io::Read;
+use std::path::Path;
+use structopt::StructOpt;
+
+mod dump;
+mod edit;
+mod extract;
+mod json;
+mod validate;
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "ebml")]
+enum Opt {
+    /// prints the element tree
+    #[structopt(name = "dump")]
+    Dump(dump::DumpOpt),
+    /// converts EBML into JSON
+    #[structopt(name = "to-json")]
+    ToJson(json::ToJsonOpt),
+    /// converts JSON made by `to-json` back into EBML
+    #[structopt(name = "from-json")]
+    FromJson(json::FromJsonOpt),
+    /// checks schema constraints and prints the findings as JSON lines
+    #[structopt(name = "validate")]
+    Validate(validate::ValidateOpt),
+    /// writes track frames, CodecPrivate and attachments into files
+    #[structopt(name = "extract")]
+    Extract(extract::ExtractOpt),
+    /// rewrites elements in place using the following Void space
+    #[structopt(name = "edit")]
+    Edit(edit::EditOpt),
+}
+
+type CliResult = Result<(), Box<dyn std::error::Error>>;
+
+/// opens the file, or stdin for `-`
+fn open(path: &Path) -> std::io::Result<Box<dyn Read>> {
+    if path == Path::new("-") {
+        Ok(Box::new(std::io::stdin()))
+    } else {
+        Ok(Box::new(std::fs::File::open(path)?))
+    }
+}
+
+fn main() {
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    let ret = match Opt::from_args() {
+        Opt::Dump(opt) => dump::run(&opt, &mut out),
+        Opt::ToJson(opt) => json::to_json(&opt, &mut out),
+        Opt::FromJson(opt) => json::from_json(&opt, &mut out),
+        Opt::Validate(opt) => validate::run(&opt, &mut out),
+        Opt::Extract(opt) => extract::run(&opt, &mut out),
+        Opt::Edit(opt) => edit::run(&opt, &mut out),
+    };
+    if let Err(err) = ret {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
+}
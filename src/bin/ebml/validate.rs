@@ -0,0 +1,27 @@
+use super::{open, CliResult};
+use ebml::schema::DefaultSchema;
+use ebml::validate::validate_reader;
+use std::io::Write;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct ValidateOpt {
+    /// input file, `-` for stdin
+    #[structopt(parse(from_os_str))]
+    input: PathBuf,
+}
+
+/// prints the findings as JSON lines and fails when there is any
+pub fn run<W: Write>(opt: &ValidateOpt, out: &mut W) -> CliResult {
+    let schema = DefaultSchema::default();
+    let findings = validate_reader(&schema, &mut open(&opt.input)?)?;
+    for finding in findings.iter() {
+        writeln!(out, "{}", serde_json::to_string(finding)?)?;
+    }
+    if findings.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{} finding(s)", findings.len()).into())
+    }
+}
@@ -0,0 +1,174 @@
+use crate::ebml;
+use crate::vint::{read_vint, write_vint, UnrepresentableLengthError, UnrepresentableValueError};
+use err_derive::Error;
+use log_derive::{logfn, logfn_inputs};
+use std::convert::TryFrom;
+
+#[derive(Debug, Error)]
+pub enum ReadBlockError {
+    #[error(display = "{}", _0)]
+    ReadVint(#[error(cause)] UnrepresentableLengthError),
+    #[error(display = "block is truncated")]
+    Truncated,
+    #[error(display = "invalid lacing")]
+    InvalidLacing,
+}
+
+impl From<UnrepresentableLengthError> for ReadBlockError {
+    fn from(o: UnrepresentableLengthError) -> Self {
+        ReadBlockError::ReadVint(o)
+    }
+}
+
+/// https://www.matroska.org/technical/specs/index.html#simpleblock_structure
+/// reads the payload of a SimpleBlock or a Block.
+/// Block has no keyframe flag so `keyframe` is always false for it.
+#[logfn_inputs(TRACE)]
+#[logfn(ok = "TRACE", err = "ERROR")]
+pub fn read_block(buf: &[u8]) -> Result<ebml::SimpleBlock, ReadBlockError> {
+    use ReadBlockError::*;
+    let track = read_vint(buf, 0)?.ok_or(Truncated)?;
+    let mut cursor = track.length as usize;
+    if buf.len() < cursor + 3 {
+        return Err(Truncated);
+    }
+    let timecode = i64::from(i16::from_be_bytes([buf[cursor], buf[cursor + 1]]));
+    let flags = buf[cursor + 2];
+    cursor += 3;
+    let keyframe = flags & 0b_1000_0000 != 0;
+    let invisible = flags & 0b_0000_1000 != 0;
+    let lacing = (flags & 0b_0000_0110) >> 1;
+    let discardable = flags & 0b_0000_0001 != 0;
+    let frames = if lacing == 0 {
+        vec![buf[cursor..].to_vec()]
+    } else {
+        let count = usize::from(*buf.get(cursor).ok_or(Truncated)?) + 1;
+        cursor += 1;
+        let sizes = match lacing {
+            // Xiph lacing
+            0b01 => {
+                let mut sizes = vec![];
+                for _ in 0..count - 1 {
+                    let mut size = 0;
+                    loop {
+                        let b = *buf.get(cursor).ok_or(Truncated)?;
+                        cursor += 1;
+                        size += usize::from(b);
+                        if b != 0xFF {
+                            break;
+                        }
+                    }
+                    sizes.push(size);
+                }
+                sizes
+            }
+            // fixed-size lacing
+            0b10 => {
+                let rest = buf.len() - cursor;
+                if !rest.is_multiple_of(count) {
+                    return Err(InvalidLacing);
+                }
+                vec![rest / count; count - 1]
+            }
+            // EBML lacing
+            _ => {
+                let mut sizes = vec![];
+                let first = read_vint(buf, cursor)?.ok_or(Truncated)?;
+                cursor += first.length as usize;
+                let mut size = first.value;
+                sizes.push(size);
+                for _ in 1..count - 1 {
+                    let diff = read_vint(buf, cursor)?.ok_or(Truncated)?;
+                    cursor += diff.length as usize;
+                    // signed vint: subtract half of the range
+                    size += diff.value - ((1 << (7 * i64::from(diff.length) - 1)) - 1);
+                    sizes.push(size);
+                }
+                let mut usizes = vec![];
+                for size in sizes {
+                    if size < 0 {
+                        return Err(InvalidLacing);
+                    }
+                    usizes.push(size as usize);
+                }
+                usizes
+            }
+        };
+        let mut frames = vec![];
+        for size in sizes {
+            if buf.len() < cursor + size {
+                return Err(Truncated);
+            }
+            frames.push(buf[cursor..cursor + size].to_vec());
+            cursor += size;
+        }
+        // the last frame takes the rest
+        frames.push(buf[cursor..].to_vec());
+        frames
+    };
+    Ok(ebml::SimpleBlock {
+        discardable,
+        frames,
+        invisible,
+        keyframe,
+        timecode,
+        track_number: track.value,
+    })
+}
+
+#[derive(Debug, Error)]
+pub enum WriteBlockError {
+    #[error(display = "{}", _0)]
+    WriteVint(#[error(cause)] UnrepresentableValueError),
+    #[error(display = "timecode out of range: {}", _0)]
+    TimecodeOutOfRange(i64),
+    #[error(display = "too many frames: {}", _0)]
+    TooManyFrames(usize),
+}
+
+impl From<UnrepresentableValueError> for WriteBlockError {
+    fn from(o: UnrepresentableValueError) -> Self {
+        WriteBlockError::WriteVint(o)
+    }
+}
+
+/// writes the payload of a SimpleBlock or a Block.
+/// multiple frames are written with Xiph lacing.
+#[logfn(ok = "TRACE", err = "ERROR")]
+pub fn write_block(block: &ebml::SimpleBlock) -> Result<Vec<u8>, WriteBlockError> {
+    use WriteBlockError::*;
+    let timecode = i16::try_from(block.timecode).map_err(|_| TimecodeOutOfRange(block.timecode))?;
+    let mut flags = 0;
+    if block.keyframe {
+        flags |= 0b_1000_0000;
+    }
+    if block.invisible {
+        flags |= 0b_0000_1000;
+    }
+    if block.frames.len() > 1 {
+        flags |= 0b_0000_0010;
+    }
+    if block.discardable {
+        flags |= 0b_0000_0001;
+    }
+    let mut buf = write_vint(block.track_number)?;
+    buf.extend_from_slice(&timecode.to_be_bytes());
+    buf.push(flags);
+    if block.frames.len() > 1 {
+        let count =
+            u8::try_from(block.frames.len() - 1).map_err(|_| TooManyFrames(block.frames.len()))?;
+        buf.push(count);
+        for frame in block.frames[..block.frames.len() - 1].iter() {
+            let mut size = frame.len();
+            while size >= 0xFF {
+                buf.push(0xFF);
+                size -= 0xFF;
+            }
+            buf.push(size as u8);
+        }
+    }
+    for frame in block.frames.iter() {
+        buf.extend_from_slice(frame);
+    }
+    Ok(buf)
+}
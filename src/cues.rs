@@ -0,0 +1,178 @@
+use crate::ebml;
+use crate::ids;
+use crate::schema::SchemaDict;
+use crate::segment::{SegmentError, SegmentIndex};
+use err_derive::Error;
+use log_derive::logfn;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Seek, SeekFrom};
+
+#[derive(Debug, Error)]
+pub enum CuesError {
+    #[error(display = "Segment")]
+    Segment(#[error(cause)] SegmentError),
+    #[error(display = "Io")]
+    Io(#[error(cause)] std::io::Error),
+    #[error(display = "Cues not found")]
+    CuesNotFound,
+    #[error(display = "Info not found")]
+    InfoNotFound,
+}
+
+impl From<SegmentError> for CuesError {
+    fn from(o: SegmentError) -> Self {
+        CuesError::Segment(o)
+    }
+}
+
+impl From<std::io::Error> for CuesError {
+    fn from(o: std::io::Error) -> Self {
+        CuesError::Io(o)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct CuePoint {
+    // CueTime in TimecodeScale units
+    pub time: u64,
+    pub positions: Vec<CueTrackPosition>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct CueTrackPosition {
+    pub track: u64,
+    // absolute offset of the Cluster in the stream
+    pub cluster_position: u64,
+    // offset of the block relative to the Cluster data
+    pub relative_position: Option<u64>,
+}
+
+/// index of the `Cues` element with positions resolved to absolute stream offsets
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Cues {
+    // absolute offset of the first byte of the Segment data
+    pub segment_start: u64,
+    pub timecode_scale: u64,
+    pub points: Vec<CuePoint>,
+}
+
+impl Cues {
+    /// reads `Cues` and `Info` through `SeekHead`, scanning the top-level elements when `SeekHead`
+    /// misses either of them
+    #[logfn(ok = "TRACE", err = "ERROR")]
+    pub fn read<R: Read + Seek, D: SchemaDict + ?Sized>(
+        reader: &mut R,
+        schema: &D,
+    ) -> Result<Self, CuesError> {
+        let mut index = SegmentIndex::read(reader, schema)?;
+        if index.position(ids::CUES).is_none() || index.position(ids::INFO).is_none() {
+            index = SegmentIndex::scan(reader)?;
+        }
+        let info = index
+            .load(reader, schema, ids::INFO)?
+            .ok_or(CuesError::InfoNotFound)?;
+        // TimecodeScale is optional with the default of 1ms
+        let mut timecode_scale = 1_000_000;
+        for elm in info {
+            if let ebml::Element::ChildElement(ebml::ChildElement::UnsignedIntegerElement(o)) =
+                elm.into()
+            {
+                if o.ebml_id == ids::TIMECODE_SCALE {
+                    timecode_scale = o.value;
+                }
+            }
+        }
+        let cues = index
+            .load(reader, schema, ids::CUES)?
+            .ok_or(CuesError::CuesNotFound)?;
+        let points = parse_cue_points(cues, index.segment_start);
+        Ok(Cues {
+            segment_start: index.segment_start,
+            timecode_scale,
+            points,
+        })
+    }
+    /// the last cue point of the track at or before `timestamp` (in nanoseconds)
+    pub fn find(&self, track: u64, timestamp: u64) -> Option<(&CuePoint, &CueTrackPosition)> {
+        self.points
+            .iter()
+            .filter(|o| o.time.saturating_mul(self.timecode_scale) <= timestamp)
+            .filter_map(|o| {
+                o.positions
+                    .iter()
+                    .find(|pos| pos.track == track)
+                    .map(|pos| (o, pos))
+            })
+            .max_by_key(|(o, _)| o.time)
+    }
+    /// moves the reader to the Cluster containing `timestamp` (in nanoseconds) for the track,
+    /// so that a new `Decoder` can start decoding there
+    pub fn seek<R: Seek>(
+        &self,
+        reader: &mut R,
+        track: u64,
+        timestamp: u64,
+    ) -> Result<Option<&CuePoint>, CuesError> {
+        match self.find(track, timestamp) {
+            Some((point, pos)) => {
+                reader.seek(SeekFrom::Start(pos.cluster_position))?;
+                Ok(Some(point))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+fn parse_cue_points(elms: Vec<ebml::ElementDetail>, segment_start: u64) -> Vec<CuePoint> {
+    let mut points = vec![];
+    let mut point: Option<CuePoint> = None;
+    let mut position: Option<CueTrackPosition> = None;
+    for elm in elms {
+        match elm.into() {
+            ebml::Element::MasterElement(ebml::MasterElement::MasterStartElement(o)) => {
+                match o.ebml_id {
+                    ids::CUE_POINT => {
+                        point = Some(CuePoint {
+                            time: 0,
+                            positions: vec![],
+                        })
+                    }
+                    ids::CUE_TRACK_POSITIONS => {
+                        position = Some(CueTrackPosition {
+                            track: 0,
+                            cluster_position: segment_start,
+                            relative_position: None,
+                        })
+                    }
+                    _ => {}
+                }
+            }
+            ebml::Element::MasterElement(ebml::MasterElement::MasterEndElement(o)) => {
+                match o.ebml_id {
+                    ids::CUE_POINT => points.extend(point.take()),
+                    ids::CUE_TRACK_POSITIONS => {
+                        if let (Some(point), Some(position)) = (point.as_mut(), position.take()) {
+                            point.positions.push(position);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            ebml::Element::ChildElement(ebml::ChildElement::UnsignedIntegerElement(o)) => {
+                match (o.ebml_id, point.as_mut(), position.as_mut()) {
+                    (ids::CUE_TIME, Some(point), _) => point.time = o.value,
+                    (ids::CUE_TRACK, _, Some(position)) => position.track = o.value,
+                    (ids::CUE_CLUSTER_POSITION, _, Some(position)) => {
+                        position.cluster_position = segment_start + o.value
+                    }
+                    (ids::CUE_RELATIVE_POSITION, _, Some(position)) => {
+                        position.relative_position = Some(o.value)
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    points
+}
@@ -1,16 +1,16 @@
 use crate::ebml;
-use crate::schema::{Schema, SchemaDict};
-use crate::vint::{read_vint, UnrepresentableLengthError};
-use chrono::{DateTime, NaiveDateTime, Utc};
+use crate::ids;
+use crate::schema::{SchemaDict, SchemaRegistry, SharedSchema};
+use crate::vint::{read_vint, ReadVintError, ReadVintExt, UnrepresentableLengthError};
 use err_derive::Error;
 use log_derive::{logfn, logfn_inputs};
-use std::convert::TryFrom;
+use serde::{Deserialize, Serialize};
 
 pub trait ReadEbmlExt: std::io::Read {
     #[logfn(ok = "TRACE", err = "ERROR")]
-    fn read_ebml_to_end<'a, D: SchemaDict<'a>>(
+    fn read_ebml_to_end<D: SchemaDict + ?Sized>(
         &mut self,
-        schema: &'a D,
+        schema: &D,
     ) -> Result<Vec<ebml::ElementDetail>, DecodeError> {
         let mut decoder = Decoder::new(schema);
         let mut buf = vec![];
@@ -18,15 +18,35 @@ pub trait ReadEbmlExt: std::io::Read {
         let elms = decoder.decode(buf)?;
         Ok(elms)
     }
+    /// reads the id and the size of the next element
+    #[logfn(ok = "TRACE", err = "ERROR")]
+    fn read_element_header(&mut self) -> Result<ebml::ElementHeader, DecodeError> {
+        let read = |o: &mut Self| match o.read_vint() {
+            Ok(vint) => Ok(vint),
+            Err(ReadVintError::NeedMoreBuffer(err)) => Err(DecodeError::Io(err)),
+            Err(ReadVintError::UnrepresentableLength(err)) => Err(DecodeError::ReadVint(err)),
+        };
+        let tag = read(self)?;
+        let size = read(self)?;
+        Ok(ebml::ElementHeader {
+            ebml_id: ebml::EbmlId(tag.value | (1 << (7 * i64::from(tag.length)))),
+            header_size: usize::from(tag.length + size.length),
+            content_size: if size.value == (1 << (7 * i64::from(size.length))) - 1 {
+                -1
+            } else {
+                size.value
+            },
+        })
+    }
 }
 
 impl<R: std::io::Read + ?Sized> ReadEbmlExt for R {}
 
 pub trait BufReadEbmlExt: std::io::BufRead {
     #[logfn(ok = "TRACE", err = "ERROR")]
-    fn read<'a, D: SchemaDict<'a>>(
+    fn read<D: SchemaDict + ?Sized>(
         &mut self,
-        schema: &'a D,
+        schema: &D,
     ) -> Result<Vec<ebml::ElementDetail>, DecodeError> {
         let mut decoder = Decoder::new(schema);
         let mut buf = vec![];
@@ -57,12 +77,41 @@ pub enum DecodeError {
     ReadVint(#[error(cause)] UnrepresentableLengthError),
     #[error(display = "UnknwonSizeNotAllowedInChildElement: pos {:?}", _0)]
     UnknwonSizeNotAllowedInChildElement(ebml::ElementPosition),
-    #[error(display = "ReadContent")]
+    #[error(display = "{}", _0)]
     ReadContent(#[error(cause)] ReadContentError),
     #[error(display = "UnknownEbmlId: {:?}", _0)]
     UnknownEbmlId(ebml::EbmlId),
     #[error(display = "Io")]
     Io(#[error(cause)] std::io::Error),
+    #[error(display = "unexpected end of data")]
+    UnexpectedEnd,
+    #[error(display = "invalid checkpoint")]
+    InvalidCheckpoint,
+    #[error(display = "invalid ancestor: {:?}", _0)]
+    InvalidAncestor(ebml::ElementPosition),
+    #[error(display = "no schema for DocType {}", _0)]
+    UnknownDocType(String),
+    #[error(display = "no DocType in the EBML header and no default")]
+    MissingDocType,
+    #[error(display = "{} {}", _0, _1)]
+    Context(#[error(cause)] Box<DecodeError>, ebml::ErrorContext),
+}
+
+impl DecodeError {
+    /// where the error occurred; only errors from `Decoder` have it
+    pub fn context(&self) -> Option<&ebml::ErrorContext> {
+        match self {
+            DecodeError::Context(_, context) => Some(context),
+            _ => None,
+        }
+    }
+    /// the error without context
+    pub fn kind(&self) -> &DecodeError {
+        match self {
+            DecodeError::Context(err, _) => err.kind(),
+            err => err,
+        }
+    }
 }
 
 impl From<UnrepresentableLengthError> for DecodeError {
@@ -77,15 +126,120 @@ impl From<ReadContentError> for DecodeError {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 enum State {
     Tag,
     Size,
     Content,
 }
 
-pub struct Decoder<'a, D: SchemaDict<'a>> {
-    schema: &'a D,
+/// how to read string contents and which elements to emit
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct DecoderOptions {
+    // `s` must be printable ASCII (0x20-0x7E) followed by zero padding
+    pub strict_strings: bool,
+    // invalid UTF-8 in `8` is replaced with U+FFFD instead of an error
+    pub lossy_utf8: bool,
+    pub filter: ElementFilter,
+}
+
+/// element selected by its EBML ID anywhere, or by its names from the top level
+/// such as `Segment/Tracks`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ElementSelector {
+    Id(ebml::EbmlId),
+    Path(String),
+}
+
+/// elements to emit. elements not emitted are skipped by size without being read,
+/// except for unknown-size masters, whose children are read but not emitted.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct ElementFilter {
+    // when not empty, only the matching elements and their descendants are emitted.
+    // masters which may contain them are read without their start and end.
+    pub include: Vec<ElementSelector>,
+    // the matching elements and their descendants are not emitted, even when included
+    pub exclude: Vec<ElementSelector>,
+}
+
+// `ElementFilter` with the names of the paths resolved to IDs by the current schema.
+// None for a name not in the schema, which matches no element
+#[derive(Debug, Clone, Default)]
+struct ResolvedFilter {
+    include: Vec<ResolvedSelector>,
+    exclude: Vec<ResolvedSelector>,
+}
+
+#[derive(Debug, Clone)]
+enum ResolvedSelector {
+    Id(ebml::EbmlId),
+    Path(Vec<Option<ebml::EbmlId>>),
+}
+
+impl ResolvedFilter {
+    fn new<D: SchemaDict>(filter: &ElementFilter, schema: &D) -> Self {
+        let resolve = |selectors: &[ElementSelector]| {
+            selectors
+                .iter()
+                .map(|o| match o {
+                    ElementSelector::Id(ebml_id) => ResolvedSelector::Id(*ebml_id),
+                    ElementSelector::Path(path) => ResolvedSelector::Path(
+                        path.split('/')
+                            .map(|name| schema.get_by_name(name).map(|o| o.0))
+                            .collect(),
+                    ),
+                })
+                .collect()
+        };
+        Self {
+            include: resolve(&filter.include),
+            exclude: resolve(&filter.exclude),
+        }
+    }
+}
+
+/// saved state of `Decoder` to resume decoding a stream later
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DecoderCheckpoint {
+    state: State,
+    // absolute offset of the first pending byte
+    total: usize,
+    // open elements from the top level
+    stack: Vec<ebml::ElementPosition>,
+    // bytes fed but not decoded yet
+    pending: Vec<u8>,
+    #[serde(default)]
+    doc_type: Option<String>,
+}
+
+impl DecoderCheckpoint {
+    /// absolute offset of the stream to feed from after `restore`
+    pub fn offset(&self) -> usize {
+        self.total + self.pending.len()
+    }
+}
+
+// schema for the DocType; None for the EBML header.
+// a fn pointer, not a closure, so that dropping the decoder does not need the schema borrowed
+struct SchemaSelector<D> {
+    registry: SchemaRegistry,
+    select: fn(&SchemaRegistry, Option<&str>) -> Option<D>,
+}
+
+impl<D> SchemaSelector<D> {
+    fn select(&self, doc_type: Option<&str>) -> Option<D> {
+        (self.select)(&self.registry, doc_type)
+    }
+}
+
+pub struct Decoder<D: SchemaDict> {
+    schema: D,
+    options: DecoderOptions,
+    filter: ResolvedFilter,
+    selector: Option<SchemaSelector<D>>,
+    // DocType of the last EBML header
+    doc_type: Option<String>,
     state: State,
     buffer: Vec<u8>,
     cursor: usize,
@@ -94,10 +248,32 @@ pub struct Decoder<'a, D: SchemaDict<'a>> {
     queue: Vec<ebml::ElementDetail>,
 }
 
-impl<'a, D: SchemaDict<'a>> Decoder<'a, D> {
-    pub fn new(schema: &'a D) -> Self {
+impl Decoder<SharedSchema> {
+    /// reads the EBML header with its own schema and the rest with the schema for its DocType
+    pub fn with_registry(registry: SchemaRegistry, options: DecoderOptions) -> Self {
+        let mut decoder = Self::with_options(registry.header(), options);
+        decoder.selector = Some(SchemaSelector {
+            registry,
+            select: |registry, doc_type| match doc_type {
+                Some(doc_type) => registry.get(doc_type),
+                None => Some(registry.header()),
+            },
+        });
+        decoder
+    }
+}
+
+impl<D: SchemaDict> Decoder<D> {
+    pub fn new(schema: D) -> Self {
+        Self::with_options(schema, DecoderOptions::default())
+    }
+    pub fn with_options(schema: D, options: DecoderOptions) -> Self {
         Self {
+            filter: ResolvedFilter::new(&options.filter, &schema),
             schema,
+            options,
+            selector: None,
+            doc_type: None,
             state: State::Tag,
             buffer: vec![],
             cursor: 0,
@@ -106,6 +282,38 @@ impl<'a, D: SchemaDict<'a>> Decoder<'a, D> {
             queue: vec![],
         }
     }
+    /// decoder for the middle of a stream, e.g. a Cluster found via Cues.
+    /// the first chunk starts at the absolute `offset` inside the open `ancestors` from the top level;
+    /// their `level` and `r#type` are taken from the schema.
+    /// sized ancestors are closed at their end, unknown-size ones as usual.
+    pub fn with_ancestors(
+        schema: D,
+        options: DecoderOptions,
+        offset: usize,
+        mut ancestors: Vec<ebml::ElementPosition>,
+    ) -> Result<Self, DecodeError> {
+        for pos in &mut ancestors {
+            let entry = schema
+                .get(pos.ebml_id)
+                .filter(|o| o.r#type() == 'm')
+                .ok_or(DecodeError::InvalidAncestor(*pos))?;
+            pos.level = entry.level();
+            pos.r#type = 'm';
+            let end = pos.content_start as i64 + pos.content_size;
+            if offset < pos.content_start || pos.content_size >= 0 && end < offset as i64 {
+                return Err(DecodeError::InvalidAncestor(*pos));
+            }
+        }
+        let mut decoder = Self::with_options(schema, options);
+        decoder.restore(DecoderCheckpoint {
+            state: State::Tag,
+            total: offset,
+            stack: ancestors,
+            pending: vec![],
+            doc_type: None,
+        })?;
+        Ok(decoder)
+    }
     #[logfn(ok = "TRACE", err = "ERROR")]
     pub fn decode(&mut self, chunk: Vec<u8>) -> Result<Vec<ebml::ElementDetail>, DecodeError> {
         self.read_chunk(chunk)?;
@@ -113,31 +321,221 @@ impl<'a, D: SchemaDict<'a>> Decoder<'a, D> {
         std::mem::swap(&mut self.queue, &mut result);
         Ok(result)
     }
+    /// call at the end of input. closes the unknown-size masters still open,
+    /// or returns `UnexpectedEnd` with the context of the incomplete element.
+    /// the decoder is reset for the next stream either way.
+    #[logfn(ok = "TRACE", err = "ERROR")]
+    pub fn finish(&mut self) -> Result<Vec<ebml::ElementDetail>, DecodeError> {
+        let incomplete = if self.state != State::Tag || self.cursor < self.buffer.len() {
+            // in the middle of a tag, a size or a content
+            Some(self.context(&DecodeError::UnexpectedEnd))
+        } else if let Some(i) = self.stack.iter().rposition(|o| o.content_size >= 0) {
+            // sized master not read to the end
+            let current = self.stack[i];
+            Some(self.context_at(current.tag_start, Some(current.ebml_id), &self.stack[..i]))
+        } else {
+            None
+        };
+        let result = match incomplete {
+            Some(context) => Err(DecodeError::Context(
+                Box::new(DecodeError::UnexpectedEnd),
+                context,
+            )),
+            None => {
+                // 不定長の親要素はデータの終わりで閉じる
+                while let Some(pos) = self.stack.last() {
+                    let end_pos = ebml::ElementPosition {
+                        content_end: self.total as i64,
+                        ..*pos
+                    };
+                    self.end_master(end_pos);
+                }
+                Ok(std::mem::take(&mut self.queue))
+            }
+        };
+        self.reset();
+        result
+    }
+    /// whether the last element of the path and its parents are emitted
+    fn visible(&self, path: &[ebml::ElementPosition]) -> bool {
+        let filter = &self.filter;
+        let matches = |selectors: &[ResolvedSelector]| {
+            (1..=path.len()).any(|i| selectors.iter().any(|o| Self::matches(o, &path[..i])))
+        };
+        (filter.include.is_empty() || matches(&filter.include)) && !matches(&filter.exclude)
+    }
+    /// whether the last element of the path can be skipped by size
+    fn skippable(&self, path: &[ebml::ElementPosition]) -> bool {
+        let current = path.last().unwrap();
+        if current.content_size < 0 || self.visible(path) {
+            return false;
+        }
+        // DocType is read to select the schema even when not emitted
+        if self.selector.is_some() && path[0].ebml_id == ids::EBML {
+            return false;
+        }
+        let filter = &self.filter;
+        let excluded =
+            (1..=path.len()).any(|i| filter.exclude.iter().any(|o| Self::matches(o, &path[..i])));
+        if excluded || current.r#type != 'm' {
+            return true;
+        }
+        // no included element in the master
+        !filter.include.iter().any(|o| match o {
+            ResolvedSelector::Id(_) => true,
+            ResolvedSelector::Path(ids) => {
+                ids.len() >= path.len()
+                    && path.iter().zip(ids).all(|(o, id)| Some(o.ebml_id) == *id)
+            }
+        })
+    }
+    fn matches(selector: &ResolvedSelector, path: &[ebml::ElementPosition]) -> bool {
+        match selector {
+            ResolvedSelector::Id(ebml_id) => path.last().map(|o| o.ebml_id) == Some(*ebml_id),
+            ResolvedSelector::Path(ids) => {
+                ids.iter().copied().eq(path.iter().map(|o| Some(o.ebml_id)))
+            }
+        }
+    }
+    /// queues the end of the last element of the stack and pops it
+    fn end_master(&mut self, pos: ebml::ElementPosition) {
+        if self.visible(&self.stack) {
+            self.queue.push(
+                (
+                    ebml::MasterEndElement {
+                        ebml_id: pos.ebml_id,
+                    },
+                    pos,
+                )
+                    .into(),
+            );
+        }
+        self.stack.pop();
+    }
+    /// state between `decode` calls
+    pub fn checkpoint(&self) -> DecoderCheckpoint {
+        DecoderCheckpoint {
+            state: self.state,
+            total: self.total,
+            stack: self.stack.clone(),
+            pending: self.buffer[self.cursor..].to_vec(),
+            doc_type: self.doc_type.clone(),
+        }
+    }
+    /// continues from the checkpoint; the following chunks start at `checkpoint.offset()`.
+    /// the decoder should have the same options as the one which made the checkpoint.
+    #[logfn(ok = "TRACE", err = "ERROR")]
+    pub fn restore(&mut self, checkpoint: DecoderCheckpoint) -> Result<(), DecodeError> {
+        // the tag and the size belong to the last element in the stack
+        if checkpoint.state != State::Tag && checkpoint.stack.is_empty() {
+            return Err(DecodeError::InvalidCheckpoint);
+        }
+        self.state = checkpoint.state;
+        self.buffer = checkpoint.pending;
+        self.cursor = 0;
+        self.total = checkpoint.total;
+        self.stack = checkpoint.stack;
+        self.queue.clear();
+        match checkpoint.doc_type {
+            Some(doc_type) => self.select_schema(doc_type)?,
+            None => self.reset_schema(),
+        }
+        Ok(())
+    }
+    /// DocType of the EBML header read last
+    pub fn doc_type(&self) -> Option<&str> {
+        self.doc_type.as_deref()
+    }
+    /// switches to the schema for the DocType when made by `with_registry`
+    fn select_schema(&mut self, doc_type: String) -> Result<(), DecodeError> {
+        if let Some(selector) = &self.selector {
+            self.schema = selector
+                .select(Some(&doc_type))
+                .ok_or_else(|| DecodeError::UnknownDocType(doc_type.clone()))?;
+            self.filter = ResolvedFilter::new(&self.options.filter, &self.schema);
+        }
+        self.doc_type = Some(doc_type);
+        Ok(())
+    }
+    fn reset_schema(&mut self) {
+        if let Some(schema) = self.selector.as_ref().and_then(|o| o.select(None)) {
+            self.schema = schema;
+            self.filter = ResolvedFilter::new(&self.options.filter, &self.schema);
+        }
+        self.doc_type = None;
+    }
+    fn reset(&mut self) {
+        self.reset_schema();
+        self.state = State::Tag;
+        self.buffer.clear();
+        self.cursor = 0;
+        self.total = 0;
+        self.stack.clear();
+        self.queue.clear();
+    }
     #[logfn(ok = "TRACE", err = "ERROR")]
     fn read_chunk(&mut self, mut chunk: Vec<u8>) -> Result<(), DecodeError> {
         // 読みかけの(読めなかった) buffer と 新しい chunk を合わせて読み直す
         self.buffer.append(&mut chunk);
-        while self.cursor < self.buffer.len() {
-            match self.state {
-                State::Tag => {
-                    if !self.read_tag()? {
-                        break;
-                    }
-                }
-                State::Size => {
-                    if !self.read_size()? {
-                        break;
-                    }
-                }
-                State::Content => {
-                    if !self.read_content()? {
-                        break;
-                    }
+        // zero-length content may be at the end of the buffer
+        while self.cursor < self.buffer.len() || self.state == State::Content {
+            let ret = match self.state {
+                State::Tag => self.read_tag(),
+                State::Size => self.read_size(),
+                State::Content => self.read_content(),
+            };
+            match ret {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(err) => {
+                    let context = self.context(&err);
+                    return Err(DecodeError::Context(Box::new(err), context));
                 }
             }
         }
         Ok(())
     }
+    /// the element being read and its ancestors
+    fn context(&self, err: &DecodeError) -> ebml::ErrorContext {
+        let (offset, ebml_id, parents) = match (self.state, err) {
+            (State::Tag, DecodeError::UnknownEbmlId(ebml_id)) => {
+                (self.total, Some(*ebml_id), &self.stack[..])
+            }
+            (State::Tag, _) => (self.total, None, &self.stack[..]),
+            _ => {
+                let (current, parents) = self.stack.split_last().unwrap();
+                (current.tag_start, Some(current.ebml_id), parents)
+            }
+        };
+        self.context_at(offset, ebml_id, parents)
+    }
+    fn context_at(
+        &self,
+        offset: usize,
+        ebml_id: Option<ebml::EbmlId>,
+        parents: &[ebml::ElementPosition],
+    ) -> ebml::ErrorContext {
+        let name = |ebml_id| self.schema.get(ebml_id).map(|o| o.name().to_string());
+        // self.buffer[0] is at `total - cursor`, at or before the current tag
+        let buffer_start = self.total - self.cursor;
+        // empty when the offset is no longer buffered
+        let start =
+            (offset.saturating_sub(16).max(buffer_start) - buffer_start).min(self.buffer.len());
+        let end = (offset + 32)
+            .saturating_sub(buffer_start)
+            .min(self.buffer.len());
+        ebml::ErrorContext {
+            offset,
+            ebml_id,
+            name: ebml_id.and_then(name),
+            path: parents
+                .iter()
+                .map(|o| name(o.ebml_id).unwrap_or_else(|| "?".to_string()))
+                .collect(),
+            excerpt_start: buffer_start + start,
+            excerpt: self.buffer[start..end.max(start)].to_vec(),
+        }
+    }
     /// return false when waiting for more data
     #[logfn(ok = "TRACE", err = "ERROR")]
     fn read_tag(&mut self) -> Result<bool, DecodeError> {
@@ -153,24 +551,52 @@ impl<'a, D: SchemaDict<'a>> Decoder<'a, D> {
             return Ok(false);
         }
         let tag_size = opt_tag.unwrap().length;
-        let ebml_id = ebml::EbmlId(opt_tag.unwrap().value);
+        // ebml id keeps its vint marker bit (e.g. 0x1A45DFA3)
+        let ebml_id = ebml::EbmlId(opt_tag.unwrap().value | (1 << (7 * i64::from(tag_size))));
 
         let tag_start = self.total;
         let size_start = self.total + (tag_size as usize);
         let content_start = 0;
         let content_size = 0;
-        let schema = self
+        // DocType defaults to the one of the registry when the EBML header has none
+        if self.stack.is_empty() && ebml_id != ids::EBML && self.doc_type.is_none() {
+            if let Some(selector) = &self.selector {
+                let doc_type = selector
+                    .registry
+                    .default_doc_type()
+                    .ok_or(DecodeError::MissingDocType)?
+                    .to_string();
+                self.select_schema(doc_type)?;
+            }
+        }
+        let (level, r#type) = self
             .schema
             .get(ebml_id)
-            .ok_or_else(|| DecodeError::UnknownEbmlId(ebml_id))?;
+            .map(|o| (o.level(), o.r#type()))
+            .ok_or(DecodeError::UnknownEbmlId(ebml_id))?;
+        // 不定長の親要素は同じかより上位のレベルの要素が来たら閉じる
+        while let Some(parent_pos) = self.stack.last() {
+            if parent_pos.content_size >= 0 || level < 0 || parent_pos.level < level {
+                break;
+            }
+            // 次の要素の直前で終わる
+            let end_pos = ebml::ElementPosition {
+                content_end: tag_start as i64,
+                ..*parent_pos
+            };
+            self.end_master(end_pos);
+        }
         let pos = ebml::ElementPosition {
-            level: schema.level(),
-            r#type: schema.r#type(),
+            level,
+            r#type,
             ebml_id,
             tag_start,
             size_start,
             content_start,
             content_size,
+            tag_length: tag_size as usize,
+            size_length: 0,
+            content_end: -1,
         };
         self.stack.push(pos);
 
@@ -199,13 +625,26 @@ impl<'a, D: SchemaDict<'a>> Decoder<'a, D> {
 
         // decide current tag data size
         let ebml::ElementPosition {
-            ref mut tag_start,
+            ref size_start,
             ref mut content_start,
             ref mut content_size,
+            ref mut size_length,
+            ref mut content_end,
             ..
         } = self.stack.last_mut().unwrap();
-        *content_start = *tag_start + (size.length as usize);
-        *content_size = size.value;
+        *size_length = size.length as usize;
+        *content_start = *size_start + *size_length;
+        // all value bits set to one means unknown size
+        *content_size = if size.value == (1 << (7 * i64::from(size.length))) - 1 {
+            -1
+        } else {
+            size.value
+        };
+        *content_end = if *content_size < 0 {
+            -1
+        } else {
+            *content_start as i64 + *content_size
+        };
 
         // move cursor and change state
         self.cursor += size.length as usize;
@@ -217,33 +656,35 @@ impl<'a, D: SchemaDict<'a>> Decoder<'a, D> {
     #[logfn(ok = "TRACE", err = "ERROR")]
 
     fn read_content(&mut self) -> Result<bool, DecodeError> {
+        // filtered out
+        if self.skippable(&self.stack) {
+            return Ok(self.skip_content());
+        }
         let current_pos = self.stack.last().unwrap();
         // master element は子要素を持つので生データはない
         if current_pos.r#type == 'm' {
-            let elm = (
-                ebml::MasterStartElement {
-                    ebml_id: current_pos.ebml_id,
-                    unknown_size: current_pos.content_size == -1,
-                },
-                *current_pos,
-            )
-                .into();
-            self.queue.push(elm);
+            let current_pos = *current_pos;
+            // a new EBML header
+            if current_pos.ebml_id == ids::EBML && self.stack.len() == 1 {
+                self.reset_schema();
+            }
+            if self.visible(&self.stack) {
+                let elm = (
+                    ebml::MasterStartElement {
+                        ebml_id: current_pos.ebml_id,
+                        unknown_size: current_pos.content_size == -1,
+                    },
+                    current_pos,
+                )
+                    .into();
+                self.queue.push(elm);
+            }
             self.state = State::Tag;
             // この Mastert Element は空要素か
             if current_pos.content_size == 0 {
-                // 即座に終了タグを追加
-                self.queue.push(
-                    (
-                        ebml::MasterEndElement {
-                            ebml_id: current_pos.ebml_id,
-                        },
-                        *current_pos,
-                    )
-                        .into(),
-                );
-                // スタックからこのタグを捨てる
-                self.stack.pop();
+                // 即座に終了タグを追加し、スタックからこのタグを捨てる
+                self.end_master(current_pos);
+                self.close_parents();
             }
             return Ok(true);
         }
@@ -261,16 +702,34 @@ impl<'a, D: SchemaDict<'a>> Decoder<'a, D> {
         }
         // タグの中身の生データ
         let content = self.buffer[self.cursor..self.cursor + content_size].to_vec();
-        // 読み終わったバッファを捨てて読み込んでいる部分のバッファのみ残す
-        self.buffer = self.buffer.split_off(self.cursor + content_size);
-
         let child_elm = read_child_element(
             current_pos.ebml_id,
             current_pos.r#type,
             std::io::Cursor::new(content),
             content_size,
+            &self.options,
         )?;
-        self.queue.push((child_elm, *current_pos).into());
+        // DocType of the EBML header decides the schema of the body
+        if current_pos.ebml_id == ids::DOC_TYPE
+            && self.stack.len() == 2
+            && self.stack[0].ebml_id == ids::EBML
+        {
+            let doc_type = match &child_elm {
+                ebml::ChildElement::StringElement(o) => {
+                    String::from_utf8_lossy(&o.value).into_owned()
+                }
+                ebml::ChildElement::Utf8Element(o) => o.value.clone(),
+                _ => String::new(),
+            };
+            self.select_schema(doc_type)?;
+        }
+        let current_pos = *self.stack.last().unwrap();
+        // 読み終わったバッファを捨てて読み込んでいる部分のバッファのみ残す
+        self.buffer = self.buffer.split_off(self.cursor + content_size);
+        // the EBML header is read for DocType even when filtered out
+        if self.visible(&self.stack) {
+            self.queue.push((child_elm, current_pos).into());
+        }
 
         // ポインタを進める
         self.total += content_size;
@@ -279,16 +738,18 @@ impl<'a, D: SchemaDict<'a>> Decoder<'a, D> {
         self.cursor = 0;
         // remove the object from the stack
         self.stack.pop();
-
-        while !self.stack.is_empty() {
-            let parent_pos = self.stack.last().unwrap();
+        self.close_parents();
+        Ok(true)
+    }
+    /// emit end tags of the sized parents which have been read to the end
+    fn close_parents(&mut self) {
+        while let Some(parent_pos) = self.stack.last() {
             // 親が不定長サイズなので閉じタグは期待できない
             if parent_pos.content_size < 0 {
-                self.stack.pop(); // 親タグを捨てる
-                return Ok(true);
+                break;
             }
             // 閉じタグの来るべき場所まで来たかどうか
-            if self.total < parent_pos.content_start + content_size {
+            if self.total < parent_pos.content_start + parent_pos.content_size as usize {
                 break;
             }
             // 閉じタグを挿入すべきタイミングが来た
@@ -296,42 +757,51 @@ impl<'a, D: SchemaDict<'a>> Decoder<'a, D> {
                 // throw new Error("parent element is not master element");
                 unreachable!();
             }
-            self.queue.push(
-                (
-                    ebml::MasterEndElement {
-                        ebml_id: parent_pos.ebml_id,
-                    },
-                    *parent_pos,
-                )
-                    .into(),
-            );
             // スタックからこのタグを捨てる
-            self.stack.pop();
+            self.end_master(*parent_pos);
         }
-        Ok(true)
+    }
+    /// drops the content of the current element as it arrives.
+    /// return false when waiting for more data
+    fn skip_content(&mut self) -> bool {
+        let current_pos = self.stack.last().unwrap();
+        let end = current_pos.content_start + current_pos.content_size as usize;
+        let len = (end - self.total).min(self.buffer.len() - self.cursor);
+        self.buffer = self.buffer.split_off(self.cursor + len);
+        self.cursor = 0;
+        self.total += len;
+        if self.total < end {
+            return false;
+        }
+        self.state = State::Tag;
+        self.stack.pop();
+        self.close_parents();
+        true
     }
 }
 
 #[derive(Debug, Error)]
 pub enum ReadContentError {
-    #[error(display = "Date")]
+    #[error(display = "Date: {}", _0)]
     Date(#[error(cause)] std::io::Error),
-    #[error(display = "Utf8")]
+    #[error(display = "Utf8: {}", _0)]
     Utf8(#[error(cause)] std::io::Error),
-    #[error(display = "UnsignedInteger")]
+    #[error(display = "UnsignedInteger: {}", _0)]
     UnsignedInteger(#[error(cause)] std::io::Error),
-    #[error(display = "Integer")]
+    #[error(display = "Integer: {}", _0)]
     Integer(#[error(cause)] std::io::Error),
-    #[error(display = "Float")]
+    #[error(display = "Float: {}", _0)]
     Float(#[error(cause)] std::io::Error),
-    #[error(display = "Binary")]
+    #[error(display = "Binary: {}", _0)]
     Binary(#[error(cause)] std::io::Error),
-    #[error(display = "String")]
+    #[error(display = "String: {}", _0)]
     String(#[error(cause)] std::io::Error),
-    #[error(display = "Master")]
-    Master(#[error(cause)] std::io::Error),
-    #[error(display = "Unknown")]
-    Unknown(#[error(cause)] std::io::Error),
+    #[error(display = "cannot read master element as child element")]
+    Master,
+    #[error(display = "unknown type: {}", _0)]
+    Unknown(char),
+    #[error(display = "invalid content size {} for type {}", _1, _0)]
+    InvalidSize(char, usize),
 }
 
 #[logfn_inputs(TRACE)]
@@ -341,48 +811,86 @@ fn read_child_element<C: std::io::Read + std::fmt::Debug>(
     r#type: char,
     mut content: C,
     content_size: usize,
+    options: &DecoderOptions,
 ) -> Result<ebml::ChildElement, ReadContentError> {
     use byteorder::{BigEndian, ReadBytesExt as _};
     use ReadContentError::{String as StringE, *};
     match r#type {
         // Unsigned Integer - Big-endian, any size from 1 to 8 octets
+        // 0 octets means 0; the schema default is not applied
         'u' => {
-            let value = content
-                .read_uint::<BigEndian>(content_size)
-                .map_err(UnsignedInteger)?;
+            let value = match content_size {
+                0 => 0,
+                1..=8 => content
+                    .read_uint::<BigEndian>(content_size)
+                    .map_err(UnsignedInteger)?,
+                _ => Err(InvalidSize(r#type, content_size))?,
+            };
             Ok(ebml::UnsignedIntegerElement { ebml_id, value }.into())
         }
-        // Signed Integer - Big-endian, any size from 1 to 8 octets
+        // Signed Integer - Big-endian, any size from 1 to 8 octets, two's complement
         'i' => {
-            let value = content
-                .read_int::<BigEndian>(content_size)
-                .map_err(Integer)?;
+            let value = match content_size {
+                0 => 0,
+                1..=8 => content
+                    .read_int::<BigEndian>(content_size)
+                    .map_err(Integer)?,
+                _ => Err(InvalidSize(r#type, content_size))?,
+            };
             Ok(ebml::IntegerElement { ebml_id, value }.into())
         }
         // Float - Big-endian, defined for 4 and 8 octets (32, 64 bits)
+        // and 10 octets (80 bits) in the old Matroska spec
         'f' => {
-            let value = if content_size == 4 {
-                f64::from(content.read_f32::<BigEndian>().map_err(Float)?)
+            let (value, width) = if content_size == 0 {
+                (0.0, ebml::FloatWidth::Auto)
+            } else if content_size == 4 {
+                let value = content.read_f32::<BigEndian>().map_err(Float)?;
+                (f64::from(value), ebml::FloatWidth::F32)
             } else if content_size == 8 {
-                content.read_f64::<BigEndian>().map_err(Float)?
+                let value = content.read_f64::<BigEndian>().map_err(Float)?;
+                (value, ebml::FloatWidth::F64)
+            } else if content_size == 10 {
+                let mut buf = [0; 10];
+                content.read_exact(&mut buf).map_err(Float)?;
+                (f80_to_f64(buf), ebml::FloatWidth::F80)
             } else {
-                Err(Float(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    format!("invalid float content_size: {}", content_size),
-                )))?
+                Err(InvalidSize(r#type, content_size))?
             };
-            Ok(ebml::FloatElement { ebml_id, value }.into())
+            Ok(ebml::FloatElement {
+                ebml_id,
+                value,
+                width,
+            }
+            .into())
         }
         //  Printable ASCII (0x20 to 0x7E), zero-padded when needed
         's' => {
             let mut value = vec![0; content_size];
             content.read_exact(&mut value).map_err(StringE)?;
+            let padding = strip_padding(&mut value);
+            if options.strict_strings {
+                if let Some(i) = value.iter().position(|o| *o < 0x20 || 0x7E < *o) {
+                    let message = format!("not printable ASCII 0x{:02X} at {}", value[i], i);
+                    Err(StringE(invalid_data(message)))?;
+                }
+                if padding.iter().any(|o| *o != 0) {
+                    Err(StringE(invalid_data("data after NUL".to_string())))?;
+                }
+            }
             Ok(ebml::StringElement { ebml_id, value }.into())
         }
         //  Unicode string, zero padded when needed (RFC 2279)
         '8' => {
-            let mut value = std::string::String::new();
-            content.read_to_string(&mut value).map_err(Utf8)?;
+            let mut buf = vec![0; content_size];
+            content.read_exact(&mut buf).map_err(Utf8)?;
+            strip_padding(&mut buf);
+            let value = if options.lossy_utf8 {
+                std::string::String::from_utf8_lossy(&buf).into_owned()
+            } else {
+                std::string::String::from_utf8(buf)
+                    .map_err(|o| Utf8(invalid_data(o.to_string())))?
+            };
             Ok(ebml::Utf8Element { ebml_id, value }.into())
         }
         // Binary - not interpreted by the parser
@@ -391,29 +899,56 @@ fn read_child_element<C: std::io::Read + std::fmt::Debug>(
             content.read_exact(&mut value).map_err(Binary)?;
             Ok(ebml::BinaryElement { ebml_id, value }.into())
         }
-        // nano second; Date.UTC(2001,1,1,0,0,0,0) === 980985600000
-        // new Date("2001-01-01T00:00:00.000Z").getTime() = 978307200000
         // Date - signed 8 octets integer in nanoseconds with 0 indicating
-        // the precise beginning of the millennium (at 2001-01-01T00:00:00,000000000 UTC)
+        // the precise beginning of the millennium (at 2001-01-01T00:00:00,000000000 UTC).
+        // 0 octets means the epoch itself
         'd' => {
-            let nanos = content.read_i64::<BigEndian>().map_err(Date)?;
-            let unix_time_nanos: i64 = nanos - 978_307_200 * 1000 * 1000 * 1000;
-            let unix_time_secs: i64 = unix_time_nanos / 1000 / 1000 / 1000 - 1;
-            let nsecs: u32 =
-                u32::try_from((unix_time_nanos & (1000 * 1000 * 1000)) + (1000 * 1000 * 1000))
-                    .unwrap();
-            let datetime = NaiveDateTime::from_timestamp(unix_time_secs, nsecs);
-            let value = DateTime::from_utc(datetime, Utc);
-            Ok(ebml::DateElement { ebml_id, value }.into())
+            let nanos = match content_size {
+                0 => 0,
+                8 => content.read_i64::<BigEndian>().map_err(Date)?,
+                _ => Err(InvalidSize(r#type, content_size))?,
+            };
+            Ok(ebml::DateElement::from_nanos(ebml_id, nanos).into())
         }
         // Master-Element - contains other EBML sub-elements of the next lower level
-        'm' => Err(Master(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "cannot read master element as child element".to_string(),
-        )))?,
-        _ => Err(Unknown(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            format!("unknown type: {}", r#type),
-        )))?,
+        'm' => Err(Master)?,
+        _ => Err(Unknown(r#type))?,
+    }
+}
+
+/// truncates the string at the first NUL and returns the padding removed
+fn strip_padding(buf: &mut Vec<u8>) -> Vec<u8> {
+    match buf.iter().position(|o| *o == 0) {
+        Some(i) => buf.split_off(i),
+        None => vec![],
+    }
+}
+
+fn invalid_data(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// 80-bit extended precision float rounded to f64
+fn f80_to_f64(buf: [u8; 10]) -> f64 {
+    let sign_exponent = u16::from_be_bytes([buf[0], buf[1]]);
+    let mut mantissa = [0; 8];
+    mantissa.copy_from_slice(&buf[2..]);
+    let mantissa = u64::from_be_bytes(mantissa);
+    let sign = if sign_exponent & 0x8000 == 0 {
+        1.0
+    } else {
+        -1.0
+    };
+    let exponent = i32::from(sign_exponent & 0x7FFF);
+    if exponent == 0x7FFF {
+        // the integer bit is ignored
+        return if mantissa << 1 == 0 {
+            sign * f64::INFINITY
+        } else {
+            f64::NAN
+        };
     }
+    // value = mantissa * 2^(exponent - 16383 - 63); split the power not to overflow on the way
+    let e = exponent.max(1) - 16383 - 63;
+    sign * (mantissa as f64) * 2f64.powi(e / 2) * 2f64.powi(e - e / 2)
 }
@@ -99,6 +99,33 @@ pub struct IntegerElement {
 pub struct FloatElement {
     pub ebml_id: EbmlId,
     pub value: f64,
+    // width of the content; the decoder sets the one read
+    #[serde(default)]
+    pub width: FloatWidth,
+}
+
+#[derive(
+    Arbitrary,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Default,
+    Serialize,
+    Deserialize,
+)]
+pub enum FloatWidth {
+    // float32 when the value is exactly representable, float64 otherwise
+    #[default]
+    Auto,
+    F32,
+    F64,
+    // 80-bit extended precision of the old Matroska spec
+    F80,
 }
 
 #[derive(Arbitrary, Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
@@ -138,10 +165,35 @@ pub struct DateElement {
     // signed 8 octets integer in nanoseconds with 0 indicating the precise
     // beginning of the millennium (at 2001-01-01T00:00:00,000000000 UTC)
     #[proptest(strategy = "arb_datetime()")]
-    #[serde(with = "chrono::serde::ts_milliseconds")]
+    #[serde(with = "chrono::serde::ts_nanoseconds")]
     pub value: DateTime<Utc>,
 }
 
+// 2001-01-01T00:00:00 UTC in unix time
+const DATE_EPOCH_SECS: i64 = 978_307_200;
+const NANOS_PER_SEC: i64 = 1_000_000_000;
+
+impl DateElement {
+    /// the date of nanoseconds since 2001-01-01T00:00:00 UTC.
+    /// every i64 value is representable.
+    pub fn from_nanos(ebml_id: EbmlId, nanos: i64) -> Self {
+        let secs = nanos.div_euclid(NANOS_PER_SEC) + DATE_EPOCH_SECS;
+        let subsec = nanos.rem_euclid(NANOS_PER_SEC) as u32;
+        let value = DateTime::from_utc(
+            chrono::NaiveDateTime::from_timestamp_opt(secs, subsec).unwrap(),
+            Utc,
+        );
+        DateElement { ebml_id, value }
+    }
+    /// nanoseconds since 2001-01-01T00:00:00 UTC; None out of about 1709-2293
+    pub fn nanos(&self) -> Option<i64> {
+        let secs = i128::from(self.value.timestamp() - DATE_EPOCH_SECS);
+        let nanos =
+            secs * i128::from(NANOS_PER_SEC) + i128::from(self.value.timestamp_subsec_nanos());
+        std::convert::TryFrom::try_from(nanos).ok()
+    }
+}
+
 #[derive(
     Derivative,
     Arbitrary,
@@ -173,10 +225,70 @@ pub struct ElementPosition {
     pub level: i64,
     // m u i f s 8 b d
     pub r#type: char,
+    // absolute offsets from the beginning of the stream
     pub tag_start: usize,
     pub size_start: usize,
     pub content_start: usize,
+    // -1 means unknown size
     pub content_size: i64,
+    // bytes of the EBML ID and of the size vint
+    pub tag_length: usize,
+    pub size_length: usize,
+    // absolute offset just after the element.
+    // -1 until an unknown-size master is closed; set on its MasterEndElement.
+    pub content_end: i64,
+}
+
+/// id and size of an element read directly from a stream
+#[derive(Debug, Clone, PartialEq, PartialOrd, Copy, Eq, Ord, Hash, Serialize, Deserialize)]
+pub struct ElementHeader {
+    pub ebml_id: EbmlId,
+    // tag length + size length
+    pub header_size: usize,
+    // -1 means unknown size
+    pub content_size: i64,
+}
+
+/// where a decode or encode error occurred
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorContext {
+    // absolute offset of the element.
+    // for encode errors, offset in the output if the open masters ended there.
+    pub offset: usize,
+    pub ebml_id: Option<EbmlId>,
+    // schema name; None for unknown ids
+    pub name: Option<String>,
+    // names of the ancestors from the top level
+    pub path: Vec<String>,
+    // bytes around the offset and the absolute offset of the first one
+    pub excerpt_start: usize,
+    pub excerpt: Vec<u8>,
+}
+
+impl std::fmt::Display for ErrorContext {
+    /// `at offset 100 (0x64) in Segment > Info > Duration [4489]` and hex lines of the excerpt,
+    /// where the byte at the offset is bracketed
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "at offset {} (0x{:X}) in ", self.offset, self.offset)?;
+        let mut path = self.path.clone();
+        path.push(self.name.clone().unwrap_or_else(|| "?".to_string()));
+        write!(f, "{}", path.join(" > "))?;
+        if let Some(ebml_id) = self.ebml_id {
+            write!(f, " [{:X}]", ebml_id.0)?;
+        }
+        for (i, line) in self.excerpt.chunks(16).enumerate() {
+            let line_start = self.excerpt_start + i * 16;
+            write!(f, "\n  {:08X}:", line_start)?;
+            for (j, byte) in line.iter().enumerate() {
+                if line_start + j == self.offset {
+                    write!(f, " [{:02X}]", byte)?;
+                } else {
+                    write!(f, " {:02X}", byte)?;
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Arbitrary, Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
@@ -189,6 +301,38 @@ pub struct SimpleBlock {
     pub track_number: i64,
 }
 
+impl Element {
+    pub fn ebml_id(&self) -> EbmlId {
+        match self {
+            Element::MasterElement(o) => o.ebml_id(),
+            Element::ChildElement(o) => o.ebml_id(),
+        }
+    }
+}
+
+impl MasterElement {
+    pub fn ebml_id(&self) -> EbmlId {
+        match self {
+            MasterElement::MasterStartElement(o) => o.ebml_id,
+            MasterElement::MasterEndElement(o) => o.ebml_id,
+        }
+    }
+}
+
+impl ChildElement {
+    pub fn ebml_id(&self) -> EbmlId {
+        match self {
+            ChildElement::UnsignedIntegerElement(o) => o.ebml_id,
+            ChildElement::IntegerElement(o) => o.ebml_id,
+            ChildElement::FloatElement(o) => o.ebml_id,
+            ChildElement::StringElement(o) => o.ebml_id,
+            ChildElement::Utf8Element(o) => o.ebml_id,
+            ChildElement::BinaryElement(o) => o.ebml_id,
+            ChildElement::DateElement(o) => o.ebml_id,
+        }
+    }
+}
+
 impl From<ElementDetail> for Element {
     fn from(o: ElementDetail) -> Self {
         match o {
@@ -278,12 +422,34 @@ macro_rules! child_defs {
 
 child_defs!(UnsignedIntegerElement, u64);
 child_defs!(IntegerElement, i64);
-child_defs!(FloatElement, f64);
 child_defs!(StringElement, Vec<u8>);
 child_defs!(Utf8Element, String);
 child_defs!(BinaryElement, Vec<u8>);
 child_defs!(DateElement, DateTime<Utc>);
 
+impl From<FloatElement> for Element {
+    fn from(o: FloatElement) -> Element {
+        Element::ChildElement(o.into())
+    }
+}
+
+impl From<FloatElement> for ChildElement {
+    fn from(o: FloatElement) -> ChildElement {
+        ChildElement::FloatElement(o)
+    }
+}
+
+impl From<(EbmlId, f64)> for FloatElement {
+    fn from((ebml_id, value): (EbmlId, f64)) -> FloatElement {
+        FloatElement {
+            ebml_id,
+            value,
+            width: FloatWidth::Auto,
+        }
+    }
+}
+
+/// any date representable in EBML
 fn arb_datetime() -> impl Strategy<Value = ::chrono::DateTime<::chrono::Utc>> {
-    Just(::chrono::Utc::now())
+    any::<i64>().prop_map(|nanos| DateElement::from_nanos(0.into(), nanos).value)
 }
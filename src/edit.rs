@@ -0,0 +1,389 @@
+//! In-place modification of child elements such as `Title`, `Language` or `TagString`.
+//!
+//! The new element is written over the old one and the `Void` elements following it.
+//! When it does not fit there, the file has to be rewritten as a whole.
+//! Space reserved by `Encoder::reserve` is filled later by `fill_void`.
+//! `CRC-32` of the parents of edited elements is recomputed and patched as well.
+use crate::decoder::{DecodeError, ReadEbmlExt};
+use crate::ebml;
+use crate::encoder::{encode_padded, EncodeError, Encoder};
+use crate::ids;
+use crate::json::{from_json, JsonElement, JsonError};
+use crate::schema::SchemaDict;
+use err_derive::Error;
+use log_derive::logfn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+// bytes read at once to compute CRC-32
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Error)]
+pub enum EditError {
+    #[error(display = "Io")]
+    Io(#[error(cause)] std::io::Error),
+    #[error(display = "{}", _0)]
+    Decode(#[error(cause)] DecodeError),
+    #[error(display = "{}", _0)]
+    Encode(#[error(cause)] EncodeError),
+    #[error(display = "Json")]
+    Json(#[error(cause)] JsonError),
+    #[error(display = "invalid path: {}", _0)]
+    InvalidPath(String),
+    #[error(display = "element not found: {}", _0)]
+    NotFound(String),
+    #[error(display = "no Void element at {}", _0)]
+    NotVoid(u64),
+    #[error(
+        display = "{} needs {} bytes but {} bytes are available; full rewrite is needed",
+        path,
+        needed,
+        available
+    )]
+    RewriteRequired {
+        path: String,
+        needed: usize,
+        available: usize,
+    },
+}
+
+impl From<std::io::Error> for EditError {
+    fn from(o: std::io::Error) -> Self {
+        EditError::Io(o)
+    }
+}
+
+impl From<DecodeError> for EditError {
+    fn from(o: DecodeError) -> Self {
+        EditError::Decode(o)
+    }
+}
+
+impl From<EncodeError> for EditError {
+    fn from(o: EncodeError) -> Self {
+        EditError::Encode(o)
+    }
+}
+
+impl From<JsonError> for EditError {
+    fn from(o: JsonError) -> Self {
+        EditError::Json(o)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Edit {
+    // element names from the top level separated by `/`, e.g. `Segment/Info/Title`.
+    // `Name[n]` selects the n-th (from 0) element of the name in the parent;
+    // otherwise all the elements of the name are edited.
+    pub path: String,
+    // new value in the form of `json` module
+    pub value: serde_json::Value,
+}
+
+/// bytes to write at the absolute offset
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Patch {
+    pub offset: u64,
+    pub data: Vec<u8>,
+}
+
+impl std::fmt::Debug for Patch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Patch {{ offset: {:?}, data: Vec<u8; {:?}> }}",
+            self.offset,
+            self.data.len()
+        )
+    }
+}
+
+// element and the Void elements just after it
+struct Slot {
+    offset: u64,
+    element_len: u64,
+    void_len: u64,
+    // CRC-32 of the ancestors
+    crcs: Vec<Crc>,
+}
+
+// CRC-32 element of a master and the data it covers
+#[derive(Clone, PartialEq)]
+struct Crc {
+    // offset of the CRC-32 value
+    offset: u64,
+    data_start: u64,
+    data_end: u64,
+}
+
+/// computes the patches without writing anything
+#[logfn(ok = "TRACE", err = "ERROR")]
+pub fn plan<R: Read + Seek, D: SchemaDict + ?Sized>(
+    reader: &mut R,
+    schema: &D,
+    edits: &[Edit],
+) -> Result<Vec<Patch>, EditError> {
+    let mut patches = vec![];
+    let mut crcs: Vec<Crc> = vec![];
+    for edit in edits {
+        let components = parse_path(&edit.path)?;
+        let slots = find(reader, schema, &components, 0, None, &[])?;
+        if slots.is_empty() {
+            return Err(EditError::NotFound(edit.path.clone()));
+        }
+        let name = components.last().unwrap().0.clone();
+        let elms = from_json(
+            schema,
+            vec![JsonElement {
+                name,
+                value: Some(edit.value.clone()),
+                unknown_size: false,
+                children: None,
+                width: None,
+            }],
+        )?;
+        let data = Encoder::new(schema).encode(elms)?;
+        for slot in slots {
+            let available = (slot.element_len + slot.void_len) as usize;
+            let data =
+                encode_padded(data.clone(), available).map_err(|_| EditError::RewriteRequired {
+                    path: edit.path.clone(),
+                    needed: data.len(),
+                    available,
+                })?;
+            patches.push(Patch {
+                offset: slot.offset,
+                data,
+            });
+            for crc in slot.crcs {
+                if !crcs.contains(&crc) {
+                    crcs.push(crc);
+                }
+            }
+        }
+    }
+    // inner masters first, since the CRC-32 of the outer one covers them
+    crcs.sort_by_key(|o| o.data_end - o.data_start);
+    for crc in crcs {
+        let value = checksum(reader, &patches, crc.data_start, crc.data_end)?;
+        patches.push(Patch {
+            offset: crc.offset,
+            data: value.to_le_bytes().to_vec(),
+        });
+    }
+    Ok(patches)
+}
+
+#[logfn(ok = "TRACE", err = "ERROR")]
+pub fn apply<W: Write + Seek>(writer: &mut W, patches: &[Patch]) -> Result<(), EditError> {
+    for patch in patches {
+        writer.seek(SeekFrom::Start(patch.offset))?;
+        writer.write_all(&patch.data)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// applies all the edits, or nothing when any of them does not fit
+#[logfn(ok = "TRACE", err = "ERROR")]
+pub fn edit_in_place<F: Read + Write + Seek, D: SchemaDict + ?Sized>(
+    file: &mut F,
+    schema: &D,
+    edits: &[Edit],
+) -> Result<Vec<Patch>, EditError> {
+    let patches = plan(file, schema, edits)?;
+    apply(file, &patches)?;
+    Ok(patches)
+}
+
+/// replaces the Void elements from `offset` with the elements and a smaller Void,
+/// so that the offsets of all the following elements stay the same
+#[logfn(ok = "TRACE", err = "ERROR")]
+pub fn fill_void<R: Read + Seek, D: SchemaDict + ?Sized, E: Into<ebml::Element>>(
+    reader: &mut R,
+    schema: &D,
+    offset: u64,
+    elms: Vec<E>,
+) -> Result<Patch, EditError> {
+    let available = following_voids(reader, offset, None)? as usize;
+    if available == 0 {
+        return Err(EditError::NotVoid(offset));
+    }
+    let data = Encoder::new(schema).encode(elms)?;
+    let needed = data.len();
+    let data = encode_padded(data, available).map_err(|_| EditError::RewriteRequired {
+        path: format!("Void at {}", offset),
+        needed,
+        available,
+    })?;
+    Ok(Patch { offset, data })
+}
+
+/// `Segment/Tracks/TrackEntry[1]/Language` -> [(name, index)]
+fn parse_path(path: &str) -> Result<Vec<(String, Option<usize>)>, EditError> {
+    let invalid = || EditError::InvalidPath(path.to_string());
+    let mut components = vec![];
+    for component in path.split('/') {
+        if component.is_empty() {
+            return Err(invalid());
+        }
+        match component.find('[') {
+            Some(i) if component.ends_with(']') => {
+                let index = component[i + 1..component.len() - 1]
+                    .parse()
+                    .map_err(|_| invalid())?;
+                components.push((component[..i].to_string(), Some(index)));
+            }
+            Some(_) => return Err(invalid()),
+            None => components.push((component.to_string(), None)),
+        }
+    }
+    Ok(components)
+}
+
+/// slots of the elements matching the path between `start` and `end`
+fn find<R: Read + Seek, D: SchemaDict + ?Sized>(
+    reader: &mut R,
+    schema: &D,
+    components: &[(String, Option<usize>)],
+    start: u64,
+    end: Option<u64>,
+    crcs: &[Crc],
+) -> Result<Vec<Slot>, EditError> {
+    let (name, index) = &components[0];
+    let mut slots = vec![];
+    let mut counts = HashMap::new();
+    let mut position = start;
+    while end.map(|end| position < end).unwrap_or(true) {
+        reader.seek(SeekFrom::Start(position))?;
+        let header = match reader.read_element_header() {
+            Ok(header) => header,
+            Err(DecodeError::Io(_)) => break,
+            Err(err) => return Err(err.into()),
+        };
+        let content_start = position + header.header_size as u64;
+        let count = counts.entry(header.ebml_id).or_insert(0);
+        let matched = schema
+            .get(header.ebml_id)
+            .map(|o| o.name() == name)
+            .unwrap_or(false)
+            && index.map(|o| o == *count).unwrap_or(true);
+        *count += 1;
+        if header.content_size < 0 {
+            // unknown-size element cannot be skipped
+            if matched && components.len() > 1 {
+                slots.append(&mut find(
+                    reader,
+                    schema,
+                    &components[1..],
+                    content_start,
+                    end,
+                    crcs,
+                )?);
+            }
+            break;
+        }
+        let next = content_start + header.content_size as u64;
+        if matched && components.len() > 1 {
+            let mut crcs = crcs.to_vec();
+            crcs.extend(find_crc(reader, content_start, next)?);
+            slots.append(&mut find(
+                reader,
+                schema,
+                &components[1..],
+                content_start,
+                Some(next),
+                &crcs,
+            )?);
+        } else if matched {
+            let void_len = following_voids(reader, next, end)?;
+            slots.push(Slot {
+                offset: position,
+                element_len: next - position,
+                void_len,
+                crcs: crcs.to_vec(),
+            });
+        }
+        position = next;
+    }
+    Ok(slots)
+}
+
+/// total length of the contiguous Void elements from `position`
+fn following_voids<R: Read + Seek>(
+    reader: &mut R,
+    position: u64,
+    end: Option<u64>,
+) -> Result<u64, EditError> {
+    let mut len = 0;
+    while end.map(|end| position + len < end).unwrap_or(true) {
+        reader.seek(SeekFrom::Start(position + len))?;
+        let header = match reader.read_element_header() {
+            Ok(header) => header,
+            Err(DecodeError::Io(_)) => break,
+            Err(err) => return Err(err.into()),
+        };
+        if header.ebml_id != ids::VOID || header.content_size < 0 {
+            break;
+        }
+        len += header.header_size as u64 + header.content_size as u64;
+    }
+    Ok(len)
+}
+
+/// CRC-32 element at the beginning of the sized master content
+fn find_crc<R: Read + Seek>(
+    reader: &mut R,
+    content_start: u64,
+    content_end: u64,
+) -> Result<Option<Crc>, EditError> {
+    if content_start >= content_end {
+        return Ok(None);
+    }
+    reader.seek(SeekFrom::Start(content_start))?;
+    let header = reader.read_element_header()?;
+    if header.ebml_id != ids::CRC32 || header.content_size != 4 {
+        return Ok(None);
+    }
+    let offset = content_start + header.header_size as u64;
+    Ok(Some(Crc {
+        offset,
+        data_start: offset + 4,
+        data_end: content_end,
+    }))
+}
+
+/// CRC-32 of the range as it will be after the patches
+fn checksum<R: Read + Seek>(
+    reader: &mut R,
+    patches: &[Patch],
+    start: u64,
+    end: u64,
+) -> Result<u32, EditError> {
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buf = vec![0; CHUNK_SIZE];
+    let mut position = start;
+    reader.seek(SeekFrom::Start(start))?;
+    while position < end {
+        let len = usize::try_from(end - position).map_or(CHUNK_SIZE, |o| o.min(CHUNK_SIZE));
+        let chunk = &mut buf[..len];
+        reader.read_exact(chunk)?;
+        let chunk_end = position + len as u64;
+        for patch in patches {
+            // overlap of the patch and the chunk
+            let from = patch.offset.max(position);
+            let to = (patch.offset + patch.data.len() as u64).min(chunk_end);
+            if from < to {
+                chunk[(from - position) as usize..(to - position) as usize].copy_from_slice(
+                    &patch.data[(from - patch.offset) as usize..(to - patch.offset) as usize],
+                );
+            }
+        }
+        hasher.update(chunk);
+        position = chunk_end;
+    }
+    Ok(hasher.finalize())
+}
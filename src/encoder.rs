@@ -1,7 +1,10 @@
 #![allow(unused_imports, dead_code)]
 use crate::ebml;
+use crate::ids;
 use crate::schema::{Schema, SchemaDict};
-use crate::vint::{write_vint, UnrepresentableValueError, WriteVintExt};
+use crate::vint::{
+    read_vint, write_vint, write_vint_with_length, UnrepresentableValueError, WriteVintExt,
+};
 use byteorder::{BigEndian, WriteBytesExt};
 use err_derive::Error;
 use log::debug;
@@ -16,8 +19,27 @@ pub enum EncodeError {
     UnknownEbmlId(ebml::EbmlId),
     #[error(display = "EBML structure is broken")]
     Bloken,
-    #[error(display = "EncodeTagError")]
+    #[error(display = "{}", _0)]
     EncodeTag(#[error(cause)] EncodeTagError),
+    #[error(display = "{} {}", _0, _1)]
+    Context(#[error(cause)] Box<EncodeError>, ebml::ErrorContext),
+}
+
+impl EncodeError {
+    /// where the error occurred; only errors from `Encoder` have it
+    pub fn context(&self) -> Option<&ebml::ErrorContext> {
+        match self {
+            EncodeError::Context(_, context) => Some(context),
+            _ => None,
+        }
+    }
+    /// the error without context
+    pub fn kind(&self) -> &EncodeError {
+        match self {
+            EncodeError::Context(err, _) => err.kind(),
+            err => err,
+        }
+    }
 }
 
 impl From<std::io::Error> for EncodeError {
@@ -32,8 +54,8 @@ impl From<EncodeTagError> for EncodeError {
     }
 }
 
-pub struct Encoder<'a, D: SchemaDict<'a>> {
-    schema: &'a D,
+pub struct Encoder<D: SchemaDict> {
+    schema: D,
     stack: Vec<(ebml::MasterStartElement, Vec<u8>)>,
     // c
     // c
@@ -48,25 +70,71 @@ pub struct Encoder<'a, D: SchemaDict<'a>> {
     // + c
     // c
     queue: Vec<u8>,
+    // bytes returned by `encode` so far
+    total: usize,
 }
 
-impl<'a, D: SchemaDict<'a>> Encoder<'a, D> {
-    pub fn new(schema: &'a D) -> Self {
+impl<D: SchemaDict> Encoder<D> {
+    pub fn new(schema: D) -> Self {
         Self {
             schema,
             stack: vec![],
             queue: vec![],
+            total: 0,
         }
     }
     #[logfn(ok = "TRACE", err = "ERROR")]
     pub fn encode<E: Into<ebml::Element>>(&mut self, elms: Vec<E>) -> Result<Vec<u8>, EncodeError> {
         for elm in elms {
-            self.encode_chunk(elm.into())?;
+            let elm = elm.into();
+            let ebml_id = elm.ebml_id();
+            if let Err(err) = self.encode_chunk(elm) {
+                let context = self.context(ebml_id);
+                return Err(EncodeError::Context(Box::new(err), context));
+            }
         }
         let mut result = vec![];
         std::mem::swap(&mut self.queue, &mut result);
+        self.total += result.len();
         Ok(result)
     }
+    /// the element being encoded and the open masters
+    fn context(&self, ebml_id: ebml::EbmlId) -> ebml::ErrorContext {
+        let name = |ebml_id| self.schema.get(ebml_id).map(|o| o.name().to_string());
+        // sized masters get their headers when closed
+        let offset = self.total
+            + self.queue.len()
+            + self
+                .stack
+                .iter()
+                .map(|(o, buf)| {
+                    if o.unknown_size {
+                        return buf.len();
+                    }
+                    let tag: Vec<u8> = o.ebml_id.into();
+                    let size = write_vint(buf.len() as i64).map(|o| o.len()).unwrap_or(8);
+                    tag.len() + size + buf.len()
+                })
+                .sum::<usize>();
+        let sink = if self.stack.iter().all(|(o, _)| o.unknown_size) {
+            &self.queue
+        } else {
+            &self.stack.last().unwrap().1
+        };
+        let excerpt = sink[sink.len().saturating_sub(16)..].to_vec();
+        ebml::ErrorContext {
+            offset,
+            ebml_id: Some(ebml_id),
+            name: name(ebml_id),
+            path: self
+                .stack
+                .iter()
+                .map(|(o, _)| name(o.ebml_id).unwrap_or_else(|| "?".to_string()))
+                .collect(),
+            excerpt_start: offset - excerpt.len(),
+            excerpt,
+        }
+    }
     #[logfn(ok = "TRACE", err = "ERROR")]
     fn encode_chunk(&mut self, elm: ebml::Element) -> Result<(), EncodeError> {
         match elm {
@@ -86,14 +154,13 @@ impl<'a, D: SchemaDict<'a>> Encoder<'a, D> {
     }
     #[logfn(ok = "TRACE", err = "ERROR")]
     fn write_tag(&mut self, elm: ebml::ChildElement) -> Result<(), EncodeError> {
+        let bytesize = self.schema.get(elm.ebml_id()).and_then(|o| o.bytesize());
+        let elm = match bytesize {
+            Some(bytesize) => pad_string(elm, bytesize)?,
+            None => elm,
+        };
         let mut data = encode_child_tag(elm)?;
-        // 親要素が閉じタグありなら閉じタグが来るまで待つ(master stack queueに入る)
-        if !self.stack.is_empty() {
-            let last = self.stack.last_mut().unwrap();
-            last.1.append(&mut data);
-            return Ok(());
-        }
-        self.queue.append(&mut data);
+        self.sink().append(&mut data);
         Ok(())
     }
     #[logfn(ok = "TRACE", err = "ERROR")]
@@ -101,13 +168,12 @@ impl<'a, D: SchemaDict<'a>> Encoder<'a, D> {
         let schema = self
             .schema
             .get(o.ebml_id)
-            .ok_or_else(|| EncodeError::UnknownEbmlId(o.ebml_id))?;
+            .ok_or(EncodeError::UnknownEbmlId(o.ebml_id))?;
         let _level = schema.level();
         if o.unknown_size {
-            // 不定長の場合はスタックに積まずに即時バッファに書き込む
+            // 不定長の場合は閉じタグを待たずに即時バッファに書き込む
             let mut data = encode_master_tag(o, vec![])?;
-            self.queue.append(&mut data);
-            return Ok(());
+            self.sink().append(&mut data);
         }
         let tree = (o, vec![]);
         // スタックに積む
@@ -116,21 +182,39 @@ impl<'a, D: SchemaDict<'a>> Encoder<'a, D> {
     }
     #[logfn(ok = "TRACE", err = "ERROR")]
     fn end_tag(&mut self, ebml_id: ebml::EbmlId) -> Result<(), EncodeError> {
-        // このスタックの大きさが確定した
-        let (o, buf) = self.stack.pop().ok_or_else(|| EncodeError::Bloken)?;
         // opening tag と closing tag の id が一致するか確認
-        if o.ebml_id != ebml_id {
-            return Err(EncodeError::Bloken);
-        }
-        let mut data = encode_master_tag(o, buf)?;
-        if !self.stack.is_empty() {
-            let last = self.stack.last_mut().unwrap();
-            last.1.append(&mut data);
-            return Ok(());
+        match self.stack.last() {
+            Some((o, _)) if o.ebml_id == ebml_id => {}
+            _ => return Err(EncodeError::Bloken),
         }
-        self.queue.append(&mut data);
+        // このスタックの大きさが確定した
+        let (o, buf) = self.stack.pop().unwrap();
+        let mut data = if o.unknown_size {
+            // 開始タグは書き込み済み
+            buf
+        } else {
+            encode_master_tag(o, buf)?
+        };
+        self.sink().append(&mut data);
         Ok(())
     }
+    /// writes a Void element of exactly `len` bytes at the current position.
+    /// it is returned by the next `encode` together with the following elements.
+    #[logfn(ok = "TRACE", err = "ERROR")]
+    pub fn reserve(&mut self, len: usize) -> Result<(), EncodeError> {
+        let mut data = encode_void(len)?;
+        self.sink().append(&mut data);
+        Ok(())
+    }
+    /// buffer where the next encoded bytes go
+    fn sink(&mut self) -> &mut Vec<u8> {
+        // 親要素がすべて不定長なら閉じタグを待つ必要はない
+        if self.stack.iter().all(|(o, _)| o.unknown_size) {
+            return &mut self.queue;
+        }
+        // 親要素が閉じタグありなら閉じタグが来るまで待つ(master stack queueに入る)
+        &mut self.stack.last_mut().unwrap().1
+    }
 }
 
 #[derive(Debug, Error)]
@@ -139,6 +223,12 @@ pub enum EncodeTagError {
     UnrepresentableValue(#[error(cause)] UnrepresentableValueError),
     #[error(display = "TryFromIntError")]
     TryFromIntError(#[error(cause)] std::num::TryFromIntError),
+    #[error(display = "cannot fill exactly {} bytes", _0)]
+    UnfillableLength(usize),
+    #[error(display = "date out of range: {}", _0)]
+    DateOutOfRange(chrono::DateTime<chrono::Utc>),
+    #[error(display = "{} bytes string is longer than bytesize {}", len, bytesize)]
+    TooLong { len: usize, bytesize: usize },
 }
 
 impl From<std::num::TryFromIntError> for EncodeTagError {
@@ -175,18 +265,45 @@ fn encode_master_tag(
         write_vint(i64::try_from(body.len())?)?
     };
     let mut buf2 = vec![];
-    buf2.write_int::<BigEndian>(o.ebml_id.0, 1).unwrap();
+    buf2.append(&mut o.ebml_id.into());
     buf2.append(&mut size_buffer);
     buf2.append(&mut body);
     Ok(buf2)
 }
 
+/// zero-pads the string to the fixed length
+fn pad_string(
+    elm: ebml::ChildElement,
+    bytesize: usize,
+) -> Result<ebml::ChildElement, EncodeTagError> {
+    let too_long = |len| EncodeTagError::TooLong { len, bytesize };
+    Ok(match elm {
+        ebml::ChildElement::StringElement(mut o) => {
+            if o.value.len() > bytesize {
+                return Err(too_long(o.value.len()));
+            }
+            o.value.resize(bytesize, 0);
+            o.into()
+        }
+        ebml::ChildElement::Utf8Element(mut o) => {
+            if o.value.len() > bytesize {
+                return Err(too_long(o.value.len()));
+            }
+            while o.value.len() < bytesize {
+                o.value.push('\0');
+            }
+            o.into()
+        }
+        o => o,
+    })
+}
+
 #[logfn_inputs(TRACE)]
 #[logfn(ok = "TRACE", err = "ERROR")]
 fn encode_child_tag(elm: ebml::ChildElement) -> Result<Vec<u8>, EncodeTagError> {
     Ok(match elm {
         ebml::ChildElement::BinaryElement(o) => o.into(),
-        ebml::ChildElement::DateElement(o) => o.into(),
+        ebml::ChildElement::DateElement(o) => Vec::try_from(o)?,
         ebml::ChildElement::FloatElement(o) => o.into(),
         ebml::ChildElement::IntegerElement(o) => o.into(),
         ebml::ChildElement::StringElement(o) => o.into(),
@@ -195,28 +312,74 @@ fn encode_child_tag(elm: ebml::ChildElement) -> Result<Vec<u8>, EncodeTagError>
     })
 }
 
+/// Void element of exactly `len` bytes, e.g. to reserve space for `SeekHead` or `Cues`.
+/// the size field gets longer as needed; no Void is shorter than 2 bytes.
+#[logfn_inputs(TRACE)]
+#[logfn(ok = "TRACE", err = "ERROR")]
+pub fn encode_void(len: usize) -> Result<Vec<u8>, EncodeTagError> {
+    for size_len in 1..=8u8 {
+        let content_len = match len.checked_sub(1 + usize::from(size_len)) {
+            Some(o) => i64::try_from(o)?,
+            None => break,
+        };
+        // content too long for this size length
+        if let Ok(mut size) = write_vint_with_length(content_len, size_len) {
+            let mut buf: Vec<u8> = ids::VOID.into();
+            buf.append(&mut size);
+            buf.resize(len, 0);
+            return Ok(buf);
+        }
+    }
+    Err(EncodeTagError::UnfillableLength(len))
+}
+
+/// encoded elements followed by a Void so that the total is exactly `len` bytes.
+/// when just 1 byte is left, the size field of the first element gets 1 byte longer instead.
+#[logfn(ok = "TRACE", err = "ERROR")]
+pub fn encode_padded(mut data: Vec<u8>, len: usize) -> Result<Vec<u8>, EncodeTagError> {
+    let unfillable = || EncodeTagError::UnfillableLength(len);
+    let rest = len.checked_sub(data.len()).ok_or_else(unfillable)?;
+    match rest {
+        0 => Ok(data),
+        1 => {
+            let tag = read_vint(&data, 0).ok().flatten().ok_or_else(unfillable)?;
+            let tag_len = usize::from(tag.length);
+            let size = read_vint(&data, tag_len)
+                .ok()
+                .flatten()
+                .ok_or_else(unfillable)?;
+            // unknown size cannot be widened
+            if size.value == (1 << (7 * i64::from(size.length))) - 1 {
+                return Err(unfillable());
+            }
+            let mut buf = data[..tag_len].to_vec();
+            buf.append(
+                &mut write_vint_with_length(size.value, size.length + 1)
+                    .map_err(|_| unfillable())?,
+            );
+            buf.extend_from_slice(&data[tag_len + usize::from(size.length)..]);
+            Ok(buf)
+        }
+        _ => {
+            data.append(&mut encode_void(rest)?);
+            Ok(data)
+        }
+    }
+}
+
 impl From<ebml::EbmlId> for Vec<u8> {
-    #[allow(clippy::int_plus_one)] 
     fn from(ebml_id: ebml::EbmlId) -> Self {
-        // bits, big-endian
+        // bits, big-endian; the vint marker is a part of the id
         // 1xxx xxxx                                  - Class A IDs (2^7 -1 possible values) (base 0x8X)
         // 01xx xxxx  xxxx xxxx                       - Class B IDs (2^14-1 possible values) (base 0x4X 0xXX)
         // 001x xxxx  xxxx xxxx  xxxx xxxx            - Class C IDs (2^21-1 possible values) (base 0x2X 0xXX 0xXX)
         // 0001 xxxx  xxxx xxxx  xxxx xxxx  xxxx xxxx - Class D IDs (2^28-1 possible values) (base 0x1X 0xXX 0xXX 0xXX)
-        let mut buf2 = vec![];
-        if ebml_id.0 <= i64::pow(2, 7) - 1 {
-            buf2.write_int::<BigEndian>(ebml_id.0, 1).unwrap();
-            *buf2.get_mut(0).unwrap() |= 0b_1000_0000;
-        } else if ebml_id.0 <= i64::pow(2, 14) - 1 {
-            buf2.write_int::<BigEndian>(ebml_id.0, 2).unwrap();
-            *buf2.get_mut(0).unwrap() |= 0b_0100_0000;
-        } else if ebml_id.0 <= i64::pow(2, 21) - 1 {
-            buf2.write_int::<BigEndian>(ebml_id.0, 3).unwrap();
-            *buf2.get_mut(0).unwrap() |= 0b_0010_0000;
-        } else if ebml_id.0 <= i64::pow(2, 28) - 1 {
-            buf2.write_int::<BigEndian>(ebml_id.0, 4).unwrap();
-            *buf2.get_mut(0).unwrap() |= 0b_0001_0000;
+        let mut bytes: usize = 1;
+        while bytes < 4 && ebml_id.0 >= i64::pow(2, 8 * u32::try_from(bytes).unwrap()) {
+            bytes += 1;
         }
+        let mut buf2 = vec![];
+        buf2.write_int::<BigEndian>(ebml_id.0, bytes).unwrap();
         buf2
     }
 }
@@ -225,10 +388,10 @@ impl From<ebml::UnsignedIntegerElement> for Vec<u8> {
     fn from(elm: ebml::UnsignedIntegerElement) -> Self {
         // Big-endian, any size from 1 to 8
         let mut bytes: usize = 1;
-        while elm.value >= u64::pow(2, 8 * u32::try_from(bytes).unwrap()) {
+        while bytes < 8 && elm.value >> (8 * bytes) != 0 {
             bytes += 1;
         }
-        let mut buf = vec![0; bytes];
+        let mut buf = vec![];
         buf.write_uint::<BigEndian>(elm.value, bytes).unwrap();
         let mut buf2 = vec![];
         buf2.append(&mut elm.ebml_id.into());
@@ -240,12 +403,13 @@ impl From<ebml::UnsignedIntegerElement> for Vec<u8> {
 
 impl From<ebml::IntegerElement> for Vec<u8> {
     fn from(elm: ebml::IntegerElement) -> Self {
-        // Big-endian, any size from 1 to 8 octets
+        // Big-endian, any size from 1 to 8 octets, two's complement.
+        // the shortest one whose sign bit keeps the sign
         let mut bytes: usize = 1;
-        while elm.value >= i64::pow(2, 8 * u32::try_from(bytes).unwrap()) {
+        while bytes < 8 && elm.value >> (8 * bytes - 1) != elm.value >> 63 {
             bytes += 1;
         }
-        let mut buf = vec![0; bytes];
+        let mut buf = vec![];
         buf.write_int::<BigEndian>(elm.value, bytes).unwrap();
         let mut buf2 = vec![];
         buf2.append(&mut elm.ebml_id.into());
@@ -258,9 +422,23 @@ impl From<ebml::IntegerElement> for Vec<u8> {
 impl From<ebml::FloatElement> for Vec<u8> {
     fn from(elm: ebml::FloatElement) -> Self {
         // Big-endian, defined for 4 and 8 octets (32, 64 bits)
-        // currently 64bit support only
-        let mut buf = vec![0; 8];
-        buf.write_f64::<BigEndian>(elm.value).unwrap();
+        // and 10 octets (80 bits) in the old Matroska spec
+        let width = match elm.width {
+            // NaN payloads are compared too
+            ebml::FloatWidth::Auto
+                if f64::from(elm.value as f32).to_bits() == elm.value.to_bits() =>
+            {
+                ebml::FloatWidth::F32
+            }
+            ebml::FloatWidth::Auto => ebml::FloatWidth::F64,
+            o => o,
+        };
+        let mut buf = vec![];
+        match width {
+            ebml::FloatWidth::F32 => buf.write_f32::<BigEndian>(elm.value as f32).unwrap(),
+            ebml::FloatWidth::F80 => buf.extend_from_slice(&f64_to_f80(elm.value)),
+            _ => buf.write_f64::<BigEndian>(elm.value).unwrap(),
+        }
         let mut buf2 = vec![];
         buf2.append(&mut elm.ebml_id.into());
         buf2.append(&mut write_vint(i64::try_from(buf.len()).unwrap()).unwrap());
@@ -269,6 +447,31 @@ impl From<ebml::FloatElement> for Vec<u8> {
     }
 }
 
+/// 80-bit extended precision float: sign, 15-bit exponent and 64-bit mantissa with explicit integer bit
+fn f64_to_f80(value: f64) -> [u8; 10] {
+    let bits = value.to_bits();
+    let sign = ((bits >> 63) as u16) << 15;
+    let exponent = ((bits >> 52) & 0x7FF) as i32;
+    let fraction = bits & 0x000F_FFFF_FFFF_FFFF;
+    let (exponent, mantissa) = if exponent == 0x7FF {
+        // infinity or NaN
+        let nan = if fraction == 0 { 0 } else { 1 << 62 };
+        (0x7FFF, 1 << 63 | nan | fraction << 11)
+    } else if exponent != 0 {
+        (exponent - 1023 + 16383, 1 << 63 | fraction << 11)
+    } else if fraction != 0 {
+        // subnormal f64 is normal in 80 bits
+        let msb = 63 - fraction.leading_zeros() as i32;
+        (msb - 1074 + 16383, fraction << (63 - msb))
+    } else {
+        (0, 0)
+    };
+    let mut buf = [0; 10];
+    buf[..2].copy_from_slice(&(sign | exponent as u16).to_be_bytes());
+    buf[2..].copy_from_slice(&mantissa.to_be_bytes());
+    buf
+}
+
 impl From<ebml::StringElement> for Vec<u8> {
     fn from(elm: ebml::StringElement) -> Self {
         let mut buf = elm.value.clone();
@@ -302,20 +505,21 @@ impl From<ebml::BinaryElement> for Vec<u8> {
     }
 }
 
-impl From<ebml::DateElement> for Vec<u8> {
-    fn from(elm: ebml::DateElement) -> Self {
-        // nano second; Date.UTC(2001,1,1,0,0,0,0) === 980985600000
-        // new Date("2001-01-01T00:00:00.000Z").getTime() = 978307200000
+impl TryFrom<ebml::DateElement> for Vec<u8> {
+    type Error = EncodeTagError;
+    fn try_from(elm: ebml::DateElement) -> Result<Self, Self::Error> {
         // Date - signed 8 octets integer in nanoseconds with 0 indicating
         // the precise beginning of the millennium (at 2001-01-01T00:00:00,000000000 UTC)
-        let nanos = elm.value.timestamp_nanos() + 978_307_200 * 1000 * 1000 * 1000;
-        let mut buf = vec![0; 8];
-        buf.write_int::<BigEndian>(nanos, 8).unwrap();
+        let nanos = elm
+            .nanos()
+            .ok_or(EncodeTagError::DateOutOfRange(elm.value))?;
+        let mut buf = vec![];
+        buf.write_i64::<BigEndian>(nanos).unwrap();
         let mut buf2 = vec![];
         buf2.append(&mut elm.ebml_id.into());
-        buf2.append(&mut write_vint(i64::try_from(buf.len()).unwrap()).unwrap());
+        buf2.append(&mut write_vint(i64::try_from(buf.len())?)?);
         buf2.append(&mut buf);
-        buf2
+        Ok(buf2)
     }
 }
 
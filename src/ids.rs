@@ -0,0 +1,94 @@
+//! well-known EBML and Matroska element ids
+//! https://www.matroska.org/technical/specs/index.html
+use crate::ebml::EbmlId;
+
+// EBML header
+pub const EBML: EbmlId = EbmlId(0x1A45_DFA3);
+pub const EBML_VERSION: EbmlId = EbmlId(0x4286);
+pub const EBML_READ_VERSION: EbmlId = EbmlId(0x42F7);
+pub const EBML_MAX_ID_LENGTH: EbmlId = EbmlId(0x42F2);
+pub const EBML_MAX_SIZE_LENGTH: EbmlId = EbmlId(0x42F3);
+pub const DOC_TYPE: EbmlId = EbmlId(0x4282);
+pub const DOC_TYPE_VERSION: EbmlId = EbmlId(0x4287);
+pub const DOC_TYPE_READ_VERSION: EbmlId = EbmlId(0x4285);
+
+// global elements
+pub const VOID: EbmlId = EbmlId(0xEC);
+pub const CRC32: EbmlId = EbmlId(0xBF);
+
+pub const SEGMENT: EbmlId = EbmlId(0x1853_8067);
+
+// meta seek information
+pub const SEEK_HEAD: EbmlId = EbmlId(0x114D_9B74);
+pub const SEEK: EbmlId = EbmlId(0x4DBB);
+pub const SEEK_ID: EbmlId = EbmlId(0x53AB);
+pub const SEEK_POSITION: EbmlId = EbmlId(0x53AC);
+
+// segment information
+pub const INFO: EbmlId = EbmlId(0x1549_A966);
+pub const TIMECODE_SCALE: EbmlId = EbmlId(0x2A_D7B1);
+pub const DURATION: EbmlId = EbmlId(0x4489);
+pub const TITLE: EbmlId = EbmlId(0x7BA9);
+pub const MUXING_APP: EbmlId = EbmlId(0x4D80);
+pub const WRITING_APP: EbmlId = EbmlId(0x5741);
+
+// cluster
+pub const CLUSTER: EbmlId = EbmlId(0x1F43_B675);
+pub const TIMECODE: EbmlId = EbmlId(0xE7);
+pub const SIMPLE_BLOCK: EbmlId = EbmlId(0xA3);
+pub const BLOCK_GROUP: EbmlId = EbmlId(0xA0);
+pub const BLOCK: EbmlId = EbmlId(0xA1);
+pub const BLOCK_DURATION: EbmlId = EbmlId(0x9B);
+pub const REFERENCE_BLOCK: EbmlId = EbmlId(0xFB);
+
+// track
+pub const TRACKS: EbmlId = EbmlId(0x1654_AE6B);
+pub const TRACK_ENTRY: EbmlId = EbmlId(0xAE);
+pub const TRACK_NUMBER: EbmlId = EbmlId(0xD7);
+pub const TRACK_UID: EbmlId = EbmlId(0x73C5);
+pub const TRACK_TYPE: EbmlId = EbmlId(0x83);
+pub const DEFAULT_DURATION: EbmlId = EbmlId(0x23_E383);
+pub const NAME: EbmlId = EbmlId(0x536E);
+pub const LANGUAGE: EbmlId = EbmlId(0x22_B59C);
+pub const CODEC_ID: EbmlId = EbmlId(0x86);
+pub const CODEC_PRIVATE: EbmlId = EbmlId(0x63A2);
+pub const CODEC_DELAY: EbmlId = EbmlId(0x56AA);
+pub const SEEK_PRE_ROLL: EbmlId = EbmlId(0x56BB);
+
+// video
+pub const VIDEO: EbmlId = EbmlId(0xE0);
+pub const PIXEL_WIDTH: EbmlId = EbmlId(0xB0);
+pub const PIXEL_HEIGHT: EbmlId = EbmlId(0xBA);
+
+// audio
+pub const AUDIO: EbmlId = EbmlId(0xE1);
+pub const SAMPLING_FREQUENCY: EbmlId = EbmlId(0xB5);
+pub const CHANNELS: EbmlId = EbmlId(0x9F);
+pub const BIT_DEPTH: EbmlId = EbmlId(0x6264);
+
+// cueing data
+pub const CUES: EbmlId = EbmlId(0x1C53_BB6B);
+pub const CUE_POINT: EbmlId = EbmlId(0xBB);
+pub const CUE_TIME: EbmlId = EbmlId(0xB3);
+pub const CUE_TRACK_POSITIONS: EbmlId = EbmlId(0xB7);
+pub const CUE_TRACK: EbmlId = EbmlId(0xF7);
+pub const CUE_CLUSTER_POSITION: EbmlId = EbmlId(0xF1);
+pub const CUE_RELATIVE_POSITION: EbmlId = EbmlId(0xF0);
+
+// Attachment
+pub const ATTACHMENTS: EbmlId = EbmlId(0x1941_A469);
+pub const ATTACHED_FILE: EbmlId = EbmlId(0x61A7);
+pub const FILE_DESCRIPTION: EbmlId = EbmlId(0x467E);
+pub const FILE_NAME: EbmlId = EbmlId(0x466E);
+pub const FILE_MIME_TYPE: EbmlId = EbmlId(0x4660);
+pub const FILE_DATA: EbmlId = EbmlId(0x465C);
+pub const FILE_UID: EbmlId = EbmlId(0x46AE);
+
+pub const CHAPTERS: EbmlId = EbmlId(0x1043_A770);
+
+// tagging
+pub const TAGS: EbmlId = EbmlId(0x1254_C367);
+pub const TAG: EbmlId = EbmlId(0x7373);
+pub const SIMPLE_TAG: EbmlId = EbmlId(0x67C8);
+pub const TAG_NAME: EbmlId = EbmlId(0x45A3);
+pub const TAG_STRING: EbmlId = EbmlId(0x4487);
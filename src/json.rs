@@ -0,0 +1,298 @@
+//! Human-editable JSON representation of EBML documents.
+//!
+//! Each element is an object with its schema `name`; master elements have
+//! `children` (and `unknown_size: true` when they had no size), the others have `value`:
+//!
+//! * `u` `i` `f`: number; non-finite floats are `"NaN"`, `"Infinity"` and `"-Infinity"`.
+//!   floats of 4 or 10 bytes have `width: 4` or `width: 10`; 8 bytes otherwise
+//! * `s`: string; each byte is a char of U+0000-U+00FF so that non-ASCII bytes survive
+//! * `8`: string
+//! * `b`: base64 string
+//! * `d`: ISO-8601 string with nanoseconds, e.g. `"2001-01-01T00:00:00.000000001Z"`
+//!
+//! ```json
+//! [
+//!   { "name": "EBML", "children": [{ "name": "DocType", "value": "webm" }] },
+//!   { "name": "Segment", "unknown_size": true, "children": [] }
+//! ]
+//! ```
+use crate::decoder::{DecodeError, Decoder};
+use crate::ebml;
+use crate::encoder::{EncodeError, Encoder};
+use crate::schema::SchemaDict;
+use chrono::{DateTime, SecondsFormat, Utc};
+use err_derive::Error;
+use log_derive::logfn;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::convert::TryFrom;
+
+#[derive(Debug, Error)]
+pub enum JsonError {
+    #[error(display = "{}", _0)]
+    Decode(#[error(cause)] DecodeError),
+    #[error(display = "{}", _0)]
+    Encode(#[error(cause)] EncodeError),
+    #[error(display = "Json")]
+    Json(#[error(cause)] serde_json::Error),
+    #[error(display = "UnknownEbmlId: {:?}", _0)]
+    UnknownEbmlId(ebml::EbmlId),
+    #[error(display = "unknown element name: {}", _0)]
+    UnknownName(String),
+    #[error(display = "invalid value of {}: {}", _0, _1)]
+    InvalidValue(String, Value),
+    #[error(display = "unbalanced master element: {:?}", _0)]
+    UnbalancedMaster(ebml::EbmlId),
+}
+
+impl From<DecodeError> for JsonError {
+    fn from(o: DecodeError) -> Self {
+        JsonError::Decode(o)
+    }
+}
+
+impl From<EncodeError> for JsonError {
+    fn from(o: EncodeError) -> Self {
+        JsonError::Encode(o)
+    }
+}
+
+impl From<serde_json::Error> for JsonError {
+    fn from(o: serde_json::Error) -> Self {
+        JsonError::Json(o)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonElement {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<Value>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub unknown_size: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub children: Option<Vec<JsonElement>>,
+    // bytes of float content; None for 8 bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u8>,
+}
+
+/// decodes the whole EBML document into pretty-printed JSON
+#[logfn(ok = "TRACE", err = "ERROR")]
+pub fn ebml_to_json<D: SchemaDict + ?Sized>(schema: &D, buf: Vec<u8>) -> Result<String, JsonError> {
+    let elms = Decoder::new(schema)
+        .decode(buf)?
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    Ok(serde_json::to_string_pretty(&to_json(schema, elms)?)?)
+}
+
+/// encodes the JSON made by `ebml_to_json` (or written by hand) into EBML
+#[logfn(ok = "TRACE", err = "ERROR")]
+pub fn json_to_ebml<D: SchemaDict + ?Sized>(schema: &D, json: &str) -> Result<Vec<u8>, JsonError> {
+    let elms = from_json(schema, serde_json::from_str(json)?)?;
+    Ok(Encoder::new(schema).encode(elms)?)
+}
+
+/// nests the flat elements. masters left open at the end are closed.
+#[logfn(ok = "TRACE", err = "ERROR")]
+pub fn to_json<D: SchemaDict + ?Sized>(
+    schema: &D,
+    elms: Vec<ebml::Element>,
+) -> Result<Vec<JsonElement>, JsonError> {
+    let name = |ebml_id| {
+        schema
+            .get(ebml_id)
+            .map(|o| o.name().to_string())
+            .ok_or(JsonError::UnknownEbmlId(ebml_id))
+    };
+    let mut roots = vec![];
+    // (id, open master)
+    let mut stack: Vec<(ebml::EbmlId, JsonElement)> = vec![];
+    for elm in elms {
+        let node = match elm {
+            ebml::Element::MasterElement(ebml::MasterElement::MasterStartElement(o)) => {
+                let node = JsonElement {
+                    name: name(o.ebml_id)?,
+                    value: None,
+                    unknown_size: o.unknown_size,
+                    children: Some(vec![]),
+                    width: None,
+                };
+                stack.push((o.ebml_id, node));
+                continue;
+            }
+            ebml::Element::MasterElement(ebml::MasterElement::MasterEndElement(o)) => {
+                match stack.pop() {
+                    Some((ebml_id, node)) if ebml_id == o.ebml_id => node,
+                    _ => return Err(JsonError::UnbalancedMaster(o.ebml_id)),
+                }
+            }
+            ebml::Element::ChildElement(o) => {
+                let ebml_id = o.ebml_id();
+                let width = match o {
+                    ebml::ChildElement::FloatElement(ebml::FloatElement {
+                        width: ebml::FloatWidth::F32,
+                        ..
+                    }) => Some(4),
+                    ebml::ChildElement::FloatElement(ebml::FloatElement {
+                        width: ebml::FloatWidth::F80,
+                        ..
+                    }) => Some(10),
+                    _ => None,
+                };
+                JsonElement {
+                    name: name(ebml_id)?,
+                    value: Some(child_value(o)),
+                    unknown_size: false,
+                    children: None,
+                    width,
+                }
+            }
+        };
+        push_node(&mut stack, &mut roots, node);
+    }
+    while let Some((_, node)) = stack.pop() {
+        push_node(&mut stack, &mut roots, node);
+    }
+    Ok(roots)
+}
+
+/// flattens the elements into the encoder input
+#[logfn(ok = "TRACE", err = "ERROR")]
+pub fn from_json<D: SchemaDict + ?Sized>(
+    schema: &D,
+    json: Vec<JsonElement>,
+) -> Result<Vec<ebml::Element>, JsonError> {
+    let mut elms = vec![];
+    for node in json {
+        flatten(schema, node, &mut elms)?;
+    }
+    Ok(elms)
+}
+
+fn push_node(
+    stack: &mut [(ebml::EbmlId, JsonElement)],
+    roots: &mut Vec<JsonElement>,
+    node: JsonElement,
+) {
+    match stack.last_mut() {
+        Some((_, parent)) => parent.children.get_or_insert_with(Vec::new).push(node),
+        None => roots.push(node),
+    }
+}
+
+fn child_value(elm: ebml::ChildElement) -> Value {
+    use ebml::ChildElement::*;
+    match elm {
+        UnsignedIntegerElement(o) => Value::from(o.value),
+        IntegerElement(o) => Value::from(o.value),
+        FloatElement(o) => {
+            if o.value.is_nan() {
+                Value::from("NaN")
+            } else if o.value.is_infinite() && o.value > 0.0 {
+                Value::from("Infinity")
+            } else if o.value.is_infinite() {
+                Value::from("-Infinity")
+            } else {
+                Value::from(o.value)
+            }
+        }
+        StringElement(o) => Value::from(o.value.iter().map(|b| char::from(*b)).collect::<String>()),
+        Utf8Element(o) => Value::from(o.value),
+        BinaryElement(o) => Value::from(base64::encode(&o.value)),
+        DateElement(o) => Value::from(o.value.to_rfc3339_opts(SecondsFormat::Nanos, true)),
+    }
+}
+
+fn flatten<D: SchemaDict + ?Sized>(
+    schema: &D,
+    node: JsonElement,
+    elms: &mut Vec<ebml::Element>,
+) -> Result<(), JsonError> {
+    let (ebml_id, entry) = schema
+        .get_by_name(&node.name)
+        .ok_or_else(|| JsonError::UnknownName(node.name.clone()))?;
+    if entry.r#type() == 'm' {
+        elms.push(
+            ebml::MasterStartElement {
+                ebml_id,
+                unknown_size: node.unknown_size,
+            }
+            .into(),
+        );
+        for child in node.children.unwrap_or_default() {
+            flatten(schema, child, elms)?;
+        }
+        elms.push(ebml::MasterEndElement { ebml_id }.into());
+        return Ok(());
+    }
+    let name = node.name;
+    let width = node.width;
+    let value = node.value.unwrap_or(Value::Null);
+    let invalid = || JsonError::InvalidValue(name.clone(), value.clone());
+    let elm: ebml::Element = match entry.r#type() {
+        'u' => ebml::UnsignedIntegerElement {
+            ebml_id,
+            value: value.as_u64().ok_or_else(invalid)?,
+        }
+        .into(),
+        'i' => ebml::IntegerElement {
+            ebml_id,
+            value: value.as_i64().ok_or_else(invalid)?,
+        }
+        .into(),
+        'f' => ebml::FloatElement {
+            ebml_id,
+            value: match value.as_str() {
+                Some("NaN") => f64::NAN,
+                Some("Infinity") => f64::INFINITY,
+                Some("-Infinity") => f64::NEG_INFINITY,
+                _ => value.as_f64().ok_or_else(invalid)?,
+            },
+            width: match width {
+                Some(4) => ebml::FloatWidth::F32,
+                Some(10) => ebml::FloatWidth::F80,
+                None | Some(8) => ebml::FloatWidth::F64,
+                Some(_) => return Err(invalid()),
+            },
+        }
+        .into(),
+        's' => ebml::StringElement {
+            ebml_id,
+            value: value
+                .as_str()
+                .ok_or_else(invalid)?
+                .chars()
+                .map(|c| u8::try_from(u32::from(c)).map_err(|_| invalid()))
+                .collect::<Result<_, _>>()?,
+        }
+        .into(),
+        '8' => ebml::Utf8Element {
+            ebml_id,
+            value: value.as_str().ok_or_else(invalid)?.to_string(),
+        }
+        .into(),
+        'b' => ebml::BinaryElement {
+            ebml_id,
+            value: value
+                .as_str()
+                .and_then(|o| base64::decode(o).ok())
+                .ok_or_else(invalid)?,
+        }
+        .into(),
+        'd' => ebml::DateElement {
+            ebml_id,
+            value: value
+                .as_str()
+                .and_then(|o| DateTime::parse_from_rfc3339(o).ok())
+                .map(|o| o.with_timezone(&Utc))
+                .ok_or_else(invalid)?,
+        }
+        .into(),
+        _ => return Err(invalid()),
+    };
+    elms.push(elm);
+    Ok(())
+}
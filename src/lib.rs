@@ -1,8 +1,18 @@
+pub mod block;
+pub mod cues;
 pub mod decoder;
 pub mod ebml;
+pub mod edit;
 pub mod encoder;
+pub mod ids;
+pub mod json;
+pub mod matroska;
 pub mod schema;
+pub mod segment;
+pub mod transform;
+pub mod validate;
 pub mod vint;
+pub mod webm;
 
 pub use decoder::Decoder;
 pub use encoder::Encoder;
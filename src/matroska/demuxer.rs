@@ -0,0 +1,278 @@
+use super::{Attachment, Audio, Packet, Track, Video};
+use crate::block::{read_block, ReadBlockError};
+use crate::decoder::{DecodeError, Decoder};
+use crate::ebml;
+use crate::ids;
+use crate::schema::SchemaDict;
+use err_derive::Error;
+use log_derive::logfn;
+use std::convert::TryFrom;
+
+#[derive(Debug, Error)]
+pub enum DemuxError {
+    #[error(display = "{}", _0)]
+    Decode(#[error(cause)] DecodeError),
+    #[error(display = "ReadBlock")]
+    ReadBlock(#[error(cause)] ReadBlockError),
+}
+
+impl From<DecodeError> for DemuxError {
+    fn from(o: DecodeError) -> Self {
+        DemuxError::Decode(o)
+    }
+}
+
+impl From<ReadBlockError> for DemuxError {
+    fn from(o: ReadBlockError) -> Self {
+        DemuxError::ReadBlock(o)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+struct BlockGroup {
+    block: Option<Vec<u8>>,
+    duration: Option<u64>,
+    reference: bool,
+}
+
+/// Splits a Matroska stream into packets of each track in file order.
+pub struct Demuxer<D: SchemaDict> {
+    decoder: Decoder<D>,
+    timecode_scale: u64,
+    cluster_timecode: u64,
+    tracks: Vec<Track>,
+    // TrackEntry being read
+    track: Option<Track>,
+    // BlockGroup being read
+    group: Option<BlockGroup>,
+    attachments: Vec<Attachment>,
+    // AttachedFile being read
+    attachment: Option<Attachment>,
+}
+
+impl<D: SchemaDict> Demuxer<D> {
+    pub fn new(schema: D) -> Self {
+        Self {
+            decoder: Decoder::new(schema),
+            timecode_scale: 1_000_000,
+            cluster_timecode: 0,
+            tracks: vec![],
+            track: None,
+            group: None,
+            attachments: vec![],
+            attachment: None,
+        }
+    }
+    /// tracks read so far
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+    pub fn track(&self, number: u64) -> Option<&Track> {
+        self.tracks.iter().find(|o| o.number == number)
+    }
+    /// attachments read so far
+    pub fn attachments(&self) -> &[Attachment] {
+        &self.attachments
+    }
+    /// `TimecodeScale` in nanoseconds
+    pub fn timecode_scale(&self) -> u64 {
+        self.timecode_scale
+    }
+    #[logfn(ok = "TRACE", err = "ERROR")]
+    pub fn demux(&mut self, chunk: Vec<u8>) -> Result<Vec<Packet>, DemuxError> {
+        let mut packets = vec![];
+        for elm in self.decoder.decode(chunk)? {
+            self.read_element(elm.into(), &mut packets)?;
+        }
+        Ok(packets)
+    }
+    fn read_element(
+        &mut self,
+        elm: ebml::Element,
+        packets: &mut Vec<Packet>,
+    ) -> Result<(), DemuxError> {
+        use ebml::ChildElement::*;
+        use ebml::Element::*;
+        use ebml::MasterElement::*;
+        match elm {
+            MasterElement(MasterStartElement(o)) => match o.ebml_id {
+                ids::TRACK_ENTRY => self.track = Some(Track::default()),
+                ids::VIDEO => {
+                    if let Some(track) = self.track.as_mut() {
+                        track.video = Some(Video::default());
+                    }
+                }
+                ids::AUDIO => {
+                    if let Some(track) = self.track.as_mut() {
+                        track.audio = Some(Audio {
+                            sampling_frequency: 8000.0,
+                            channels: 1,
+                            bit_depth: None,
+                        });
+                    }
+                }
+                ids::BLOCK_GROUP => self.group = Some(BlockGroup::default()),
+                ids::ATTACHED_FILE => self.attachment = Some(Attachment::default()),
+                _ => {}
+            },
+            MasterElement(MasterEndElement(o)) => match o.ebml_id {
+                ids::TRACK_ENTRY => {
+                    if let Some(track) = self.track.take() {
+                        self.tracks.retain(|o| o.number != track.number);
+                        self.tracks.push(track);
+                    }
+                }
+                ids::BLOCK_GROUP => {
+                    if let Some(BlockGroup {
+                        block: Some(block),
+                        duration,
+                        reference,
+                    }) = self.group.take()
+                    {
+                        self.push_block(&block, duration, Some(!reference), packets)?;
+                    }
+                }
+                ids::ATTACHED_FILE => {
+                    if let Some(attachment) = self.attachment.take() {
+                        self.attachments.push(attachment);
+                    }
+                }
+                _ => {}
+            },
+            ChildElement(UnsignedIntegerElement(o)) => match (o.ebml_id, self.track.as_mut()) {
+                (ids::TIMECODE_SCALE, _) => self.timecode_scale = o.value,
+                (ids::TIMECODE, _) => self.cluster_timecode = o.value,
+                (ids::FILE_UID, _) => {
+                    if let Some(attachment) = self.attachment.as_mut() {
+                        attachment.uid = o.value;
+                    }
+                }
+                (ids::BLOCK_DURATION, _) => {
+                    if let Some(group) = self.group.as_mut() {
+                        group.duration = Some(o.value);
+                    }
+                }
+                (ids::TRACK_NUMBER, Some(track)) => track.number = o.value,
+                (ids::TRACK_UID, Some(track)) => track.uid = o.value,
+                (ids::TRACK_TYPE, Some(track)) => track.track_type = o.value,
+                (ids::DEFAULT_DURATION, Some(track)) => track.default_duration = Some(o.value),
+                (ids::CODEC_DELAY, Some(track)) => track.codec_delay = Some(o.value),
+                (ids::SEEK_PRE_ROLL, Some(track)) => track.seek_pre_roll = Some(o.value),
+                (
+                    ids::PIXEL_WIDTH,
+                    Some(Track {
+                        video: Some(video), ..
+                    }),
+                ) => video.pixel_width = o.value,
+                (
+                    ids::PIXEL_HEIGHT,
+                    Some(Track {
+                        video: Some(video), ..
+                    }),
+                ) => video.pixel_height = o.value,
+                (
+                    ids::CHANNELS,
+                    Some(Track {
+                        audio: Some(audio), ..
+                    }),
+                ) => audio.channels = o.value,
+                (
+                    ids::BIT_DEPTH,
+                    Some(Track {
+                        audio: Some(audio), ..
+                    }),
+                ) => audio.bit_depth = Some(o.value),
+                _ => {}
+            },
+            ChildElement(IntegerElement(o)) => {
+                if let (ids::REFERENCE_BLOCK, Some(group)) = (o.ebml_id, self.group.as_mut()) {
+                    group.reference = true;
+                }
+            }
+            ChildElement(FloatElement(o)) => {
+                if let (
+                    ids::SAMPLING_FREQUENCY,
+                    Some(Track {
+                        audio: Some(audio), ..
+                    }),
+                ) = (o.ebml_id, self.track.as_mut())
+                {
+                    audio.sampling_frequency = o.value;
+                }
+            }
+            ChildElement(StringElement(o)) => {
+                let value = String::from_utf8_lossy(&o.value).into_owned();
+                match (o.ebml_id, self.track.as_mut(), self.attachment.as_mut()) {
+                    (ids::CODEC_ID, Some(track), _) => track.codec_id = value,
+                    (ids::LANGUAGE, Some(track), _) => track.language = Some(value),
+                    (ids::FILE_MIME_TYPE, _, Some(attachment)) => attachment.mime_type = value,
+                    _ => {}
+                }
+            }
+            ChildElement(Utf8Element(o)) => {
+                match (o.ebml_id, self.track.as_mut(), self.attachment.as_mut()) {
+                    (ids::NAME, Some(track), _) => track.name = Some(o.value),
+                    (ids::FILE_NAME, _, Some(attachment)) => attachment.name = o.value,
+                    (ids::FILE_DESCRIPTION, _, Some(attachment)) => {
+                        attachment.description = Some(o.value)
+                    }
+                    _ => {}
+                }
+            }
+            ChildElement(BinaryElement(o)) => match o.ebml_id {
+                ids::SIMPLE_BLOCK => self.push_block(&o.value, None, None, packets)?,
+                ids::BLOCK => {
+                    if let Some(group) = self.group.as_mut() {
+                        group.block = Some(o.value);
+                    }
+                }
+                ids::CODEC_PRIVATE => {
+                    if let Some(track) = self.track.as_mut() {
+                        track.codec_private = Some(o.value);
+                    }
+                }
+                ids::FILE_DATA => {
+                    if let Some(attachment) = self.attachment.as_mut() {
+                        attachment.data = o.value;
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+        Ok(())
+    }
+    /// `keyframe` is None for SimpleBlock, which has the keyframe flag in itself
+    fn push_block(
+        &self,
+        buf: &[u8],
+        block_duration: Option<u64>,
+        keyframe: Option<bool>,
+        packets: &mut Vec<Packet>,
+    ) -> Result<(), DemuxError> {
+        let block = read_block(buf)?;
+        let track = u64::try_from(block.track_number).unwrap_or(0);
+        let scale = i64::try_from(self.timecode_scale).unwrap_or(i64::MAX);
+        let timecode = i64::try_from(self.cluster_timecode)
+            .unwrap_or(i64::MAX)
+            .saturating_add(block.timecode);
+        let pts_ns = timecode.saturating_mul(scale);
+        let count = block.frames.len() as u64;
+        // laced frames share the block duration
+        let duration_ns = match block_duration {
+            Some(duration) => Some(duration.saturating_mul(self.timecode_scale) / count),
+            None => self.track(track).and_then(|o| o.default_duration),
+        };
+        for (i, data) in block.frames.into_iter().enumerate() {
+            let offset = duration_ns.unwrap_or(0).saturating_mul(i as u64);
+            packets.push(Packet {
+                track,
+                pts_ns: pts_ns.saturating_add(i64::try_from(offset).unwrap_or(i64::MAX)),
+                duration_ns,
+                keyframe: keyframe.unwrap_or(block.keyframe),
+                data,
+            });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,94 @@
+//! Matroska / WebM track level API on top of `Decoder` and `Encoder`
+use serde::{Deserialize, Serialize};
+
+mod demuxer;
+mod muxer;
+
+pub use demuxer::{DemuxError, Demuxer};
+pub use muxer::{MuxError, Muxer, MuxerOptions};
+
+pub const TRACK_TYPE_VIDEO: u64 = 1;
+pub const TRACK_TYPE_AUDIO: u64 = 2;
+pub const TRACK_TYPE_SUBTITLE: u64 = 0x11;
+
+/// `TrackEntry`
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Track {
+    pub number: u64,
+    pub uid: u64,
+    pub track_type: u64,
+    pub codec_id: String,
+    pub codec_private: Option<Vec<u8>>,
+    // nanoseconds
+    pub default_duration: Option<u64>,
+    // nanoseconds
+    pub codec_delay: Option<u64>,
+    // nanoseconds
+    pub seek_pre_roll: Option<u64>,
+    pub name: Option<String>,
+    pub language: Option<String>,
+    pub video: Option<Video>,
+    pub audio: Option<Audio>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Video {
+    pub pixel_width: u64,
+    pub pixel_height: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Audio {
+    pub sampling_frequency: f64,
+    pub channels: u64,
+    pub bit_depth: Option<u64>,
+}
+
+/// `AttachedFile`
+#[derive(Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Attachment {
+    pub uid: u64,
+    pub name: String,
+    pub mime_type: String,
+    pub description: Option<String>,
+    pub data: Vec<u8>,
+}
+
+impl std::fmt::Debug for Attachment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Attachment {{ uid: {:?}, name: {:?}, mime_type: {:?}, description: {:?}, data: Vec<u8; {:?}> }}",
+            self.uid,
+            self.name,
+            self.mime_type,
+            self.description,
+            self.data.len()
+        )
+    }
+}
+
+/// a frame of a track
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Packet {
+    pub track: u64,
+    // absolute timestamp in nanoseconds
+    pub pts_ns: i64,
+    pub duration_ns: Option<u64>,
+    pub keyframe: bool,
+    pub data: Vec<u8>,
+}
+
+impl std::fmt::Debug for Packet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Packet {{ track: {:?}, pts_ns: {:?}, duration_ns: {:?}, keyframe: {:?}, data: Vec<u8; {:?}> }}",
+            self.track,
+            self.pts_ns,
+            self.duration_ns,
+            self.keyframe,
+            self.data.len()
+        )
+    }
+}
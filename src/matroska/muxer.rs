@@ -0,0 +1,496 @@
+use super::{Packet, Track, TRACK_TYPE_VIDEO};
+use crate::block::{write_block, WriteBlockError};
+use crate::ebml;
+use crate::encoder::{encode_padded, encode_void, EncodeError, EncodeTagError, Encoder};
+use crate::ids;
+use crate::schema::SchemaDict;
+use crate::vint::write_vint_with_length;
+use crate::webm::{cues_elements, seek_head_elements};
+use err_derive::Error;
+use log_derive::logfn;
+use std::convert::TryFrom;
+use std::io::{Seek, SeekFrom, Write};
+
+#[derive(Debug, Error)]
+pub enum MuxError {
+    #[error(display = "{}", _0)]
+    Encode(#[error(cause)] EncodeError),
+    #[error(display = "WriteBlock")]
+    WriteBlock(#[error(cause)] WriteBlockError),
+    #[error(display = "unknown track: {}", _0)]
+    UnknownTrack(u64),
+    #[error(display = "negative timestamp: {}", _0)]
+    NegativeTimestamp(i64),
+    #[error(display = "{}", _0)]
+    Io(#[error(cause)] std::io::Error),
+}
+
+impl From<EncodeError> for MuxError {
+    fn from(o: EncodeError) -> Self {
+        MuxError::Encode(o)
+    }
+}
+
+impl From<WriteBlockError> for MuxError {
+    fn from(o: WriteBlockError) -> Self {
+        MuxError::WriteBlock(o)
+    }
+}
+
+impl From<EncodeTagError> for MuxError {
+    fn from(o: EncodeTagError) -> Self {
+        MuxError::Encode(o.into())
+    }
+}
+
+impl From<std::io::Error> for MuxError {
+    fn from(o: std::io::Error) -> Self {
+        MuxError::Io(o)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MuxerOptions {
+    pub doc_type: String,
+    // nanoseconds
+    pub timecode_scale: u64,
+    // a new cluster is started at the next keyframe after these limits
+    pub max_cluster_duration_ns: u64,
+    pub max_cluster_size: usize,
+}
+
+impl Default for MuxerOptions {
+    fn default() -> Self {
+        Self {
+            doc_type: "webm".to_string(),
+            timecode_scale: 1_000_000,
+            max_cluster_duration_ns: 5_000_000_000,
+            max_cluster_size: 5 * 1024 * 1024,
+        }
+    }
+}
+
+struct Cluster {
+    // TimecodeScale units
+    timecode: u64,
+    elms: Vec<ebml::Element>,
+    size: usize,
+    // tracks which already have a cue point in this cluster
+    cued_tracks: Vec<u64>,
+}
+
+// positions in the output, relative to the start of the Segment data except `size`
+#[derive(Debug, Clone, Copy)]
+struct Layout {
+    // absolute position of the 8 bytes Segment size
+    size: u64,
+    // absolute position of the Segment data
+    data_start: u64,
+    // Void reserved for SeekHead
+    seek_head_len: usize,
+    info: u64,
+    info_len: usize,
+    tracks: u64,
+}
+
+/// Builds a complete Matroska / WebM file from tracks and packets.
+///
+/// Clusters start at keyframes of the video tracks (or of any track when there is no video)
+/// and are written out as soon as they are complete, so only the current cluster and the cue
+/// points are kept in memory. `Cues` are written on `finish`, which then seeks back to fill the
+/// Segment size, the `SeekHead` in the reserved Void, and the `Duration`.
+pub struct Muxer<D: SchemaDict, W: Write + Seek> {
+    schema: D,
+    options: MuxerOptions,
+    tracks: Vec<Track>,
+    writer: W,
+    // written with the first cluster
+    layout: Option<Layout>,
+    cluster_positions: Vec<u64>,
+    cluster: Option<Cluster>,
+    // (cue time, track number, index of cluster)
+    cue_points: Vec<(u64, u64, usize)>,
+    // (track number, timecode of the last block) for ReferenceBlock
+    last_timecodes: Vec<(u64, i64)>,
+    // end of the last packet in TimecodeScale units
+    duration: u64,
+}
+
+impl<D: SchemaDict, W: Write + Seek> Muxer<D, W> {
+    pub fn new(schema: D, tracks: Vec<Track>, writer: W) -> Self {
+        Self::with_options(schema, tracks, MuxerOptions::default(), writer)
+    }
+    pub fn with_options(schema: D, tracks: Vec<Track>, options: MuxerOptions, writer: W) -> Self {
+        Self {
+            schema,
+            options,
+            tracks,
+            writer,
+            layout: None,
+            cluster_positions: vec![],
+            cluster: None,
+            cue_points: vec![],
+            last_timecodes: vec![],
+            duration: 0,
+        }
+    }
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+    /// packets should be given in timestamp order
+    #[logfn(ok = "TRACE", err = "ERROR")]
+    pub fn write(&mut self, packet: Packet) -> Result<(), MuxError> {
+        let track = self
+            .tracks
+            .iter()
+            .find(|o| o.number == packet.track)
+            .ok_or(MuxError::UnknownTrack(packet.track))?;
+        let default_duration = track.default_duration;
+        let has_video = self.tracks.iter().any(|o| o.track_type == TRACK_TYPE_VIDEO);
+        let aligned = packet.keyframe && (!has_video || track.track_type == TRACK_TYPE_VIDEO);
+        if packet.pts_ns < 0 {
+            return Err(MuxError::NegativeTimestamp(packet.pts_ns));
+        }
+        let scale = self.options.timecode_scale;
+        let timecode = packet.pts_ns as u64 / scale;
+
+        let new_cluster = match self.cluster.as_ref() {
+            None => true,
+            Some(cluster) => {
+                let relative = timecode as i64 - cluster.timecode as i64;
+                let elapsed = timecode.saturating_sub(cluster.timecode) * scale;
+                // block timecodes are 16 bit signed
+                i16::try_from(relative).is_err()
+                    || aligned
+                        && (elapsed >= self.options.max_cluster_duration_ns
+                            || cluster.size >= self.options.max_cluster_size)
+            }
+        };
+        if new_cluster {
+            self.flush_cluster()?;
+            self.cluster = Some(Cluster {
+                timecode,
+                elms: vec![
+                    ebml::MasterStartElement {
+                        ebml_id: ids::CLUSTER,
+                        unknown_size: false,
+                    }
+                    .into(),
+                    ebml::UnsignedIntegerElement {
+                        ebml_id: ids::TIMECODE,
+                        value: timecode,
+                    }
+                    .into(),
+                ],
+                size: 0,
+                cued_tracks: vec![],
+            });
+        }
+        let cluster_index = self.cluster_positions.len();
+        let cluster = self.cluster.as_mut().unwrap();
+
+        if aligned && !cluster.cued_tracks.contains(&packet.track) {
+            cluster.cued_tracks.push(packet.track);
+            self.cue_points
+                .push((timecode, packet.track, cluster_index));
+        }
+        let duration = packet.duration_ns.map(|o| o / scale);
+        self.duration = self.duration.max(
+            timecode
+                + duration
+                    .or(default_duration.map(|o| o / scale))
+                    .unwrap_or(0),
+        );
+
+        let last_timecode = self
+            .last_timecodes
+            .iter()
+            .find(|(o, _)| *o == packet.track)
+            .map(|(_, o)| *o);
+        self.last_timecodes.retain(|(o, _)| *o != packet.track);
+        self.last_timecodes.push((packet.track, timecode as i64));
+
+        let relative = timecode as i64 - cluster.timecode as i64;
+        let track_number = i64::try_from(packet.track).unwrap_or(i64::MAX);
+        let mut block = ebml::SimpleBlock {
+            discardable: false,
+            frames: vec![packet.data],
+            invisible: false,
+            keyframe: packet.keyframe,
+            timecode: relative,
+            track_number,
+        };
+        // BlockDuration is needed only when the duration is not the DefaultDuration
+        match duration {
+            Some(duration) if packet.duration_ns != default_duration => {
+                block.keyframe = false;
+                let value = write_block(&block)?;
+                cluster.size += value.len();
+                cluster.elms.push(
+                    ebml::MasterStartElement {
+                        ebml_id: ids::BLOCK_GROUP,
+                        unknown_size: false,
+                    }
+                    .into(),
+                );
+                cluster.elms.push(
+                    ebml::BinaryElement {
+                        ebml_id: ids::BLOCK,
+                        value,
+                    }
+                    .into(),
+                );
+                cluster.elms.push(
+                    ebml::UnsignedIntegerElement {
+                        ebml_id: ids::BLOCK_DURATION,
+                        value: duration,
+                    }
+                    .into(),
+                );
+                if !packet.keyframe {
+                    let reference = last_timecode.unwrap_or(timecode as i64) - timecode as i64;
+                    cluster.elms.push(
+                        ebml::IntegerElement {
+                            ebml_id: ids::REFERENCE_BLOCK,
+                            value: reference,
+                        }
+                        .into(),
+                    );
+                }
+                cluster.elms.push(
+                    ebml::MasterEndElement {
+                        ebml_id: ids::BLOCK_GROUP,
+                    }
+                    .into(),
+                );
+            }
+            _ => {
+                let value = write_block(&block)?;
+                cluster.size += value.len();
+                cluster.elms.push(
+                    ebml::BinaryElement {
+                        ebml_id: ids::SIMPLE_BLOCK,
+                        value,
+                    }
+                    .into(),
+                );
+            }
+        }
+        Ok(())
+    }
+    /// writes the rest of the file and returns the writer at its end
+    #[logfn(err = "ERROR")]
+    pub fn finish(mut self) -> Result<W, MuxError> {
+        self.flush_cluster()?;
+        let layout = self.begin()?;
+        let mut seeks = vec![(ids::INFO, layout.info), (ids::TRACKS, layout.tracks)];
+        if !self.cue_points.is_empty() {
+            seeks.push((ids::CUES, self.position(&layout)?));
+            let cues = cues_elements(&self.cue_points, &self.cluster_positions);
+            let buf = Encoder::new(&self.schema).encode(cues)?;
+            self.writer.write_all(&buf)?;
+        }
+        let end = self.writer.stream_position()?;
+        let size = i64::try_from(end - layout.data_start).map_err(EncodeTagError::from)?;
+        let size = write_vint_with_length(size, 8).map_err(EncodeTagError::from)?;
+        self.writer.seek(SeekFrom::Start(layout.size))?;
+        self.writer.write_all(&size)?;
+        let seek_head = Encoder::new(&self.schema).encode(seek_head_elements(seeks))?;
+        self.writer.seek(SeekFrom::Start(layout.data_start))?;
+        self.writer
+            .write_all(&encode_padded(seek_head, layout.seek_head_len)?)?;
+        // Info keeps its length as Duration is always an 8 bytes float
+        let info = Encoder::new(&self.schema).encode(self.info_elements())?;
+        self.writer
+            .seek(SeekFrom::Start(layout.data_start + layout.info))?;
+        self.writer
+            .write_all(&encode_padded(info, layout.info_len)?)?;
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+    /// writes the EBML header, the Segment up to Tracks, and returns the positions
+    fn begin(&mut self) -> Result<Layout, MuxError> {
+        if let Some(layout) = self.layout {
+            return Ok(layout);
+        }
+        let header = Encoder::new(&self.schema).encode(self.header_elements())?;
+        self.writer.write_all(&header)?;
+        let size = self.writer.stream_position()? + 4;
+        // unknown size until finish
+        let mut segment: Vec<u8> = ids::SEGMENT.into();
+        segment.extend_from_slice(&[0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        self.writer.write_all(&segment)?;
+        let data_start = self.writer.stream_position()?;
+        // room for the largest positions
+        let seeks = vec![
+            (ids::INFO, u64::MAX),
+            (ids::TRACKS, u64::MAX),
+            (ids::CUES, u64::MAX),
+        ];
+        let seek_head_len = Encoder::new(&self.schema)
+            .encode(seek_head_elements(seeks))?
+            .len();
+        self.writer.write_all(&encode_void(seek_head_len)?)?;
+        let info = Encoder::new(&self.schema).encode(self.info_elements())?;
+        let tracks = Encoder::new(&self.schema).encode(self.tracks_elements())?;
+        let info_position = seek_head_len as u64;
+        self.writer.write_all(&info)?;
+        self.writer.write_all(&tracks)?;
+        let layout = Layout {
+            size,
+            data_start,
+            seek_head_len,
+            info: info_position,
+            info_len: info.len(),
+            tracks: info_position + info.len() as u64,
+        };
+        self.layout = Some(layout);
+        Ok(layout)
+    }
+    /// current position relative to the Segment data
+    fn position(&mut self, layout: &Layout) -> Result<u64, MuxError> {
+        Ok(self.writer.stream_position()? - layout.data_start)
+    }
+    fn flush_cluster(&mut self) -> Result<(), MuxError> {
+        if let Some(mut cluster) = self.cluster.take() {
+            cluster.elms.push(
+                ebml::MasterEndElement {
+                    ebml_id: ids::CLUSTER,
+                }
+                .into(),
+            );
+            let buf = Encoder::new(&self.schema).encode(cluster.elms)?;
+            let layout = self.begin()?;
+            let position = self.position(&layout)?;
+            self.cluster_positions.push(position);
+            self.writer.write_all(&buf)?;
+        }
+        Ok(())
+    }
+    fn header_elements(&self) -> Vec<ebml::Element> {
+        vec![
+            start(ids::EBML),
+            uint(ids::EBML_VERSION, 1),
+            uint(ids::EBML_READ_VERSION, 1),
+            uint(ids::EBML_MAX_ID_LENGTH, 4),
+            uint(ids::EBML_MAX_SIZE_LENGTH, 8),
+            string(ids::DOC_TYPE, &self.options.doc_type),
+            uint(ids::DOC_TYPE_VERSION, 4),
+            uint(ids::DOC_TYPE_READ_VERSION, 2),
+            end(ids::EBML),
+        ]
+    }
+    fn info_elements(&self) -> Vec<ebml::Element> {
+        vec![
+            start(ids::INFO),
+            uint(ids::TIMECODE_SCALE, self.options.timecode_scale),
+            ebml::FloatElement {
+                ebml_id: ids::DURATION,
+                value: self.duration as f64,
+                width: ebml::FloatWidth::F64,
+            }
+            .into(),
+            utf8(ids::MUXING_APP, env!("CARGO_PKG_NAME")),
+            utf8(ids::WRITING_APP, env!("CARGO_PKG_NAME")),
+            end(ids::INFO),
+        ]
+    }
+    fn tracks_elements(&self) -> Vec<ebml::Element> {
+        let mut elms = vec![start(ids::TRACKS)];
+        for track in self.tracks.iter() {
+            elms.push(start(ids::TRACK_ENTRY));
+            elms.push(uint(ids::TRACK_NUMBER, track.number));
+            let uid = if track.uid == 0 {
+                track.number
+            } else {
+                track.uid
+            };
+            elms.push(uint(ids::TRACK_UID, uid));
+            elms.push(uint(ids::TRACK_TYPE, track.track_type));
+            elms.push(string(ids::CODEC_ID, &track.codec_id));
+            if let Some(codec_private) = track.codec_private.as_ref() {
+                elms.push(
+                    ebml::BinaryElement {
+                        ebml_id: ids::CODEC_PRIVATE,
+                        value: codec_private.clone(),
+                    }
+                    .into(),
+                );
+            }
+            if let Some(value) = track.default_duration {
+                elms.push(uint(ids::DEFAULT_DURATION, value));
+            }
+            if let Some(value) = track.codec_delay {
+                elms.push(uint(ids::CODEC_DELAY, value));
+            }
+            if let Some(value) = track.seek_pre_roll {
+                elms.push(uint(ids::SEEK_PRE_ROLL, value));
+            }
+            if let Some(value) = track.name.as_ref() {
+                elms.push(utf8(ids::NAME, value));
+            }
+            if let Some(value) = track.language.as_ref() {
+                elms.push(string(ids::LANGUAGE, value));
+            }
+            if let Some(video) = track.video.as_ref() {
+                elms.push(start(ids::VIDEO));
+                elms.push(uint(ids::PIXEL_WIDTH, video.pixel_width));
+                elms.push(uint(ids::PIXEL_HEIGHT, video.pixel_height));
+                elms.push(end(ids::VIDEO));
+            }
+            if let Some(audio) = track.audio.as_ref() {
+                elms.push(start(ids::AUDIO));
+                elms.push(
+                    ebml::FloatElement {
+                        ebml_id: ids::SAMPLING_FREQUENCY,
+                        value: audio.sampling_frequency,
+                        // float32 when lossless as many files do
+                        width: ebml::FloatWidth::Auto,
+                    }
+                    .into(),
+                );
+                elms.push(uint(ids::CHANNELS, audio.channels));
+                if let Some(value) = audio.bit_depth {
+                    elms.push(uint(ids::BIT_DEPTH, value));
+                }
+                elms.push(end(ids::AUDIO));
+            }
+            elms.push(end(ids::TRACK_ENTRY));
+        }
+        elms.push(end(ids::TRACKS));
+        elms
+    }
+}
+
+fn start(ebml_id: ebml::EbmlId) -> ebml::Element {
+    ebml::MasterStartElement {
+        ebml_id,
+        unknown_size: false,
+    }
+    .into()
+}
+
+fn end(ebml_id: ebml::EbmlId) -> ebml::Element {
+    ebml::MasterEndElement { ebml_id }.into()
+}
+
+fn uint(ebml_id: ebml::EbmlId, value: u64) -> ebml::Element {
+    ebml::UnsignedIntegerElement { ebml_id, value }.into()
+}
+
+fn string(ebml_id: ebml::EbmlId, value: &str) -> ebml::Element {
+    ebml::StringElement {
+        ebml_id,
+        value: value.as_bytes().to_vec(),
+    }
+    .into()
+}
+
+fn utf8(ebml_id: ebml::EbmlId, value: &str) -> ebml::Element {
+    ebml::Utf8Element {
+        ebml_id,
+        value: value.to_string(),
+    }
+    .into()
+}
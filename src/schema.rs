@@ -1,18 +1,116 @@
 use crate::ebml;
+use crate::ids;
+use err_derive::Error;
 use serde::Deserialize;
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::Arc;
 
 const DEFAULT_SCHEMA_JSON: &str = include_str!("../schema.json");
 
-pub trait SchemaDict<'a> {
-    type Item: Schema;
-    fn get(&'a self, ebml_id: ebml::EbmlId) -> Option<&'a Self::Item>;
+// m u i f s 8 b d
+const TYPES: &str = "muifs8bd";
+
+// elements available before DocType is known
+const HEADER_IDS: &[ebml::EbmlId] = &[
+    ids::EBML,
+    ids::EBML_VERSION,
+    ids::EBML_READ_VERSION,
+    ids::EBML_MAX_ID_LENGTH,
+    ids::EBML_MAX_SIZE_LENGTH,
+    ids::DOC_TYPE,
+    ids::DOC_TYPE_VERSION,
+    ids::DOC_TYPE_READ_VERSION,
+    ids::VOID,
+    ids::CRC32,
+];
+
+/// `Decoder` and `Encoder` hold it by value:
+/// `&DefaultSchema`, `DefaultSchema` itself or `SharedSchema` to move them between threads
+pub trait SchemaDict {
+    fn get(&self, ebml_id: ebml::EbmlId) -> Option<&dyn Schema>;
+    fn get_by_name(&self, name: &str) -> Option<(ebml::EbmlId, &dyn Schema)>;
+    /// all the element IDs; schemas which cannot list them return none
+    fn ids(&self) -> Vec<ebml::EbmlId> {
+        vec![]
+    }
+}
+
+/// schema shared by the decoders and the encoders in different threads
+pub type SharedSchema = Arc<dyn SchemaDict + Send + Sync>;
+
+impl<T: SchemaDict + ?Sized> SchemaDict for &T {
+    fn get(&self, ebml_id: ebml::EbmlId) -> Option<&dyn Schema> {
+        (**self).get(ebml_id)
+    }
+    fn get_by_name(&self, name: &str) -> Option<(ebml::EbmlId, &dyn Schema)> {
+        (**self).get_by_name(name)
+    }
+    fn ids(&self) -> Vec<ebml::EbmlId> {
+        (**self).ids()
+    }
+}
+
+impl<T: SchemaDict + ?Sized> SchemaDict for Box<T> {
+    fn get(&self, ebml_id: ebml::EbmlId) -> Option<&dyn Schema> {
+        (**self).get(ebml_id)
+    }
+    fn get_by_name(&self, name: &str) -> Option<(ebml::EbmlId, &dyn Schema)> {
+        (**self).get_by_name(name)
+    }
+    fn ids(&self) -> Vec<ebml::EbmlId> {
+        (**self).ids()
+    }
+}
+
+impl<T: SchemaDict + ?Sized> SchemaDict for Arc<T> {
+    fn get(&self, ebml_id: ebml::EbmlId) -> Option<&dyn Schema> {
+        (**self).get(ebml_id)
+    }
+    fn get_by_name(&self, name: &str) -> Option<(ebml::EbmlId, &dyn Schema)> {
+        (**self).get_by_name(name)
+    }
+    fn ids(&self) -> Vec<ebml::EbmlId> {
+        (**self).ids()
+    }
 }
 
 pub trait Schema {
     fn name(&self) -> &str;
     fn r#type(&self) -> char;
     fn level(&self) -> i64;
+    // fixed content length
+    fn bytesize(&self) -> Option<usize> {
+        None
+    }
+    // EBML path from the top level, e.g. `\Segment\Info\Title`.
+    // `+` marks recursive elements: `\Segment\Chapters\EditionEntry\+ChapterAtom`
+    fn path(&self) -> Option<&str> {
+        None
+    }
+    fn mandatory(&self) -> bool {
+        false
+    }
+    fn multiple(&self) -> bool {
+        false
+    }
+    // e.g. `not 0`, `> 0`, `0-1`
+    fn range(&self) -> Option<&str> {
+        None
+    }
+    fn default_value(&self) -> Option<&serde_json::Value> {
+        None
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SchemaError {
+    #[error(display = "invalid EBML ID: {:?}", _0)]
+    InvalidId(ebml::EbmlId),
+    #[error(display = "invalid entry {}: {}", _0, _1)]
+    InvalidEntry(String, String),
+    #[error(display = "{:?} {} is already defined", _0, _1)]
+    Conflict(ebml::EbmlId, String),
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq)]
@@ -25,10 +123,40 @@ impl Default for DefaultSchema {
     }
 }
 
-impl<'a> SchemaDict<'a> for DefaultSchema {
-    type Item = DefaultSchemaEntry;
-    fn get(&'a self, ebml_id: ebml::EbmlId) -> Option<&'a Self::Item> {
-        self.0.get(&format!("{}", ebml_id)).map(Into::into)
+impl DefaultSchema {
+    /// the EBML header and the global elements only
+    pub fn header() -> Self {
+        let mut schema = Self::default();
+        schema
+            .0
+            .retain(|id, _| HEADER_IDS.iter().any(|o| format!("{}", o) == *id));
+        schema
+    }
+    /// entry with all the properties in schema.json
+    pub fn entry(&self, ebml_id: ebml::EbmlId) -> Option<&DefaultSchemaEntry> {
+        self.0.get(&format!("{}", ebml_id))
+    }
+    pub fn entry_by_name(&self, name: &str) -> Option<(ebml::EbmlId, &DefaultSchemaEntry)> {
+        self.0
+            .iter()
+            .find(|(_, o)| o.name == name)
+            .and_then(|(id, o)| id.parse().ok().map(|id| (ebml::EbmlId(id), o)))
+    }
+}
+
+impl SchemaDict for DefaultSchema {
+    fn get(&self, ebml_id: ebml::EbmlId) -> Option<&dyn Schema> {
+        self.entry(ebml_id).map(|o| o as &dyn Schema)
+    }
+    fn get_by_name(&self, name: &str) -> Option<(ebml::EbmlId, &dyn Schema)> {
+        self.entry_by_name(name)
+            .map(|(ebml_id, o)| (ebml_id, o as &dyn Schema))
+    }
+    fn ids(&self) -> Vec<ebml::EbmlId> {
+        self.0
+            .keys()
+            .filter_map(|id| id.parse().ok().map(ebml::EbmlId))
+            .collect()
     }
 }
 
@@ -37,9 +165,14 @@ pub struct DefaultSchemaEntry {
     pub name: String,
     pub r#type: String,
     pub level: i64,
+    // no path for global elements
+    pub path: Option<String>,
     pub description: String,
     pub cppname: Option<String>,
+    pub mandatory: Option<bool>,
     pub multiple: Option<bool>,
+    // "1" for elements which can be their own child
+    pub recursive: Option<String>,
     pub webm: Option<bool>,
     pub minver: Option<i64>,
     pub bytesize: Option<i64>,
@@ -47,6 +180,29 @@ pub struct DefaultSchemaEntry {
     pub default: Option<serde_json::Value>,
 }
 
+impl DefaultSchemaEntry {
+    /// entry without the optional properties.
+    /// `level` is -1 for global elements
+    pub fn new(name: &str, r#type: char, level: i64) -> Self {
+        Self {
+            name: name.to_string(),
+            r#type: r#type.to_string(),
+            level,
+            path: None,
+            description: String::new(),
+            cppname: None,
+            mandatory: None,
+            multiple: None,
+            recursive: None,
+            webm: None,
+            minver: None,
+            bytesize: None,
+            range: None,
+            default: None,
+        }
+    }
+}
+
 impl Schema for DefaultSchemaEntry {
     fn name(&self) -> &str {
         &self.name
@@ -57,4 +213,203 @@ impl Schema for DefaultSchemaEntry {
     fn level(&self) -> i64 {
         self.level
     }
+    fn bytesize(&self) -> Option<usize> {
+        self.bytesize.and_then(|o| usize::try_from(o).ok())
+    }
+    fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+    fn mandatory(&self) -> bool {
+        self.mandatory == Some(true)
+    }
+    fn multiple(&self) -> bool {
+        self.multiple == Some(true)
+    }
+    fn range(&self) -> Option<&str> {
+        self.range.as_deref()
+    }
+    fn default_value(&self) -> Option<&serde_json::Value> {
+        self.default.as_ref()
+    }
+}
+
+/// schemas by DocType of the EBML header
+#[derive(Clone)]
+pub struct SchemaRegistry {
+    header: SharedSchema,
+    schemas: HashMap<String, SharedSchema>,
+    // for unknown DocTypes
+    fallback: Option<SharedSchema>,
+    // for EBML headers without DocType
+    default_doc_type: Option<String>,
+}
+
+impl Default for SchemaRegistry {
+    /// `matroska` and `webm` with `DefaultSchema`; `matroska` when the EBML header has no DocType
+    fn default() -> Self {
+        let schema: SharedSchema = Arc::new(DefaultSchema::default());
+        let mut registry = Self::empty();
+        registry.register("matroska", schema.clone());
+        registry.register("webm", schema);
+        registry.set_default_doc_type(Some("matroska".to_string()));
+        registry
+    }
+}
+
+impl SchemaRegistry {
+    pub fn empty() -> Self {
+        Self {
+            header: Arc::new(DefaultSchema::header()),
+            schemas: HashMap::new(),
+            fallback: None,
+            default_doc_type: None,
+        }
+    }
+    pub fn register<S: Into<String>>(&mut self, doc_type: S, schema: SharedSchema) -> &mut Self {
+        self.schemas.insert(doc_type.into(), schema);
+        self
+    }
+    pub fn set_fallback(&mut self, schema: Option<SharedSchema>) -> &mut Self {
+        self.fallback = schema;
+        self
+    }
+    /// DocType for EBML headers without one; when None they are an error
+    pub fn set_default_doc_type(&mut self, doc_type: Option<String>) -> &mut Self {
+        self.default_doc_type = doc_type;
+        self
+    }
+    pub fn default_doc_type(&self) -> Option<&str> {
+        self.default_doc_type.as_deref()
+    }
+    /// schema for the DocType, with the EBML header and the global elements
+    pub fn get(&self, doc_type: &str) -> Option<SharedSchema> {
+        let schema = self.schemas.get(doc_type).or(self.fallback.as_ref())?;
+        Some(Arc::new(WithHeader {
+            header: self.header.clone(),
+            schema: schema.clone(),
+        }))
+    }
+    /// schema to read the EBML header
+    pub fn header(&self) -> SharedSchema {
+        self.header.clone()
+    }
+}
+
+/// the EBML header elements are looked up when the schema does not have them
+struct WithHeader {
+    header: SharedSchema,
+    schema: SharedSchema,
+}
+
+impl SchemaDict for WithHeader {
+    fn get(&self, ebml_id: ebml::EbmlId) -> Option<&dyn Schema> {
+        self.schema
+            .get(ebml_id)
+            .or_else(|| self.header.get(ebml_id))
+    }
+    fn get_by_name(&self, name: &str) -> Option<(ebml::EbmlId, &dyn Schema)> {
+        self.schema
+            .get_by_name(name)
+            .or_else(|| self.header.get_by_name(name))
+    }
+    fn ids(&self) -> Vec<ebml::EbmlId> {
+        merge_ids(self.schema.ids(), self.header.ids())
+    }
+}
+
+/// entries defined in code, e.g. private elements to put on `DefaultSchema` with `LayeredSchema`
+#[derive(Debug, Clone, Default)]
+pub struct SchemaBuilder {
+    entries: Vec<(ebml::EbmlId, DefaultSchemaEntry)>,
+}
+
+impl SchemaBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// element of the type `m u i f s 8 b d` at the level (-1 for global)
+    pub fn element(self, ebml_id: ebml::EbmlId, name: &str, r#type: char, level: i64) -> Self {
+        self.entry(ebml_id, DefaultSchemaEntry::new(name, r#type, level))
+    }
+    /// element with the other properties such as `multiple` or `bytesize`
+    pub fn entry(mut self, ebml_id: ebml::EbmlId, entry: DefaultSchemaEntry) -> Self {
+        self.entries.push((ebml_id, entry));
+        self
+    }
+    pub fn build(self) -> Result<DefaultSchema, SchemaError> {
+        let mut schema = DefaultSchema(HashMap::new());
+        for (ebml_id, entry) in self.entries {
+            validate_entry(ebml_id, &entry)?;
+            if schema.get(ebml_id).is_some() || schema.get_by_name(&entry.name).is_some() {
+                return Err(SchemaError::Conflict(ebml_id, entry.name));
+            }
+            schema.0.insert(format!("{}", ebml_id), entry);
+        }
+        Ok(schema)
+    }
+}
+
+/// schema with the `overlay` entries added to `base`, e.g. private elements on `DefaultSchema`
+pub struct LayeredSchema<B: SchemaDict> {
+    base: B,
+    overlay: DefaultSchema,
+}
+
+impl<B: SchemaDict> LayeredSchema<B> {
+    /// fails when an ID or a name of the overlay is already in the base
+    pub fn new(base: B, overlay: DefaultSchema) -> Result<Self, SchemaError> {
+        for (id, entry) in &overlay.0 {
+            let ebml_id = id
+                .parse()
+                .map(ebml::EbmlId)
+                .map_err(|_| SchemaError::InvalidEntry(entry.name.clone(), id.clone()))?;
+            validate_entry(ebml_id, entry)?;
+            if base.get(ebml_id).is_some() || base.get_by_name(&entry.name).is_some() {
+                return Err(SchemaError::Conflict(ebml_id, entry.name.clone()));
+            }
+        }
+        Ok(Self { base, overlay })
+    }
+}
+
+impl<B: SchemaDict> SchemaDict for LayeredSchema<B> {
+    fn get(&self, ebml_id: ebml::EbmlId) -> Option<&dyn Schema> {
+        self.overlay.get(ebml_id).or_else(|| self.base.get(ebml_id))
+    }
+    fn get_by_name(&self, name: &str) -> Option<(ebml::EbmlId, &dyn Schema)> {
+        self.overlay
+            .get_by_name(name)
+            .or_else(|| self.base.get_by_name(name))
+    }
+    fn ids(&self) -> Vec<ebml::EbmlId> {
+        merge_ids(self.overlay.ids(), self.base.ids())
+    }
+}
+
+fn merge_ids(mut ids: Vec<ebml::EbmlId>, others: Vec<ebml::EbmlId>) -> Vec<ebml::EbmlId> {
+    for id in others {
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+    ids
+}
+
+fn validate_entry(ebml_id: ebml::EbmlId, entry: &DefaultSchemaEntry) -> Result<(), SchemaError> {
+    // 1 to 4 bytes with the vint marker bit
+    if !(1..=4).any(|len| ebml_id.0 >> (7 * len) == 1) {
+        return Err(SchemaError::InvalidId(ebml_id));
+    }
+    let invalid =
+        |message: &str| SchemaError::InvalidEntry(entry.name.clone(), message.to_string());
+    if entry.name.is_empty() {
+        return Err(invalid("empty name"));
+    }
+    if entry.r#type.chars().count() != 1 || !TYPES.contains(&entry.r#type) {
+        return Err(invalid(&format!("unknown type {:?}", entry.r#type)));
+    }
+    if entry.level < -1 {
+        return Err(invalid(&format!("level {}", entry.level)));
+    }
+    Ok(())
 }
@@ -0,0 +1,234 @@
+use crate::decoder::{DecodeError, Decoder, ReadEbmlExt};
+use crate::ebml;
+use crate::ids;
+use crate::schema::SchemaDict;
+use err_derive::Error;
+use log_derive::logfn;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::io::{Read, Seek, SeekFrom};
+
+#[derive(Debug, Error)]
+pub enum SegmentError {
+    #[error(display = "{}", _0)]
+    Decode(#[error(cause)] DecodeError),
+    #[error(display = "Io")]
+    Io(#[error(cause)] std::io::Error),
+    #[error(display = "Segment not found")]
+    SegmentNotFound,
+    #[error(display = "unknown-size element cannot be read as a whole: {:?}", _0)]
+    UnknownSize(ebml::EbmlId),
+    #[error(display = "element {:?} at {} runs past the end of the stream", _0, _1)]
+    Truncated(ebml::EbmlId, u64),
+    #[error(display = "SeekPosition {} is out of range", _0)]
+    InvalidSeekPosition(u64),
+}
+
+impl From<DecodeError> for SegmentError {
+    fn from(o: DecodeError) -> Self {
+        SegmentError::Decode(o)
+    }
+}
+
+impl From<std::io::Error> for SegmentError {
+    fn from(o: std::io::Error) -> Self {
+        SegmentError::Io(o)
+    }
+}
+
+/// positions of the top-level elements of the first Segment,
+/// used to load them on demand without reading the whole stream
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct SegmentIndex {
+    // absolute offset of the first byte of the Segment data
+    pub segment_start: u64,
+    // absolute offset of the end of the Segment data; None for unknown-size Segment
+    pub segment_end: Option<u64>,
+    // (id, absolute offset) of the top-level elements
+    pub entries: Vec<(ebml::EbmlId, u64)>,
+}
+
+impl SegmentIndex {
+    /// builds the index from `SeekHead`, or by scanning the top-level elements when it is missing
+    #[logfn(ok = "TRACE", err = "ERROR")]
+    pub fn read<R: Read + Seek, D: SchemaDict + ?Sized>(
+        reader: &mut R,
+        schema: &D,
+    ) -> Result<Self, SegmentError> {
+        let (segment_start, segment_end) = find_segment(reader)?;
+        reader.seek(SeekFrom::Start(segment_start))?;
+        let first = match reader.read_element_header() {
+            Ok(header) => header,
+            Err(DecodeError::Io(_)) => return Self::scan(reader),
+            Err(err) => return Err(err.into()),
+        };
+        if first.ebml_id != ids::SEEK_HEAD {
+            return Self::scan(reader);
+        }
+        let mut entries = vec![(ids::SEEK_HEAD, segment_start)];
+        let mut seek_heads = vec![segment_start];
+        while let Some(position) = seek_heads.pop() {
+            for (ebml_id, position) in read_seek_head(reader, schema, segment_start, position)? {
+                if entries.contains(&(ebml_id, position)) {
+                    continue;
+                }
+                // SeekHead may point to another SeekHead
+                if ebml_id == ids::SEEK_HEAD {
+                    seek_heads.push(position);
+                }
+                entries.push((ebml_id, position));
+            }
+        }
+        entries.sort_by_key(|(_, position)| *position);
+        Ok(SegmentIndex {
+            segment_start,
+            segment_end,
+            entries,
+        })
+    }
+    /// builds the index by skipping over every top-level element.
+    /// stops at the first unknown-size element, which cannot be skipped.
+    #[logfn(ok = "TRACE", err = "ERROR")]
+    pub fn scan<R: Read + Seek>(reader: &mut R) -> Result<Self, SegmentError> {
+        let (segment_start, segment_end) = find_segment(reader)?;
+        let mut entries = vec![];
+        let mut position = reader.seek(SeekFrom::Start(segment_start))?;
+        while segment_end.map(|end| position < end).unwrap_or(true) {
+            let header = match reader.read_element_header() {
+                Ok(header) => header,
+                Err(DecodeError::Io(_)) => break,
+                Err(err) => return Err(err.into()),
+            };
+            entries.push((header.ebml_id, position));
+            if header.content_size < 0 {
+                break;
+            }
+            position = reader.seek(SeekFrom::Start(
+                position + header.header_size as u64 + header.content_size as u64,
+            ))?;
+        }
+        Ok(SegmentIndex {
+            segment_start,
+            segment_end,
+            entries,
+        })
+    }
+    /// absolute offset of the first top-level element of the id
+    pub fn position(&self, ebml_id: ebml::EbmlId) -> Option<u64> {
+        self.positions(ebml_id).next()
+    }
+    /// absolute offsets of all top-level elements of the id
+    pub fn positions(&self, ebml_id: ebml::EbmlId) -> impl Iterator<Item = u64> + '_ {
+        self.entries
+            .iter()
+            .filter(move |(id, _)| *id == ebml_id)
+            .map(|(_, position)| *position)
+    }
+    /// decodes the first top-level element of the id
+    #[logfn(ok = "TRACE", err = "ERROR")]
+    pub fn load<R: Read + Seek, D: SchemaDict + ?Sized>(
+        &self,
+        reader: &mut R,
+        schema: &D,
+        ebml_id: ebml::EbmlId,
+    ) -> Result<Option<Vec<ebml::ElementDetail>>, SegmentError> {
+        match self.position(ebml_id) {
+            Some(position) => Ok(Some(read_element(reader, schema, position)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// decodes the whole element at the absolute offset
+#[logfn(ok = "TRACE", err = "ERROR")]
+pub fn read_element<R: Read + Seek, D: SchemaDict + ?Sized>(
+    reader: &mut R,
+    schema: &D,
+    position: u64,
+) -> Result<Vec<ebml::ElementDetail>, SegmentError> {
+    let stream_len = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(position))?;
+    let header = reader.read_element_header()?;
+    if header.content_size < 0 {
+        return Err(SegmentError::UnknownSize(header.ebml_id));
+    }
+    // the size comes from the stream; check it before allocating
+    let len = usize::try_from(header.content_size)
+        .ok()
+        .and_then(|o| o.checked_add(header.header_size))
+        .filter(|o| {
+            u64::try_from(*o)
+                .ok()
+                .and_then(|o| position.checked_add(o))
+                .is_some_and(|end| end <= stream_len)
+        })
+        .ok_or(SegmentError::Truncated(header.ebml_id, position))?;
+    let mut buf = vec![0; len];
+    reader.seek(SeekFrom::Start(position))?;
+    reader.read_exact(&mut buf)?;
+    Ok(Decoder::new(schema).decode(buf)?)
+}
+
+/// (absolute start, absolute end) of the first Segment data
+fn find_segment<R: Read + Seek>(reader: &mut R) -> Result<(u64, Option<u64>), SegmentError> {
+    let mut position = reader.seek(SeekFrom::Start(0))?;
+    loop {
+        let header = match reader.read_element_header() {
+            Ok(header) => header,
+            Err(DecodeError::Io(_)) => return Err(SegmentError::SegmentNotFound),
+            Err(err) => return Err(err.into()),
+        };
+        let content_start = position + header.header_size as u64;
+        if header.ebml_id == ids::SEGMENT {
+            let end = if header.content_size < 0 {
+                None
+            } else {
+                Some(content_start + header.content_size as u64)
+            };
+            return Ok((content_start, end));
+        }
+        if header.content_size < 0 {
+            return Err(SegmentError::SegmentNotFound);
+        }
+        position = reader.seek(SeekFrom::Start(content_start + header.content_size as u64))?;
+    }
+}
+
+/// (id, absolute offset) of the elements listed in the SeekHead
+fn read_seek_head<R: Read + Seek, D: SchemaDict + ?Sized>(
+    reader: &mut R,
+    schema: &D,
+    segment_start: u64,
+    position: u64,
+) -> Result<Vec<(ebml::EbmlId, u64)>, SegmentError> {
+    let mut entries = vec![];
+    let mut seek_id = None;
+    let mut seek_position = None;
+    for elm in read_element(reader, schema, position)? {
+        match ebml::Element::from(elm) {
+            ebml::Element::ChildElement(ebml::ChildElement::BinaryElement(o))
+                if o.ebml_id == ids::SEEK_ID =>
+            {
+                let id = o.value.iter().fold(0, |id, b| (id << 8) | i64::from(*b));
+                seek_id = Some(ebml::EbmlId(id));
+            }
+            ebml::Element::ChildElement(ebml::ChildElement::UnsignedIntegerElement(o))
+                if o.ebml_id == ids::SEEK_POSITION =>
+            {
+                let position = segment_start
+                    .checked_add(o.value)
+                    .ok_or(SegmentError::InvalidSeekPosition(o.value))?;
+                seek_position = Some(position);
+            }
+            ebml::Element::MasterElement(ebml::MasterElement::MasterEndElement(o))
+                if o.ebml_id == ids::SEEK =>
+            {
+                if let (Some(id), Some(pos)) = (seek_id.take(), seek_position.take()) {
+                    entries.push((id, pos));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(entries)
+}
@@ -0,0 +1,271 @@
+//! Rewriting of element event streams between `Decoder` and `Encoder`.
+//!
+//! ```ignore
+//! let mut transform = map(set_title).then(filter(|o| o.ebml_id() != ids::VOID));
+//! run(&mut decoder, &mut encoder, &mut transform, &mut reader, &mut writer)?;
+//! ```
+use crate::decoder::{DecodeError, Decoder};
+use crate::ebml;
+use crate::encoder::{EncodeError, Encoder};
+use crate::schema::SchemaDict;
+use err_derive::Error;
+use log_derive::logfn;
+use std::io::{Read, Write};
+
+// bytes read at once by `run`
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Error)]
+pub enum TransformError {
+    #[error(display = "Io")]
+    Io(#[error(cause)] std::io::Error),
+    #[error(display = "{}", _0)]
+    Decode(#[error(cause)] DecodeError),
+    #[error(display = "{}", _0)]
+    Encode(#[error(cause)] EncodeError),
+}
+
+impl From<std::io::Error> for TransformError {
+    fn from(o: std::io::Error) -> Self {
+        TransformError::Io(o)
+    }
+}
+
+impl From<DecodeError> for TransformError {
+    fn from(o: DecodeError) -> Self {
+        TransformError::Decode(o)
+    }
+}
+
+impl From<EncodeError> for TransformError {
+    fn from(o: EncodeError) -> Self {
+        TransformError::Encode(o)
+    }
+}
+
+/// stage of the element event stream.
+/// master elements come as start and end events; the output should keep them balanced.
+pub trait Transform {
+    /// events to pass on in place of the event
+    fn element(&mut self, elm: ebml::Element) -> Vec<ebml::Element>;
+    /// events to add at the end of the stream
+    fn finish(&mut self) -> Vec<ebml::Element> {
+        vec![]
+    }
+    /// passes the output of this transform to the next one
+    fn then<T: Transform>(self, next: T) -> Then<Self, T>
+    where
+        Self: Sized,
+    {
+        Then(self, next)
+    }
+}
+
+impl<T: Transform + ?Sized> Transform for Box<T> {
+    fn element(&mut self, elm: ebml::Element) -> Vec<ebml::Element> {
+        (**self).element(elm)
+    }
+    fn finish(&mut self) -> Vec<ebml::Element> {
+        (**self).finish()
+    }
+}
+
+pub struct Then<A, B>(A, B);
+
+impl<A: Transform, B: Transform> Transform for Then<A, B> {
+    fn element(&mut self, elm: ebml::Element) -> Vec<ebml::Element> {
+        let next = &mut self.1;
+        self.0
+            .element(elm)
+            .into_iter()
+            .flat_map(|o| next.element(o))
+            .collect()
+    }
+    fn finish(&mut self) -> Vec<ebml::Element> {
+        let mut elms = vec![];
+        for elm in self.0.finish() {
+            elms.append(&mut self.1.element(elm));
+        }
+        elms.append(&mut self.1.finish());
+        elms
+    }
+}
+
+pub struct Map<F>(F);
+
+/// rewrites each event
+pub fn map<F: FnMut(ebml::Element) -> ebml::Element>(f: F) -> Map<F> {
+    Map(f)
+}
+
+impl<F: FnMut(ebml::Element) -> ebml::Element> Transform for Map<F> {
+    fn element(&mut self, elm: ebml::Element) -> Vec<ebml::Element> {
+        vec![(self.0)(elm)]
+    }
+}
+
+pub struct Filter<F> {
+    predicate: F,
+    // depth in the master being dropped
+    dropping: usize,
+}
+
+/// keeps the events for which the predicate returns true.
+/// false for a master start drops the whole master; end events are not tested.
+pub fn filter<F: FnMut(&ebml::Element) -> bool>(predicate: F) -> Filter<F> {
+    Filter {
+        predicate,
+        dropping: 0,
+    }
+}
+
+impl<F: FnMut(&ebml::Element) -> bool> Transform for Filter<F> {
+    fn element(&mut self, elm: ebml::Element) -> Vec<ebml::Element> {
+        match &elm {
+            ebml::Element::MasterElement(ebml::MasterElement::MasterStartElement(_)) => {
+                if self.dropping > 0 || !(self.predicate)(&elm) {
+                    self.dropping += 1;
+                    return vec![];
+                }
+            }
+            ebml::Element::MasterElement(ebml::MasterElement::MasterEndElement(_)) => {
+                if self.dropping > 0 {
+                    self.dropping -= 1;
+                    return vec![];
+                }
+            }
+            ebml::Element::ChildElement(_) => {
+                if self.dropping > 0 || !(self.predicate)(&elm) {
+                    return vec![];
+                }
+            }
+        }
+        vec![elm]
+    }
+}
+
+pub struct ReplaceSubtree<F> {
+    ebml_id: ebml::EbmlId,
+    replace: F,
+    // events of the element being collected and the depth in it
+    subtree: Vec<ebml::Element>,
+    depth: usize,
+}
+
+/// replaces each element of the ID, with its descendants, by the output of `replace`.
+/// the events of a master are held until its end.
+pub fn replace_subtree<F: FnMut(Vec<ebml::Element>) -> Vec<ebml::Element>>(
+    ebml_id: ebml::EbmlId,
+    replace: F,
+) -> ReplaceSubtree<F> {
+    ReplaceSubtree {
+        ebml_id,
+        replace,
+        subtree: vec![],
+        depth: 0,
+    }
+}
+
+impl<F: FnMut(Vec<ebml::Element>) -> Vec<ebml::Element>> Transform for ReplaceSubtree<F> {
+    fn element(&mut self, elm: ebml::Element) -> Vec<ebml::Element> {
+        match &elm {
+            ebml::Element::MasterElement(ebml::MasterElement::MasterStartElement(o)) => {
+                if self.depth > 0 || o.ebml_id == self.ebml_id {
+                    self.depth += 1;
+                }
+            }
+            ebml::Element::MasterElement(ebml::MasterElement::MasterEndElement(_)) => {
+                if self.depth > 0 {
+                    self.depth -= 1;
+                    self.subtree.push(elm);
+                    if self.depth > 0 {
+                        return vec![];
+                    }
+                    let subtree = std::mem::take(&mut self.subtree);
+                    return (self.replace)(subtree);
+                }
+            }
+            ebml::Element::ChildElement(o) => {
+                if self.depth == 0 && o.ebml_id() == self.ebml_id {
+                    return (self.replace)(vec![elm]);
+                }
+            }
+        }
+        if self.depth == 0 {
+            return vec![elm];
+        }
+        self.subtree.push(elm);
+        vec![]
+    }
+    fn finish(&mut self) -> Vec<ebml::Element> {
+        // unbalanced stream; the incomplete element is replaced as is
+        self.depth = 0;
+        let subtree = std::mem::take(&mut self.subtree);
+        if subtree.is_empty() {
+            return vec![];
+        }
+        (self.replace)(subtree)
+    }
+}
+
+pub struct InsertAfter {
+    ebml_id: ebml::EbmlId,
+    elms: Vec<ebml::Element>,
+}
+
+/// inserts the events after each element of the ID (after the end of masters)
+pub fn insert_after<E: Into<ebml::Element>>(ebml_id: ebml::EbmlId, elms: Vec<E>) -> InsertAfter {
+    InsertAfter {
+        ebml_id,
+        elms: elms.into_iter().map(Into::into).collect(),
+    }
+}
+
+impl Transform for InsertAfter {
+    fn element(&mut self, elm: ebml::Element) -> Vec<ebml::Element> {
+        let matched = match &elm {
+            ebml::Element::MasterElement(ebml::MasterElement::MasterStartElement(_)) => false,
+            o => o.ebml_id() == self.ebml_id,
+        };
+        let mut elms = vec![elm];
+        if matched {
+            elms.extend(self.elms.iter().cloned());
+        }
+        elms
+    }
+}
+
+/// reads, decodes, transforms, encodes and writes chunk by chunk until the end of the reader
+#[logfn(ok = "TRACE", err = "ERROR")]
+pub fn run<D: SchemaDict, E: SchemaDict, T: Transform, R: Read, W: Write>(
+    decoder: &mut Decoder<D>,
+    encoder: &mut Encoder<E>,
+    transform: &mut T,
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<(), TransformError> {
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        let size = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(size) => size,
+            Err(ref err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+        let elms = decoder.decode(buf[..size].to_vec())?;
+        let elms = elms
+            .into_iter()
+            .flat_map(|o| transform.element(o.into()))
+            .collect::<Vec<_>>();
+        writer.write_all(&encoder.encode(elms)?)?;
+    }
+    let mut elms = decoder
+        .finish()?
+        .into_iter()
+        .flat_map(|o| transform.element(o.into()))
+        .collect::<Vec<_>>();
+    elms.append(&mut transform.finish());
+    writer.write_all(&encoder.encode(elms)?)?;
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,407 @@
+//! Schema constraint checks of a whole EBML document
+use crate::decoder::Decoder;
+use crate::ebml;
+use crate::ids;
+use crate::schema::{Schema, SchemaDict};
+use log_derive::logfn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::Read;
+
+// bytes fed to the decoder at once; decode errors are reported at the last element before it
+const CHUNK_SIZE: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FindingKind {
+    // the document cannot be decoded any further
+    Decode,
+    Mandatory,
+    Range,
+    Multiple,
+    Parent,
+    Crc32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Finding {
+    pub kind: FindingKind,
+    // absolute offset of the element; of the parent for missing mandatory elements
+    pub offset: usize,
+    // element name; empty for decode errors outside elements
+    pub name: String,
+    pub message: String,
+}
+
+struct Frame<'a> {
+    pos: ebml::ElementPosition,
+    entry: &'a dyn Schema,
+    children: Vec<ebml::EbmlId>,
+    crc: Option<Crc>,
+}
+
+// CRC-32 being computed over the rest of the sized parent
+struct Crc {
+    stored: u32,
+    hasher: crc32fast::Hasher,
+    // absolute offsets of the bytes hashed so far and of the end of the parent
+    hashed_to: usize,
+    data_end: usize,
+}
+
+struct Validator<'a, D: SchemaDict + ?Sized> {
+    schema: &'a D,
+    // mandatory children without default value, by parent name; None for the top level
+    mandatory: HashMap<Option<String>, Vec<ebml::EbmlId>>,
+    // the chunk being decoded and its absolute offset
+    chunk: Vec<u8>,
+    chunk_start: usize,
+    stack: Vec<Frame<'a>>,
+    roots: Vec<ebml::EbmlId>,
+    // end of the last decoded element
+    last_end: usize,
+    findings: Vec<Finding>,
+}
+
+/// checks mandatory, range, multiple, parent placement and CRC-32 constraints.
+/// returns no findings for a valid document.
+#[logfn(TRACE)]
+pub fn validate<D: SchemaDict + ?Sized>(schema: &D, buf: &[u8]) -> Vec<Finding> {
+    let mut reader = buf;
+    // reading a slice does not fail
+    validate_reader(schema, &mut reader).unwrap_or_default()
+}
+
+/// `validate` reading the document chunk by chunk
+#[logfn(ok = "TRACE", err = "ERROR")]
+pub fn validate_reader<D: SchemaDict + ?Sized, R: Read>(
+    schema: &D,
+    reader: &mut R,
+) -> Result<Vec<Finding>, std::io::Error> {
+    let mut decoder = Decoder::new(schema);
+    let mut validator = Validator::new(schema);
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        let size = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(size) => size,
+            Err(ref err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        validator.chunk_start += validator.chunk.len();
+        validator.chunk = buf[..size].to_vec();
+        match decoder.decode(validator.chunk.clone()) {
+            Ok(elms) => {
+                for elm in elms {
+                    validator.element(elm);
+                }
+                validator.hash_chunk();
+            }
+            Err(err) => {
+                let context = err.context();
+                let offset = context.map(|o| o.offset).unwrap_or(validator.last_end);
+                let name = context.and_then(|o| o.name.clone()).unwrap_or_default();
+                let message = format!("{}", err.kind());
+                validator.finding(FindingKind::Decode, offset, &name, &message);
+                return Ok(validator.findings);
+            }
+        }
+    }
+    let total = validator.chunk_start + validator.chunk.len();
+    if validator.stack.iter().any(|o| o.pos.content_size >= 0) || validator.last_end < total {
+        validator.decode_error("unexpected end of data");
+        return Ok(validator.findings);
+    }
+    // unknown-size masters are closed by the end of data
+    while let Some(frame) = validator.stack.pop() {
+        validator.end(frame);
+    }
+    let roots = validator.mandatory.get(&None).cloned().unwrap_or_default();
+    for ebml_id in roots {
+        if !validator.roots.contains(&ebml_id) {
+            let name = validator.name(ebml_id);
+            validator.finding(
+                FindingKind::Mandatory,
+                0,
+                &name,
+                "missing top-level element",
+            );
+        }
+    }
+    Ok(validator.findings)
+}
+
+impl<'a, D: SchemaDict + ?Sized> Validator<'a, D> {
+    fn new(schema: &'a D) -> Self {
+        let mut mandatory: HashMap<Option<String>, Vec<ebml::EbmlId>> = HashMap::new();
+        for ebml_id in schema.ids() {
+            let entry = match schema.get(ebml_id) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            if !entry.mandatory() || entry.default_value().is_some() {
+                continue;
+            }
+            if let Some(path) = entry.path() {
+                let (parent, _) = parent_in_path(path);
+                let children = mandatory.entry(parent.map(String::from)).or_default();
+                children.push(ebml_id);
+                children.sort();
+            }
+        }
+        Validator {
+            schema,
+            mandatory,
+            chunk: vec![],
+            chunk_start: 0,
+            stack: vec![],
+            roots: vec![],
+            last_end: 0,
+            findings: vec![],
+        }
+    }
+    fn name(&self, ebml_id: ebml::EbmlId) -> String {
+        self.schema
+            .get(ebml_id)
+            .map(|o| o.name().to_string())
+            .unwrap_or_default()
+    }
+    fn finding(&mut self, kind: FindingKind, offset: usize, name: &str, message: &str) {
+        self.findings.push(Finding {
+            kind,
+            offset,
+            name: name.to_string(),
+            message: message.to_string(),
+        });
+    }
+    fn decode_error(&mut self, message: &str) {
+        let offset = self.last_end;
+        self.finding(FindingKind::Decode, offset, "", message);
+    }
+    fn element(&mut self, elm: ebml::ElementDetail) {
+        let entry = match self.schema.get(elm_position(&elm).ebml_id) {
+            Some(entry) => entry,
+            None => return,
+        };
+        match elm {
+            ebml::ElementDetail::MasterElement((
+                ebml::MasterElement::MasterStartElement(_),
+                pos,
+            )) => {
+                self.placement(&pos, entry);
+                self.last_end = pos.content_start;
+                self.stack.push(Frame {
+                    pos,
+                    entry,
+                    children: vec![],
+                    crc: None,
+                });
+            }
+            ebml::ElementDetail::MasterElement((ebml::MasterElement::MasterEndElement(_), _)) => {
+                if let Some(frame) = self.stack.pop() {
+                    self.end(frame);
+                }
+            }
+            ebml::ElementDetail::ChildElement((o, pos)) => {
+                self.placement(&pos, entry);
+                self.last_end = pos.content_start + usize::try_from(pos.content_size).unwrap_or(0);
+                self.range(&o, &pos, entry);
+                if let ebml::ChildElement::BinaryElement(o) = o {
+                    if o.ebml_id == ids::CRC32 {
+                        self.crc(&o.value, &pos);
+                    }
+                }
+            }
+        }
+    }
+    /// parent and multiple checks
+    fn placement(&mut self, pos: &ebml::ElementPosition, entry: &dyn Schema) {
+        let parent = self.stack.last().map(|o| o.entry.name());
+        let depth = self.stack.len() as i64;
+        // the parent has been checked against its own path, so that only the parent is checked.
+        // global elements (level -1) may appear anywhere
+        let (placed, expected) = match entry.path() {
+            _ if entry.level() < 0 => (true, None),
+            Some(path) => {
+                let (expected, recursive) = parent_in_path(path);
+                let placed = parent == expected || recursive && parent == Some(entry.name());
+                (placed, expected)
+            }
+            None => (entry.level() == depth, None),
+        };
+        if !placed {
+            let message = match expected {
+                Some(expected) => format!(
+                    "found in {} but must be in {}",
+                    parent.unwrap_or("(root)"),
+                    expected
+                ),
+                None => format!(
+                    "level {} element found at level {} in {}",
+                    entry.level(),
+                    depth,
+                    parent.unwrap_or("(root)")
+                ),
+            };
+            self.finding(FindingKind::Parent, pos.tag_start, entry.name(), &message);
+        }
+        let siblings = match self.stack.last_mut() {
+            Some(frame) => &mut frame.children,
+            None => &mut self.roots,
+        };
+        let duplicated = siblings.contains(&pos.ebml_id);
+        siblings.push(pos.ebml_id);
+        if duplicated && !entry.multiple() && pos.ebml_id != ids::VOID {
+            self.finding(
+                FindingKind::Multiple,
+                pos.tag_start,
+                entry.name(),
+                "element must not occur more than once in its parent",
+            );
+        }
+    }
+    fn range(&mut self, elm: &ebml::ChildElement, pos: &ebml::ElementPosition, entry: &dyn Schema) {
+        let range = match entry.range() {
+            Some(range) => range,
+            None => return,
+        };
+        let value = match elm {
+            ebml::ChildElement::UnsignedIntegerElement(o) => o.value as f64,
+            ebml::ChildElement::IntegerElement(o) => o.value as f64,
+            ebml::ChildElement::FloatElement(o) => o.value,
+            _ => return,
+        };
+        if !in_range(range, value) {
+            let message = format!("{} is out of range {:?}", value, range);
+            self.finding(FindingKind::Range, pos.tag_start, entry.name(), &message);
+        }
+    }
+    fn crc(&mut self, value: &[u8], pos: &ebml::ElementPosition) {
+        if value.len() != 4 {
+            self.finding(
+                FindingKind::Crc32,
+                pos.tag_start,
+                "CRC-32",
+                "CRC-32 must be 4 bytes",
+            );
+            return;
+        }
+        let mut stored = [0; 4];
+        stored.copy_from_slice(value);
+        let data_start = pos.content_start + 4;
+        if let Some(frame) = self.stack.last_mut() {
+            // CRC-32 covers the rest of the sized parent
+            if let Ok(size) = usize::try_from(frame.pos.content_size) {
+                frame.crc = Some(Crc {
+                    stored: u32::from_le_bytes(stored),
+                    hasher: crc32fast::Hasher::new(),
+                    hashed_to: data_start,
+                    data_end: frame.pos.content_start + size,
+                });
+            }
+        }
+    }
+    /// feeds the CRC-32 of the open masters with the current chunk
+    fn hash_chunk(&mut self) {
+        for frame in self.stack.iter_mut() {
+            if let Some(crc) = frame.crc.as_mut() {
+                hash(crc, &self.chunk, self.chunk_start);
+            }
+        }
+    }
+    /// mandatory and CRC-32 checks of a closed master
+    fn end(&mut self, frame: Frame<'a>) {
+        let mandatory = self
+            .mandatory
+            .get(&Some(frame.entry.name().to_string()))
+            .cloned()
+            .unwrap_or_default();
+        for ebml_id in mandatory {
+            if !frame.children.contains(&ebml_id) {
+                let message = format!("missing mandatory child {}", self.name(ebml_id));
+                self.finding(
+                    FindingKind::Mandatory,
+                    frame.pos.tag_start,
+                    frame.entry.name(),
+                    &message,
+                );
+            }
+        }
+        if let Some(mut crc) = frame.crc {
+            hash(&mut crc, &self.chunk, self.chunk_start);
+            let computed = crc.hasher.finalize();
+            if computed != crc.stored {
+                let message = format!("stored {:08X} but computed {:08X}", crc.stored, computed);
+                self.finding(
+                    FindingKind::Crc32,
+                    frame.pos.tag_start,
+                    frame.entry.name(),
+                    &message,
+                );
+            }
+        }
+    }
+}
+
+/// hashes the part of the chunk up to the end of the CRC-32 data
+fn hash(crc: &mut Crc, chunk: &[u8], chunk_start: usize) {
+    let end = crc.data_end.min(chunk_start + chunk.len());
+    if chunk_start <= crc.hashed_to && crc.hashed_to < end {
+        crc.hasher
+            .update(&chunk[crc.hashed_to - chunk_start..end - chunk_start]);
+        crc.hashed_to = end;
+    }
+}
+
+/// (parent name, recursive) of `\Segment\Chapters\EditionEntry\+ChapterAtom`; None for the top level
+fn parent_in_path(path: &str) -> (Option<&str>, bool) {
+    let mut components = path.rsplit('\\');
+    let recursive = components
+        .next()
+        .map(|o| o.starts_with('+'))
+        .unwrap_or(false);
+    let parent = components
+        .next()
+        .filter(|o| !o.is_empty())
+        .map(|o| o.trim_start_matches('+'));
+    (parent, recursive)
+}
+
+fn elm_position(elm: &ebml::ElementDetail) -> &ebml::ElementPosition {
+    match elm {
+        ebml::ElementDetail::MasterElement((_, pos)) => pos,
+        ebml::ElementDetail::ChildElement((_, pos)) => pos,
+    }
+}
+
+/// `range` of the schema: `not 0`, `> 0`, `<= -1`, `0-1`, `-8--1` or an exact value
+fn in_range(range: &str, value: f64) -> bool {
+    let range = range.trim();
+    let parse = |o: &str| o.trim().parse::<f64>().ok();
+    let compare = |o: &str, f: fn(f64, f64) -> bool| parse(o).map(|o| f(value, o)).unwrap_or(true);
+    if let Some(o) = range.strip_prefix("not") {
+        return compare(o, |a, b| a != b);
+    }
+    if let Some(o) = range.strip_prefix(">=") {
+        return compare(o, |a, b| a >= b);
+    }
+    if let Some(o) = range.strip_prefix('>') {
+        return compare(o, |a, b| a > b);
+    }
+    if let Some(o) = range.strip_prefix("<=") {
+        return compare(o, |a, b| a <= b);
+    }
+    if let Some(o) = range.strip_prefix('<') {
+        return compare(o, |a, b| a < b);
+    }
+    // the minimum may be negative, so the separator is the first `-` after its sign
+    let sign = usize::from(range.starts_with('-'));
+    match range[sign..].find('-') {
+        Some(i) => {
+            let (min, max) = range.split_at(sign + i);
+            compare(min, |a, b| a >= b) && compare(&max[1..], |a, b| a <= b)
+        }
+        None => compare(range, |a, b| a == b),
+    }
+}
@@ -154,3 +154,19 @@ pub fn write_vint(value: i64) -> Result<Vec<u8>, UnrepresentableValueError> {
         }
     }
 }
+
+/// writes the value with the given length of vint, which may be longer than needed
+#[logfn_inputs(TRACE)]
+#[logfn(ok = "TRACE", err = "ERROR")]
+pub fn write_vint_with_length(
+    value: i64,
+    length: u8,
+) -> Result<Vec<u8>, UnrepresentableValueError> {
+    // all value bits set to one is reserved for unknown size
+    if value < 0 || !(1..=8).contains(&length) || i64::pow(2, 7 * u32::from(length)) - 2 < value {
+        return Err(UnrepresentableValueError { value });
+    }
+    let mut buffer = (value as u64).to_be_bytes()[8 - length as usize..].to_vec();
+    buffer[0] |= 1 << (8 - length);
+    Ok(buffer)
+}
@@ -0,0 +1,417 @@
+use crate::block::{read_block, ReadBlockError};
+use crate::decoder::{DecodeError, Decoder};
+use crate::ebml;
+use crate::encoder::{EncodeError, EncodeTagError, Encoder};
+use crate::ids;
+use crate::schema::SchemaDict;
+use crate::vint::write_vint;
+use err_derive::Error;
+use log_derive::logfn;
+use std::convert::TryFrom;
+
+#[derive(Debug, Error)]
+pub enum FixupError {
+    #[error(display = "{}", _0)]
+    Decode(#[error(cause)] DecodeError),
+    #[error(display = "{}", _0)]
+    Encode(#[error(cause)] EncodeError),
+    #[error(display = "ReadBlock")]
+    ReadBlock(#[error(cause)] ReadBlockError),
+    #[error(display = "Segment not found")]
+    SegmentNotFound,
+}
+
+impl From<DecodeError> for FixupError {
+    fn from(o: DecodeError) -> Self {
+        FixupError::Decode(o)
+    }
+}
+
+impl From<EncodeError> for FixupError {
+    fn from(o: EncodeError) -> Self {
+        FixupError::Encode(o)
+    }
+}
+
+impl From<ReadBlockError> for FixupError {
+    fn from(o: ReadBlockError) -> Self {
+        FixupError::ReadBlock(o)
+    }
+}
+
+/// Makes a live-stream webm (e.g. MediaRecorder output) seekable,
+/// like ts-ebml's `makeMetadataSeekable`.
+///
+/// * unknown-size master elements are converted to sized ones
+/// * `Duration` is computed from the last block timecode
+/// * `Cues` are generated from the keyframes (the first keyframe of each track in each cluster)
+/// * `SeekHead` pointing to the top-level elements is written at the head of the segment
+///
+/// Existing `SeekHead`, `Cues` and top-level `Void` elements are dropped.
+#[logfn(ok = "TRACE", err = "ERROR")]
+pub fn fixup<D: SchemaDict + ?Sized>(schema: &D, buf: Vec<u8>) -> Result<Vec<u8>, FixupError> {
+    let mut decoder = Decoder::new(schema);
+    let elms = decoder
+        .decode(buf)?
+        .into_iter()
+        .map(Into::into)
+        .collect::<Vec<ebml::Element>>();
+    let mut elms = close_open_masters(elms);
+
+    // split into EBML header, Segment and the rest
+    let seg_start = elms
+        .iter()
+        .position(|o| is_start(o, ids::SEGMENT))
+        .ok_or(FixupError::SegmentNotFound)?;
+    let seg_len = subtree_len(&elms[seg_start..]);
+    let tail = elms.split_off(seg_start + seg_len);
+    let mut body = elms.split_off(seg_start);
+    let head = elms;
+    body.remove(0);
+    body.pop();
+
+    let mut last_timecode = 0;
+    let mut infos = vec![];
+    let mut others = vec![];
+    let mut clusters = vec![];
+    // (cue time, track number, index of cluster)
+    let mut cue_points = vec![];
+    for subtree in split_subtrees(body) {
+        match subtree[0].ebml_id() {
+            ids::INFO => infos.push(subtree),
+            ids::CLUSTER => {
+                let scanned = scan_cluster(&subtree)?;
+                last_timecode = last_timecode.max(scanned.last_timecode);
+                for (time, track) in scanned.keyframes {
+                    cue_points.push((time, track, clusters.len()));
+                }
+                clusters.push(subtree);
+            }
+            ids::SEEK_HEAD | ids::CUES | ids::VOID => {}
+            _ => others.push(subtree),
+        }
+    }
+
+    // Info with the new Duration, in TimecodeScale units as the block timecodes
+    let infos = infos
+        .into_iter()
+        .map(|info| {
+            let mut info = info
+                .into_iter()
+                .filter(|o| o.ebml_id() != ids::DURATION)
+                .collect::<Vec<_>>();
+            let end = info.pop().unwrap();
+            info.push(
+                ebml::FloatElement {
+                    ebml_id: ids::DURATION,
+                    value: last_timecode as f64,
+                    width: ebml::FloatWidth::F64,
+                }
+                .into(),
+            );
+            info.push(end);
+            info
+        })
+        .collect::<Vec<_>>();
+
+    let mut pieces = vec![];
+    for subtree in infos.into_iter().chain(others.into_iter()) {
+        let id = subtree[0].ebml_id();
+        pieces.push((id, Encoder::new(schema).encode(subtree)?));
+    }
+    for subtree in clusters {
+        pieces.push((ids::CLUSTER, Encoder::new(schema).encode(subtree)?));
+    }
+
+    let mut buf = Encoder::new(schema).encode(head)?;
+    buf.append(&mut write_segment(schema, pieces, &cue_points)?);
+    buf.append(&mut Encoder::new(schema).encode(tail)?);
+    Ok(buf)
+}
+
+/// writes a sized Segment of the encoded top-level elements,
+/// with `SeekHead` at the head and `Cues` after the last element.
+/// `cue_points` are (cue time, track number, index of cluster among the clusters).
+pub(crate) fn write_segment<D: SchemaDict + ?Sized>(
+    schema: &D,
+    pieces: Vec<(ebml::EbmlId, Vec<u8>)>,
+    cue_points: &[(u64, u64, usize)],
+) -> Result<Vec<u8>, EncodeError> {
+    // SeekHead size depends on the positions, which depend on the SeekHead size
+    let mut seek_head = vec![];
+    let cues = loop {
+        let mut positions = vec![];
+        let mut cluster_positions = vec![];
+        let mut position = seek_head.len();
+        for (id, piece) in pieces.iter() {
+            positions.push(position);
+            if *id == ids::CLUSTER {
+                cluster_positions.push(position as u64);
+            }
+            position += piece.len();
+        }
+        let cues = if cue_points.is_empty() {
+            vec![]
+        } else {
+            Encoder::new(schema).encode(cues_elements(cue_points, &cluster_positions))?
+        };
+        let mut seeks = vec![];
+        for (i, (id, _)) in pieces.iter().enumerate() {
+            if *id != ids::CLUSTER && seeks.iter().all(|(o, _)| o != id) {
+                seeks.push((*id, positions[i] as u64));
+            }
+        }
+        if !cues.is_empty() {
+            seeks.push((ids::CUES, position as u64));
+        }
+        let new_seek_head = Encoder::new(schema).encode(seek_head_elements(seeks))?;
+        let stable = new_seek_head.len() == seek_head.len();
+        seek_head = new_seek_head;
+        if stable {
+            break cues;
+        }
+    };
+
+    let mut segment_body = seek_head;
+    for (_, mut piece) in pieces {
+        segment_body.append(&mut piece);
+    }
+    segment_body.extend(cues);
+
+    let mut buf: Vec<u8> = ids::SEGMENT.into();
+    let len = i64::try_from(segment_body.len()).map_err(EncodeTagError::from)?;
+    buf.append(&mut write_vint(len).map_err(EncodeTagError::from)?);
+    buf.append(&mut segment_body);
+    Ok(buf)
+}
+
+struct ScannedCluster {
+    last_timecode: u64,
+    // (cue time, track number)
+    keyframes: Vec<(u64, u64)>,
+}
+
+fn scan_cluster(cluster: &[ebml::Element]) -> Result<ScannedCluster, FixupError> {
+    let mut cluster_timecode = 0;
+    let mut last_timecode = 0;
+    let mut keyframes: Vec<(u64, u64)> = vec![];
+    // (Block, BlockDuration, has ReferenceBlock)
+    let mut group: Option<(Option<ebml::SimpleBlock>, u64, bool)> = None;
+    for elm in cluster {
+        let (block, duration, keyframe) = match elm {
+            ebml::Element::ChildElement(ebml::ChildElement::UnsignedIntegerElement(o)) => {
+                match (o.ebml_id, group.as_mut()) {
+                    (ids::TIMECODE, None) => cluster_timecode = o.value,
+                    (ids::BLOCK_DURATION, Some(group)) => group.1 = o.value,
+                    _ => {}
+                }
+                continue;
+            }
+            ebml::Element::ChildElement(ebml::ChildElement::IntegerElement(o)) => {
+                if let (ids::REFERENCE_BLOCK, Some(group)) = (o.ebml_id, group.as_mut()) {
+                    group.2 = true;
+                }
+                continue;
+            }
+            ebml::Element::ChildElement(ebml::ChildElement::BinaryElement(o)) => {
+                match (o.ebml_id, group.as_mut()) {
+                    (ids::SIMPLE_BLOCK, None) => {
+                        let block = read_block(&o.value)?;
+                        let keyframe = block.keyframe;
+                        (block, 0, keyframe)
+                    }
+                    (ids::BLOCK, Some(group)) => {
+                        group.0 = Some(read_block(&o.value)?);
+                        continue;
+                    }
+                    _ => continue,
+                }
+            }
+            ebml::Element::MasterElement(ebml::MasterElement::MasterStartElement(o))
+                if o.ebml_id == ids::BLOCK_GROUP =>
+            {
+                group = Some((None, 0, false));
+                continue;
+            }
+            ebml::Element::MasterElement(ebml::MasterElement::MasterEndElement(o))
+                if o.ebml_id == ids::BLOCK_GROUP =>
+            {
+                match group.take() {
+                    Some((Some(block), duration, reference)) => (block, duration, !reference),
+                    _ => continue,
+                }
+            }
+            _ => continue,
+        };
+        let timecode = i64::try_from(cluster_timecode)
+            .unwrap_or(i64::MAX)
+            .saturating_add(block.timecode);
+        let timecode = u64::try_from(timecode).unwrap_or(0);
+        last_timecode = last_timecode.max(timecode.saturating_add(duration));
+        let track = u64::try_from(block.track_number).unwrap_or(0);
+        if keyframe && keyframes.iter().all(|(_, o)| *o != track) {
+            keyframes.push((timecode, track));
+        }
+    }
+    Ok(ScannedCluster {
+        last_timecode,
+        keyframes,
+    })
+}
+
+/// `Cues` of (cue time, track number, index of cluster) with the positions of the clusters
+pub(crate) fn cues_elements(
+    cue_points: &[(u64, u64, usize)],
+    cluster_positions: &[u64],
+) -> Vec<ebml::Element> {
+    let mut elms = vec![ebml::MasterStartElement {
+        ebml_id: ids::CUES,
+        unknown_size: false,
+    }
+    .into()];
+    for &(time, track, cluster) in cue_points.iter() {
+        elms.append(&mut cue_point(time, track, cluster_positions[cluster]));
+    }
+    elms.push(ebml::MasterEndElement { ebml_id: ids::CUES }.into());
+    elms
+}
+
+fn cue_point(time: u64, track: u64, cluster_position: u64) -> Vec<ebml::Element> {
+    vec![
+        ebml::MasterStartElement {
+            ebml_id: ids::CUE_POINT,
+            unknown_size: false,
+        }
+        .into(),
+        ebml::UnsignedIntegerElement {
+            ebml_id: ids::CUE_TIME,
+            value: time,
+        }
+        .into(),
+        ebml::MasterStartElement {
+            ebml_id: ids::CUE_TRACK_POSITIONS,
+            unknown_size: false,
+        }
+        .into(),
+        ebml::UnsignedIntegerElement {
+            ebml_id: ids::CUE_TRACK,
+            value: track,
+        }
+        .into(),
+        ebml::UnsignedIntegerElement {
+            ebml_id: ids::CUE_CLUSTER_POSITION,
+            value: cluster_position,
+        }
+        .into(),
+        ebml::MasterEndElement {
+            ebml_id: ids::CUE_TRACK_POSITIONS,
+        }
+        .into(),
+        ebml::MasterEndElement {
+            ebml_id: ids::CUE_POINT,
+        }
+        .into(),
+    ]
+}
+
+pub(crate) fn seek_head_elements(seeks: Vec<(ebml::EbmlId, u64)>) -> Vec<ebml::Element> {
+    let mut elms = vec![ebml::MasterStartElement {
+        ebml_id: ids::SEEK_HEAD,
+        unknown_size: false,
+    }
+    .into()];
+    for (id, position) in seeks {
+        elms.push(
+            ebml::MasterStartElement {
+                ebml_id: ids::SEEK,
+                unknown_size: false,
+            }
+            .into(),
+        );
+        elms.push(
+            ebml::BinaryElement {
+                ebml_id: ids::SEEK_ID,
+                value: id.into(),
+            }
+            .into(),
+        );
+        elms.push(
+            ebml::UnsignedIntegerElement {
+                ebml_id: ids::SEEK_POSITION,
+                value: position,
+            }
+            .into(),
+        );
+        elms.push(ebml::MasterEndElement { ebml_id: ids::SEEK }.into());
+    }
+    elms.push(
+        ebml::MasterEndElement {
+            ebml_id: ids::SEEK_HEAD,
+        }
+        .into(),
+    );
+    elms
+}
+
+fn is_start(elm: &ebml::Element, ebml_id: ebml::EbmlId) -> bool {
+    match elm {
+        ebml::Element::MasterElement(ebml::MasterElement::MasterStartElement(o)) => {
+            o.ebml_id == ebml_id
+        }
+        _ => false,
+    }
+}
+
+/// number of elements of the subtree at the head of `elms`
+fn subtree_len(elms: &[ebml::Element]) -> usize {
+    let mut depth = 0;
+    for (i, elm) in elms.iter().enumerate() {
+        match elm {
+            ebml::Element::MasterElement(ebml::MasterElement::MasterStartElement(_)) => depth += 1,
+            ebml::Element::MasterElement(ebml::MasterElement::MasterEndElement(_)) => depth -= 1,
+            ebml::Element::ChildElement(_) => {}
+        }
+        if depth == 0 {
+            return i + 1;
+        }
+    }
+    elms.len()
+}
+
+fn split_subtrees(mut elms: Vec<ebml::Element>) -> Vec<Vec<ebml::Element>> {
+    let mut subtrees = vec![];
+    while !elms.is_empty() {
+        let rest = elms.split_off(subtree_len(&elms));
+        subtrees.push(elms);
+        elms = rest;
+    }
+    subtrees
+}
+
+/// closes the master elements left open at the end of stream
+/// and makes all master elements sized
+fn close_open_masters(elms: Vec<ebml::Element>) -> Vec<ebml::Element> {
+    let mut stack = vec![];
+    let mut elms = elms
+        .into_iter()
+        .map(|elm| match elm {
+            ebml::Element::MasterElement(ebml::MasterElement::MasterStartElement(o)) => {
+                stack.push(o.ebml_id);
+                ebml::MasterStartElement {
+                    ebml_id: o.ebml_id,
+                    unknown_size: false,
+                }
+                .into()
+            }
+            ebml::Element::MasterElement(ebml::MasterElement::MasterEndElement(o)) => {
+                stack.pop();
+                o.into()
+            }
+            o => o,
+        })
+        .collect::<Vec<_>>();
+    while let Some(ebml_id) = stack.pop() {
+        elms.push(ebml::MasterEndElement { ebml_id }.into());
+    }
+    elms
+}
@@ -0,0 +1,62 @@
+use ebml::block::{read_block, write_block};
+
+#[test]
+fn test_read_simple_block() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    // track 1, timecode -2, keyframe + invisible + discardable, no lacing
+    let buf = vec![0x81, 0xFF, 0xFE, 0b_1000_1001, 1, 2, 3];
+    let block = read_block(&buf).unwrap();
+    assert_eq!(block.track_number, 1);
+    assert_eq!(block.timecode, -2);
+    assert!(block.keyframe);
+    assert!(block.invisible);
+    assert!(block.discardable);
+    assert_eq!(block.frames, vec![vec![1, 2, 3]]);
+    // track numbers are vints
+    let buf = vec![0x40, 0x81, 0x00, 0x10, 0x00];
+    let block = read_block(&buf).unwrap();
+    assert_eq!(block.track_number, 129);
+    assert_eq!(block.timecode, 16);
+    assert!(!block.keyframe);
+    assert_eq!(block.frames, vec![Vec::<u8>::new()]);
+}
+
+#[test]
+fn test_read_laced_block() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    let frames = vec![vec![1; 3], vec![2; 5], vec![3; 2]];
+    // Xiph lacing
+    let mut buf = vec![0x81, 0x00, 0x00, 0b_1000_0010, 2, 3, 5];
+    buf.extend(frames.iter().flatten());
+    assert_eq!(read_block(&buf).unwrap().frames, frames);
+    // EBML lacing; the second size is a signed difference
+    let mut buf = vec![0x81, 0x00, 0x00, 0b_1000_0110, 2, 0x83, 0xC1];
+    buf.extend(frames.iter().flatten());
+    assert_eq!(read_block(&buf).unwrap().frames, frames);
+    // fixed-size lacing
+    let mut buf = vec![0x81, 0x00, 0x00, 0b_1000_0100, 2];
+    buf.extend(vec![7; 12]);
+    assert_eq!(read_block(&buf).unwrap().frames, vec![vec![7; 4]; 3]);
+    buf.push(0);
+    assert!(read_block(&buf).is_err());
+    // truncated
+    assert!(read_block(&[0x81, 0x00]).is_err());
+}
+
+#[test]
+fn test_write_block() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    let buf = vec![0x81, 0xFF, 0xFE, 0b_1000_1001, 1, 2, 3];
+    assert_eq!(write_block(&read_block(&buf).unwrap()).unwrap(), buf);
+    // multiple frames are Xiph-laced
+    let mut block = read_block(&buf).unwrap();
+    block.frames = vec![vec![0; 300], vec![1], vec![2, 2]];
+    let buf = write_block(&block).unwrap();
+    assert_eq!(&buf[3..7], &[0b_1000_1011, 2, 0xFF, 45]);
+    assert_eq!(read_block(&buf).unwrap(), block);
+    block.timecode = 0x8000;
+    assert!(write_block(&block).is_err());
+}
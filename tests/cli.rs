@@ -0,0 +1,332 @@
+use ebml::ebml::{BinaryElement, Element};
+use ebml::ids;
+use std::process::Command;
+
+mod common;
+use common::{end, start, uint};
+
+fn mkv() -> Vec<Element> {
+    vec![
+        start(ids::EBML),
+        end(ids::EBML),
+        start(ids::SEGMENT),
+        start(ids::INFO),
+        uint(ids::TIMECODE_SCALE, 1_000_000),
+        end(ids::INFO),
+        start(ids::CLUSTER),
+        uint(ids::TIMECODE, 0),
+        BinaryElement {
+            ebml_id: ids::SIMPLE_BLOCK,
+            value: (0..20).collect(),
+        }
+        .into(),
+        end(ids::CLUSTER),
+        end(ids::SEGMENT),
+    ]
+}
+
+/// writes the file into the temp dir and returns its path
+fn write_temp(name: &str, buf: &[u8]) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("ebml-cli-{}-{}", std::process::id(), name));
+    std::fs::write(&path, buf).unwrap();
+    path
+}
+
+fn ebml(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_ebml"))
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_dump() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    let schema = ebml::schema::DefaultSchema::default();
+    let buf = ebml::Encoder::new(&schema).encode(mkv()).unwrap();
+    let path = write_temp("dump.mkv", &buf);
+    let path = path.to_str().unwrap();
+    assert_eq!(
+        ebml(&["dump", path]),
+        [
+            "EBML [1A45DFA3] offset=0 header=5 size=0",
+            "Segment [18538067] offset=5 header=5 size=42",
+            "  Info [1549A966] offset=10 header=5 size=7",
+            "    TimecodeScale [2AD7B1] offset=15 header=4 size=3: 1000000",
+            "  Cluster [1F43B675] offset=22 header=5 size=25",
+            "    Timecode [E7] offset=27 header=2 size=1: 0",
+            "    SimpleBlock [A3] offset=30 header=2 size=20: 00 01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F ...",
+            "",
+        ]
+        .join("\n")
+    );
+    assert_eq!(
+        ebml(&[
+            "dump",
+            "--select",
+            "Cluster",
+            "--max-depth",
+            "1",
+            "--hide-binary",
+            path
+        ]),
+        ["  Cluster [1F43B675] offset=22 header=5 size=25", "",].join("\n")
+    );
+    assert_eq!(
+        ebml(&["dump", "--select", "SimpleBlock", "--hide-binary", path]),
+        "    SimpleBlock [A3] offset=30 header=2 size=20: <20 bytes>\n"
+    );
+
+    // truncated in the middle of SimpleBlock
+    let path = write_temp("truncated.mkv", &buf[..buf.len() - 5]);
+    let output = Command::new(env!("CARGO_BIN_EXE_ebml"))
+        .args(["dump", "--hide-binary", path.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .ends_with("    Timecode [E7] offset=27 header=2 size=1: 0\n"));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .starts_with("error: "));
+}
+
+#[test]
+fn test_json() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    let schema = ebml::schema::DefaultSchema::default();
+    let buf = ebml::Encoder::new(&schema).encode(mkv()).unwrap();
+    let path = write_temp("json.mkv", &buf);
+    let json = ebml(&["to-json", path.to_str().unwrap()]);
+    assert!(json.contains(r#""name": "TimecodeScale""#));
+    let json_path = write_temp("json.json", json.as_bytes());
+    let out_path = std::env::temp_dir().join(format!("ebml-cli-{}-json.out", std::process::id()));
+    ebml(&[
+        "from-json",
+        json_path.to_str().unwrap(),
+        "-o",
+        out_path.to_str().unwrap(),
+    ]);
+    assert_eq!(std::fs::read(&out_path).unwrap(), buf);
+}
+
+#[test]
+fn test_validate() {
+    use std::io::Write;
+    use std::process::Stdio;
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    let schema = ebml::schema::DefaultSchema::default();
+    // Info lacks MuxingApp and WritingApp
+    let buf = ebml::Encoder::new(&schema).encode(mkv()).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ebml"))
+        .args(["validate", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(&buf).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(!output.status.success());
+    let findings = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|o| serde_json::from_str::<ebml::validate::Finding>(o).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(findings.len(), 2);
+    assert_eq!(findings[0].kind, ebml::validate::FindingKind::Mandatory);
+    assert_eq!(findings[0].offset, 10);
+}
+
+fn string(ebml_id: ebml::ebml::EbmlId, value: &str) -> Element {
+    ebml::ebml::StringElement {
+        ebml_id,
+        value: value.as_bytes().to_vec(),
+    }
+    .into()
+}
+
+fn binary(ebml_id: ebml::ebml::EbmlId, value: Vec<u8>) -> Element {
+    BinaryElement { ebml_id, value }.into()
+}
+
+#[test]
+fn test_extract() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    let schema = ebml::schema::DefaultSchema::default();
+    let elms = vec![
+        start(ids::EBML),
+        end(ids::EBML),
+        start(ids::SEGMENT),
+        start(ids::TRACKS),
+        start(ids::TRACK_ENTRY),
+        uint(ids::TRACK_NUMBER, 1),
+        string(ids::CODEC_ID, "V_VP9"),
+        start(ids::VIDEO),
+        uint(ids::PIXEL_WIDTH, 320),
+        uint(ids::PIXEL_HEIGHT, 240),
+        end(ids::VIDEO),
+        end(ids::TRACK_ENTRY),
+        start(ids::TRACK_ENTRY),
+        uint(ids::TRACK_NUMBER, 2),
+        string(ids::CODEC_ID, "A_OPUS"),
+        binary(ids::CODEC_PRIVATE, b"OpusHead".to_vec()),
+        end(ids::TRACK_ENTRY),
+        end(ids::TRACKS),
+        start(ids::ATTACHMENTS),
+        start(ids::ATTACHED_FILE),
+        ebml::ebml::Utf8Element {
+            ebml_id: ids::FILE_NAME,
+            value: "../font.ttf".to_string(),
+        }
+        .into(),
+        string(ids::FILE_MIME_TYPE, "font/ttf"),
+        binary(ids::FILE_DATA, vec![1, 2, 3]),
+        uint(ids::FILE_UID, 1),
+        end(ids::ATTACHED_FILE),
+        end(ids::ATTACHMENTS),
+        start(ids::CLUSTER),
+        uint(ids::TIMECODE, 1000),
+        binary(ids::SIMPLE_BLOCK, vec![0x81, 0, 0, 0x80, 0xA0, 0xA1]),
+        binary(ids::SIMPLE_BLOCK, vec![0x82, 0, 0, 0x80, 0xB0]),
+        binary(ids::SIMPLE_BLOCK, vec![0x81, 0, 40, 0, 0xA2]),
+        binary(ids::SIMPLE_BLOCK, vec![0x82, 0, 20, 0x80, 0xB1]),
+        end(ids::CLUSTER),
+        end(ids::SEGMENT),
+    ];
+    let buf = ebml::Encoder::new(&schema).encode(elms).unwrap();
+    let path = write_temp("extract.mkv", &buf);
+    let dir = std::env::temp_dir().join(format!("ebml-cli-{}-extract", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let dir_str = dir.to_str().unwrap();
+    let out = ebml(&[
+        "extract",
+        path.to_str().unwrap(),
+        "-d",
+        dir_str,
+        "--track",
+        "1",
+        "--track",
+        "2",
+        "--codec-private",
+        "2",
+        "--attachments",
+    ]);
+    assert_eq!(out.lines().count(), 4);
+
+    let ivf = std::fs::read(dir.join("track1.ivf")).unwrap();
+    assert_eq!(&ivf[..4], b"DKIF");
+    assert_eq!(&ivf[8..12], b"VP90");
+    assert_eq!(&ivf[12..16], &[64, 1, 240, 0]);
+    // 2 frames
+    assert_eq!(&ivf[24..28], &[2, 0, 0, 0]);
+    assert_eq!(&ivf[32..36], &[2, 0, 0, 0]);
+    assert_eq!(&ivf[36..44], &1000u64.to_le_bytes());
+    assert_eq!(&ivf[44..46], &[0xA0, 0xA1]);
+    assert_eq!(&ivf[46..50], &[1, 0, 0, 0]);
+    assert_eq!(&ivf[50..58], &1040u64.to_le_bytes());
+    assert_eq!(&ivf[58..], &[0xA2]);
+
+    assert_eq!(
+        std::fs::read(dir.join("track2.raw")).unwrap(),
+        vec![0xB0, 0xB1]
+    );
+    assert_eq!(
+        std::fs::read(dir.join("track2.codec_private")).unwrap(),
+        b"OpusHead".to_vec()
+    );
+    assert_eq!(std::fs::read(dir.join("font.ttf")).unwrap(), vec![1, 2, 3]);
+
+    // --raw for VP9
+    ebml(&[
+        "extract",
+        path.to_str().unwrap(),
+        "-d",
+        dir_str,
+        "--track",
+        "1",
+        "--raw",
+    ]);
+    assert_eq!(
+        std::fs::read(dir.join("track1.raw")).unwrap(),
+        vec![0xA0, 0xA1, 0xA2]
+    );
+
+    // width too large for the IVF header
+    let elms = vec![
+        start(ids::EBML),
+        end(ids::EBML),
+        start(ids::SEGMENT),
+        start(ids::TRACKS),
+        start(ids::TRACK_ENTRY),
+        uint(ids::TRACK_NUMBER, 1),
+        string(ids::CODEC_ID, "V_VP9"),
+        start(ids::VIDEO),
+        uint(ids::PIXEL_WIDTH, 0x10000),
+        uint(ids::PIXEL_HEIGHT, 240),
+        end(ids::VIDEO),
+        end(ids::TRACK_ENTRY),
+        end(ids::TRACKS),
+        start(ids::CLUSTER),
+        uint(ids::TIMECODE, 0),
+        binary(ids::SIMPLE_BLOCK, vec![0x81, 0, 0, 0x80, 0xA0]),
+        end(ids::CLUSTER),
+        end(ids::SEGMENT),
+    ];
+    let buf = ebml::Encoder::new(&schema).encode(elms).unwrap();
+    let path = write_temp("extract_large.mkv", &buf);
+    let output = Command::new(env!("CARGO_BIN_EXE_ebml"))
+        .args([
+            "extract",
+            path.to_str().unwrap(),
+            "-d",
+            dir_str,
+            "--track",
+            "1",
+        ])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("65536x240 is too large for IVF"));
+}
+
+#[test]
+fn test_edit() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    let schema = ebml::schema::DefaultSchema::default();
+    let buf = ebml::Encoder::new(&schema).encode(mkv()).unwrap();
+    let path = write_temp("edit.mkv", &buf);
+    let path = path.to_str().unwrap();
+    let set = ["--set", "Segment/Info/TimecodeScale=1000"];
+    assert_eq!(
+        ebml(&["edit", path, set[0], set[1], "--dry-run"]),
+        "offset=15 length=7\n"
+    );
+    assert_eq!(std::fs::read(path).unwrap(), buf);
+    assert_eq!(
+        ebml(&["edit", path, set[0], set[1]]),
+        "offset=15 length=7\n"
+    );
+    let out = ebml(&["dump", path, "--select", "TimecodeScale"]);
+    assert!(out.contains("TimecodeScale [2AD7B1] offset=15 header=5 size=2: 1000"));
+    // no Void to use
+    let output = Command::new(env!("CARGO_BIN_EXE_ebml"))
+        .args([
+            "edit",
+            path,
+            "--set",
+            "Segment/Info/TimecodeScale=100000000000",
+        ])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("full rewrite is needed"));
+}
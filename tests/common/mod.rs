@@ -0,0 +1,27 @@
+// elements shared by the tests; each test uses only some of them
+#![allow(dead_code)]
+use ebml::ebml::{EbmlId, Element, MasterEndElement, MasterStartElement, UnsignedIntegerElement};
+
+pub fn start(ebml_id: EbmlId) -> Element {
+    MasterStartElement {
+        ebml_id,
+        unknown_size: false,
+    }
+    .into()
+}
+
+pub fn start_unknown(ebml_id: EbmlId) -> Element {
+    MasterStartElement {
+        ebml_id,
+        unknown_size: true,
+    }
+    .into()
+}
+
+pub fn end(ebml_id: EbmlId) -> Element {
+    MasterEndElement { ebml_id }.into()
+}
+
+pub fn uint(ebml_id: EbmlId, value: u64) -> Element {
+    UnsignedIntegerElement { ebml_id, value }.into()
+}
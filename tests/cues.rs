@@ -0,0 +1,186 @@
+use ebml::cues::{Cues, CuesError};
+use ebml::ebml::{BinaryElement, Element, ElementDetail, MasterElement};
+use ebml::ids;
+use std::io::Cursor;
+
+mod common;
+use common::{end, start, uint};
+
+fn simple_block(track: u8, timecode: i16, keyframe: bool) -> Element {
+    let mut value = vec![0b_1000_0000 | track];
+    value.extend_from_slice(&timecode.to_be_bytes());
+    value.push(if keyframe { 0b_1000_0000 } else { 0 });
+    BinaryElement {
+        ebml_id: ids::SIMPLE_BLOCK,
+        value,
+    }
+    .into()
+}
+
+/// Segment with 2 tracks and 3 clusters of 1 second, without SeekHead and Cues
+fn webm() -> Vec<Element> {
+    let mut elms = vec![
+        start(ids::EBML),
+        end(ids::EBML),
+        start(ids::SEGMENT),
+        start(ids::INFO),
+        uint(ids::TIMECODE_SCALE, 1_000_000),
+        end(ids::INFO),
+        start(ids::TRACKS),
+        start(ids::TRACK_ENTRY),
+        uint(ids::TRACK_NUMBER, 1),
+        end(ids::TRACK_ENTRY),
+        start(ids::TRACK_ENTRY),
+        uint(ids::TRACK_NUMBER, 2),
+        end(ids::TRACK_ENTRY),
+        end(ids::TRACKS),
+    ];
+    for cluster in 0..3 {
+        elms.push(start(ids::CLUSTER));
+        elms.push(uint(ids::TIMECODE, cluster * 1000));
+        for i in 0..10 {
+            elms.push(simple_block(1, i * 100, i == 0));
+            elms.push(simple_block(2, i * 100 + 50, true));
+        }
+        elms.push(end(ids::CLUSTER));
+    }
+    elms.push(end(ids::SEGMENT));
+    elms
+}
+
+fn next_element(reader: &mut Cursor<Vec<u8>>) -> ElementDetail {
+    let schema = ebml::schema::DefaultSchema::default();
+    let mut decoder = ebml::Decoder::new(&schema);
+    let pos = reader.position() as usize;
+    let buf = reader.get_ref()[pos..pos + 16].to_vec();
+    decoder.decode(buf).unwrap().remove(0)
+}
+
+#[test]
+fn test_cues_seek() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    let schema = ebml::schema::DefaultSchema::default();
+    let buf = ebml::Encoder::new(&schema).encode(webm()).unwrap();
+    let buf = ebml::webm::fixup(&schema, buf).unwrap();
+    let mut reader = Cursor::new(buf);
+    let cues = Cues::read(&mut reader, &schema).unwrap();
+    assert_eq!(cues.timecode_scale, 1_000_000);
+    assert_eq!(
+        cues.points.iter().map(|o| o.time).collect::<Vec<_>>(),
+        vec![0, 50, 1000, 1050, 2000, 2050]
+    );
+    assert!(cues.points.iter().all(|o| o.positions.len() == 1));
+
+    // 1.5 sec is in the second cluster
+    let point = cues.seek(&mut reader, 1, 1_500_000_000).unwrap().unwrap();
+    assert_eq!(point.time, 1000);
+    let position = reader.position();
+    match next_element(&mut reader) {
+        ElementDetail::MasterElement((MasterElement::MasterStartElement(o), _)) => {
+            assert_eq!(o.ebml_id, ids::CLUSTER)
+        }
+        o => panic!("{:?}", o),
+    }
+    // cue of the other track points to the same cluster
+    let (_, pos) = cues.find(2, 1_999_999_999).unwrap();
+    assert_eq!(pos.cluster_position, position);
+    assert_eq!(cues.find(1, 2_000_000_000).unwrap().0.time, 2000);
+    assert!(cues.find(3, 0).is_none());
+}
+
+#[test]
+fn test_cues_without_seek_head() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    let schema = ebml::schema::DefaultSchema::default();
+    let mut elms = webm();
+    let segment_end = elms.pop().unwrap();
+    // Cues after the clusters; the first cluster begins after Info and Tracks
+    let info_tracks = ebml::Encoder::new(&schema)
+        .encode(elms[3..14].to_vec())
+        .unwrap();
+    elms.extend(vec![
+        start(ids::CUES),
+        start(ids::CUE_POINT),
+        uint(ids::CUE_TIME, 0),
+        start(ids::CUE_TRACK_POSITIONS),
+        uint(ids::CUE_TRACK, 1),
+        uint(ids::CUE_CLUSTER_POSITION, info_tracks.len() as u64),
+        end(ids::CUE_TRACK_POSITIONS),
+        end(ids::CUE_POINT),
+        end(ids::CUES),
+        segment_end,
+    ]);
+    let buf = ebml::Encoder::new(&schema).encode(elms).unwrap();
+    let mut reader = Cursor::new(buf);
+    let cues = Cues::read(&mut reader, &schema).unwrap();
+    assert_eq!(cues.points.len(), 1);
+    cues.seek(&mut reader, 1, 0).unwrap().unwrap();
+    match next_element(&mut reader) {
+        ElementDetail::MasterElement((MasterElement::MasterStartElement(o), _)) => {
+            assert_eq!(o.ebml_id, ids::CLUSTER)
+        }
+        o => panic!("{:?}", o),
+    }
+}
+
+#[test]
+fn test_cues_without_info_in_seek_head() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    let schema = ebml::schema::DefaultSchema::default();
+    let mut encoder = ebml::Encoder::new(&schema);
+    let mut elms = webm();
+    elms[4] = uint(ids::TIMECODE_SCALE, 500_000);
+    let segment_end = elms.pop().unwrap();
+    let body = elms.split_off(3);
+    let body_len = encoder.encode(body.clone()).unwrap().len() as u64;
+    // SeekHead only knows the Cues after the clusters
+    let seek_head = |position: u64| {
+        vec![
+            start(ids::SEEK_HEAD),
+            start(ids::SEEK),
+            BinaryElement {
+                ebml_id: ids::SEEK_ID,
+                value: ids::CUES.0.to_be_bytes()[4..].to_vec(),
+            }
+            .into(),
+            uint(ids::SEEK_POSITION, position),
+            end(ids::SEEK),
+            end(ids::SEEK_HEAD),
+        ]
+    };
+    let seek_head_len = encoder.encode(seek_head(body_len)).unwrap().len() as u64;
+    elms.extend(seek_head(seek_head_len + body_len));
+    elms.extend(body);
+    elms.extend(vec![
+        start(ids::CUES),
+        start(ids::CUE_POINT),
+        uint(ids::CUE_TIME, 4),
+        start(ids::CUE_TRACK_POSITIONS),
+        uint(ids::CUE_TRACK, 1),
+        uint(ids::CUE_CLUSTER_POSITION, seek_head_len),
+        end(ids::CUE_TRACK_POSITIONS),
+        end(ids::CUE_POINT),
+        end(ids::CUES),
+        segment_end,
+    ]);
+    let buf = encoder.encode(elms).unwrap();
+    let cues = Cues::read(&mut Cursor::new(buf), &schema).unwrap();
+    // TimecodeScale of the Info found by scanning
+    assert_eq!(cues.timecode_scale, 500_000);
+    assert_eq!(cues.find(1, 2_000_000).unwrap().0.time, 4);
+    assert!(cues.find(1, 1_999_999).is_none());
+
+    // Cues without Info
+    let mut elms = webm();
+    elms.drain(3..6);
+    let segment_end = elms.pop().unwrap();
+    elms.extend(vec![start(ids::CUES), end(ids::CUES), segment_end]);
+    let buf = encoder.encode(elms).unwrap();
+    match Cues::read(&mut Cursor::new(buf), &schema) {
+        Err(CuesError::InfoNotFound) => {}
+        o => panic!("{:?}", o),
+    }
+}
@@ -1,6 +1,9 @@
 use insta::assert_debug_snapshot_matches;
 use log::{debug, info};
 
+mod common;
+use common::{end, start, start_unknown, uint};
+
 const WEBM_FILE_LIST: &'static [&'static str] = &[
     "./matroska-test-files/test_files/test1.mkv",
     "./matroska-test-files/test_files/test2.mkv",
@@ -40,3 +43,612 @@ fn test_decoder() {
         // assert_eq!(elms.len(), 2766);
     }
 }
+
+/// every element starts where the previous one ended and its header is where the position says
+fn assert_positions(buf: &[u8]) {
+    use ebml::decoder::ReadEbmlExt;
+    use ebml::ebml::{ElementDetail, MasterElement};
+    let schema = ebml::schema::DefaultSchema::default();
+    let elms = ebml::Decoder::new(&schema).decode(buf.to_vec()).unwrap();
+    // offsets are absolute across chunks
+    for chunk_size in 1..8 {
+        let mut decoder = ebml::Decoder::new(&schema);
+        let mut elms2 = vec![];
+        for chunk in buf.chunks(chunk_size) {
+            elms2.append(&mut decoder.decode(chunk.to_vec()).unwrap());
+        }
+        assert_eq!(elms, elms2);
+    }
+    let mut cursor = 0;
+    for elm in elms {
+        let (pos, is_end) = match elm {
+            ElementDetail::MasterElement((MasterElement::MasterEndElement(_), pos)) => (pos, true),
+            ElementDetail::MasterElement((_, pos)) => (pos, false),
+            ElementDetail::ChildElement((_, pos)) => (pos, false),
+        };
+        assert_eq!(pos.size_start, pos.tag_start + pos.tag_length);
+        assert_eq!(pos.content_start, pos.size_start + pos.size_length);
+        if pos.content_size >= 0 {
+            assert_eq!(pos.content_end, pos.content_start as i64 + pos.content_size);
+        }
+        if is_end {
+            assert_eq!(pos.content_end, cursor as i64, "{:?}", pos);
+            continue;
+        }
+        assert_eq!(pos.tag_start, cursor, "{:?}", pos);
+        let header = std::io::Cursor::new(&buf[pos.tag_start..])
+            .read_element_header()
+            .unwrap();
+        assert_eq!(header.ebml_id, pos.ebml_id);
+        assert_eq!(header.header_size, pos.tag_length + pos.size_length);
+        assert_eq!(header.content_size, pos.content_size);
+        cursor = if pos.r#type == 'm' {
+            pos.content_start
+        } else {
+            pos.content_end as usize
+        };
+    }
+}
+
+#[test]
+fn test_positions() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    use ebml::ebml::{BinaryElement, Element};
+    use ebml::ids;
+    let block = |value: u8| -> Element {
+        BinaryElement {
+            ebml_id: ids::SIMPLE_BLOCK,
+            value: vec![0x81, 0, value, 0x80, value],
+        }
+        .into()
+    };
+    let schema = ebml::schema::DefaultSchema::default();
+    // 1 to 4 byte IDs, long size fields and unknown-size masters closed by the next element
+    let buf = ebml::Encoder::new(&schema)
+        .encode(vec![
+            start(ids::EBML),
+            uint(ids::EBML_VERSION, 1),
+            end(ids::EBML),
+            start_unknown(ids::SEGMENT),
+            start(ids::INFO),
+            uint(ids::TIMECODE_SCALE, 1_000_000),
+            end(ids::INFO),
+            start_unknown(ids::CLUSTER),
+            uint(ids::TIMECODE, 0),
+            block(0),
+            BinaryElement {
+                ebml_id: ids::VOID,
+                value: vec![0; 300],
+            }
+            .into(),
+            block(1),
+            start_unknown(ids::CLUSTER),
+            uint(ids::TIMECODE, 1000),
+            block(2),
+            start(ids::CUES),
+            start(ids::CUE_POINT),
+            uint(ids::CUE_TIME, 0),
+            end(ids::CUE_POINT),
+            end(ids::CUES),
+        ])
+        .unwrap();
+    assert_positions(&buf);
+
+    for path in WEBM_FILE_LIST {
+        info!("positions: {}", path);
+        assert_positions(&std::fs::read(path).unwrap());
+    }
+}
+
+#[test]
+fn test_finish() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    use ebml::decoder::DecodeError;
+    use ebml::ebml::{Element, ElementDetail, MasterElement, Utf8Element};
+    use ebml::ids;
+    let schema = ebml::schema::DefaultSchema::default();
+    let elms = vec![
+        start_unknown(ids::SEGMENT),
+        start_unknown(ids::CLUSTER),
+        uint(ids::TIMECODE, 0),
+        end(ids::CLUSTER),
+        end(ids::SEGMENT),
+    ];
+    let buf = ebml::Encoder::new(&schema).encode(elms.clone()).unwrap();
+    let mut decoder = ebml::Decoder::new(&schema);
+    let mut decoded = decoder.decode(buf.clone()).unwrap();
+    assert_eq!(decoded.len(), 3);
+    // the unknown-size masters are closed at the end of data
+    let rest = decoder.finish().unwrap();
+    for elm in &rest {
+        match elm {
+            ElementDetail::MasterElement((MasterElement::MasterEndElement(_), pos)) => {
+                assert_eq!(pos.content_end, buf.len() as i64);
+            }
+            o => panic!("{:?}", o),
+        }
+    }
+    decoded.extend(rest);
+    assert_eq!(
+        decoded
+            .into_iter()
+            .map(Into::into)
+            .collect::<Vec<Element>>(),
+        elms
+    );
+
+    // the decoder is reusable after finish
+    let buf = ebml::Encoder::new(&schema)
+        .encode(vec![
+            start(ids::SEGMENT),
+            start(ids::INFO),
+            uint(ids::TIMECODE_SCALE, 1_000_000),
+            end(ids::INFO),
+            end(ids::SEGMENT),
+        ])
+        .unwrap();
+    assert_eq!(decoder.decode(buf.clone()).unwrap().len(), 5);
+    assert_eq!(decoder.finish().unwrap(), vec![]);
+
+    // Segment 5 bytes, Info 5 bytes, TimecodeScale 3 + 3 bytes
+    let cases = vec![
+        // in the middle of a content
+        (
+            buf.len() - 1,
+            10,
+            Some("TimecodeScale"),
+            vec!["Segment", "Info"],
+        ),
+        // in the middle of a tag
+        (11, 10, None, vec!["Segment", "Info"]),
+        // between the elements of a sized master
+        (10, 5, Some("Info"), vec!["Segment"]),
+    ];
+    for (len, offset, name, path) in cases {
+        decoder.decode(buf[..len].to_vec()).unwrap();
+        let err = decoder.finish().unwrap_err();
+        match err.kind() {
+            DecodeError::UnexpectedEnd => {}
+            o => panic!("{:?}", o),
+        }
+        let context = err.context().unwrap();
+        assert_eq!(context.offset, offset);
+        assert_eq!(context.name.as_deref(), name);
+        assert_eq!(context.path, path);
+        // reset even after the error
+        assert_eq!(decoder.decode(buf.clone()).unwrap().len(), 5);
+        decoder.finish().unwrap();
+    }
+
+    // sized master which started long before the buffered data
+    let info = ebml::Encoder::new(&schema)
+        .encode(vec![
+            start(ids::INFO),
+            Utf8Element {
+                ebml_id: ids::TITLE,
+                value: "x".repeat(40),
+            }
+            .into(),
+            end(ids::INFO),
+        ])
+        .unwrap();
+    assert_eq!(info.len(), 48);
+    let mut buf: Vec<u8> = ids::SEGMENT.into();
+    // 0x1000 bytes
+    buf.extend_from_slice(&[0x50, 0x00]);
+    buf.extend(info);
+    assert_eq!(decoder.decode(buf.clone()).unwrap().len(), 4);
+    let err = decoder.finish().unwrap_err();
+    match err.kind() {
+        DecodeError::UnexpectedEnd => {}
+        o => panic!("{:?}", o),
+    }
+    let context = err.context().unwrap();
+    assert_eq!(context.offset, 0);
+    assert_eq!(context.name.as_deref(), Some("Segment"));
+    assert_eq!(context.excerpt_start, buf.len());
+    assert!(context.excerpt.is_empty());
+}
+
+#[test]
+fn test_checkpoint() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    use ebml::decoder::DecoderCheckpoint;
+    use ebml::ebml::{BinaryElement, Utf8Element};
+    use ebml::ids;
+    let schema = ebml::schema::DefaultSchema::default();
+    let buf = ebml::Encoder::new(&schema)
+        .encode(vec![
+            start(ids::EBML),
+            uint(ids::EBML_VERSION, 1),
+            end(ids::EBML),
+            start_unknown(ids::SEGMENT),
+            start(ids::INFO),
+            Utf8Element {
+                ebml_id: ids::TITLE,
+                value: "title".to_string(),
+            }
+            .into(),
+            end(ids::INFO),
+            start_unknown(ids::CLUSTER),
+            uint(ids::TIMECODE, 0),
+            BinaryElement {
+                ebml_id: ids::SIMPLE_BLOCK,
+                value: vec![0x81, 0, 0, 0x80, 0],
+            }
+            .into(),
+        ])
+        .unwrap();
+    let mut decoder = ebml::Decoder::new(&schema);
+    let mut expected = decoder.decode(buf.clone()).unwrap();
+    expected.extend(decoder.finish().unwrap());
+    // stop at every byte and resume with another decoder
+    for i in 0..=buf.len() {
+        let mut decoder = ebml::Decoder::new(&schema);
+        let mut elms = decoder.decode(buf[..i].to_vec()).unwrap();
+        let json = serde_json::to_string(&decoder.checkpoint()).unwrap();
+        drop(decoder);
+        let checkpoint: DecoderCheckpoint = serde_json::from_str(&json).unwrap();
+        assert_eq!(checkpoint.offset(), i);
+        let mut decoder = ebml::Decoder::new(&schema);
+        decoder.restore(checkpoint).unwrap();
+        elms.extend(decoder.decode(buf[i..].to_vec()).unwrap());
+        elms.extend(decoder.finish().unwrap());
+        assert_eq!(elms, expected, "{}", i);
+    }
+}
+
+#[test]
+fn test_with_ancestors() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    use ebml::decoder::{DecodeError, DecoderOptions};
+    use ebml::ebml::{BinaryElement, ElementDetail, ElementPosition, MasterElement};
+    use ebml::ids;
+    let master = |ebml_id, unknown_size| {
+        if unknown_size {
+            start_unknown(ebml_id)
+        } else {
+            start(ebml_id)
+        }
+    };
+    let cluster = |timecode, unknown_size| {
+        vec![
+            master(ids::CLUSTER, unknown_size),
+            uint(ids::TIMECODE, timecode),
+            BinaryElement {
+                ebml_id: ids::SIMPLE_BLOCK,
+                value: vec![0x81, 0, 0, 0x80, 0],
+            }
+            .into(),
+            end(ids::CLUSTER),
+        ]
+    };
+    let schema = ebml::schema::DefaultSchema::default();
+    for unknown_size in &[true, false] {
+        let mut elms = vec![master(ids::SEGMENT, *unknown_size)];
+        elms.extend(cluster(0, *unknown_size));
+        elms.extend(cluster(1000, *unknown_size));
+        elms.push(end(ids::SEGMENT));
+        let buf = ebml::Encoder::new(&schema).encode(elms).unwrap();
+        let mut decoder = ebml::Decoder::new(&schema);
+        let mut expected = decoder.decode(buf.clone()).unwrap();
+        expected.extend(decoder.finish().unwrap());
+        let position = |i: usize| match &expected[i] {
+            ElementDetail::MasterElement((MasterElement::MasterStartElement(_), pos)) => *pos,
+            o => panic!("{:?}", o),
+        };
+        // the Segment start has the same position wherever it is known from
+        let segment = position(0);
+        let offset = position(5).tag_start;
+        // decode the second Cluster only
+        let mut decoder = ebml::Decoder::with_ancestors(
+            &schema,
+            DecoderOptions::default(),
+            offset,
+            vec![segment],
+        )
+        .unwrap();
+        let mut elms = decoder.decode(buf[offset..].to_vec()).unwrap();
+        elms.extend(decoder.finish().unwrap());
+        assert_eq!(elms, &expected[5..]);
+    }
+
+    // only the position is needed
+    let ancestor = |ebml_id| ElementPosition {
+        ebml_id,
+        level: 0,
+        r#type: '?',
+        tag_start: 0,
+        size_start: 4,
+        content_start: 12,
+        content_size: 100,
+        tag_length: 4,
+        size_length: 8,
+        content_end: 112,
+    };
+    let decoder = ebml::Decoder::with_ancestors(
+        &schema,
+        DecoderOptions::default(),
+        12,
+        vec![ancestor(ids::SEGMENT)],
+    );
+    assert!(decoder.is_ok());
+    let invalid = vec![
+        // not a master
+        (12, ancestor(ids::TIMECODE)),
+        // out of the ancestor
+        (113, ancestor(ids::SEGMENT)),
+        (11, ancestor(ids::SEGMENT)),
+    ];
+    for (offset, pos) in invalid {
+        match ebml::Decoder::with_ancestors(&schema, DecoderOptions::default(), offset, vec![pos]) {
+            Err(DecodeError::InvalidAncestor(_)) => {}
+            Err(err) => panic!("{:?}", err),
+            Ok(_) => panic!("{}", offset),
+        }
+    }
+}
+
+#[test]
+fn test_filter() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    use ebml::decoder::{DecoderOptions, ElementFilter, ElementSelector};
+    use ebml::ebml::{BinaryElement, Element, ElementDetail, Utf8Element};
+    use ebml::ids;
+    let block = |value: u8| -> Element {
+        BinaryElement {
+            ebml_id: ids::SIMPLE_BLOCK,
+            value: vec![0x81, 0, value, 0x80, value],
+        }
+        .into()
+    };
+    let title: Element = Utf8Element {
+        ebml_id: ids::TITLE,
+        value: "title".to_string(),
+    }
+    .into();
+    let schema = ebml::schema::DefaultSchema::default();
+    let buf = ebml::Encoder::new(&schema)
+        .encode(vec![
+            start_unknown(ids::SEGMENT),
+            start(ids::INFO),
+            title.clone(),
+            uint(ids::TIMECODE_SCALE, 1_000_000),
+            end(ids::INFO),
+            start(ids::TRACKS),
+            start(ids::TRACK_ENTRY),
+            uint(ids::TRACK_NUMBER, 1),
+            end(ids::TRACK_ENTRY),
+            end(ids::TRACKS),
+            start_unknown(ids::CLUSTER),
+            uint(ids::TIMECODE, 0),
+            block(0),
+            start(ids::CLUSTER),
+            uint(ids::TIMECODE, 1000),
+            block(1),
+            end(ids::CLUSTER),
+        ])
+        .unwrap();
+    let mut decoder = ebml::Decoder::new(&schema);
+    let mut all = decoder.decode(buf.clone()).unwrap();
+    all.extend(decoder.finish().unwrap());
+    let id = ElementSelector::Id;
+    let path = |o: &str| ElementSelector::Path(o.to_string());
+    let cases = vec![
+        (
+            vec![],
+            vec![id(ids::CLUSTER)],
+            vec![
+                start_unknown(ids::SEGMENT),
+                start(ids::INFO),
+                title.clone(),
+                uint(ids::TIMECODE_SCALE, 1_000_000),
+                end(ids::INFO),
+                start(ids::TRACKS),
+                start(ids::TRACK_ENTRY),
+                uint(ids::TRACK_NUMBER, 1),
+                end(ids::TRACK_ENTRY),
+                end(ids::TRACKS),
+                end(ids::SEGMENT),
+            ],
+        ),
+        (
+            vec![path("Segment/Info")],
+            vec![],
+            vec![
+                start(ids::INFO),
+                title.clone(),
+                uint(ids::TIMECODE_SCALE, 1_000_000),
+                end(ids::INFO),
+            ],
+        ),
+        (
+            vec![id(ids::SIMPLE_BLOCK)],
+            vec![],
+            vec![block(0), block(1)],
+        ),
+        (
+            vec![id(ids::TRACKS), path("Segment/Info/Title")],
+            vec![path("Segment/Tracks/TrackEntry/TrackNumber")],
+            vec![
+                title.clone(),
+                start(ids::TRACKS),
+                start(ids::TRACK_ENTRY),
+                end(ids::TRACK_ENTRY),
+                end(ids::TRACKS),
+            ],
+        ),
+    ];
+    for (include, exclude, expected) in cases {
+        let options = DecoderOptions {
+            filter: ElementFilter { include, exclude },
+            ..Default::default()
+        };
+        // at once and byte by byte
+        for chunk_size in &[buf.len(), 1] {
+            let mut decoder = ebml::Decoder::with_options(&schema, options.clone());
+            let mut elms: Vec<ElementDetail> = vec![];
+            for chunk in buf.chunks(*chunk_size) {
+                elms.extend(decoder.decode(chunk.to_vec()).unwrap());
+            }
+            elms.extend(decoder.finish().unwrap());
+            // with the same positions
+            for elm in &elms {
+                assert!(all.contains(elm), "{:?}", elm);
+            }
+            assert_eq!(
+                elms.into_iter().map(Into::into).collect::<Vec<Element>>(),
+                expected,
+                "{:?}",
+                options.filter
+            );
+        }
+    }
+}
+
+#[test]
+fn test_registry() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    use ebml::decoder::{DecodeError, DecoderOptions};
+    use ebml::ebml::{Element, StringElement, UnsignedIntegerElement};
+    use ebml::ids;
+    use ebml::schema::{DefaultSchema, SchemaRegistry, SharedSchema};
+    use std::sync::Arc;
+    let header = |doc_type: Option<&str>| {
+        let mut elms = vec![start(ids::EBML)];
+        if let Some(doc_type) = doc_type {
+            elms.push(
+                StringElement {
+                    ebml_id: ids::DOC_TYPE,
+                    value: doc_type.as_bytes().to_vec(),
+                }
+                .into(),
+            );
+        }
+        elms.push(end(ids::EBML));
+        elms
+    };
+    // 0x4001 is not in schema.json
+    let custom: SharedSchema = Arc::new(
+        serde_json::from_str::<DefaultSchema>(
+            r#"{ "16385": { "name": "Foo", "type": "u", "level": 0, "description": "" } }"#,
+        )
+        .unwrap(),
+    );
+    let foo: Element = UnsignedIntegerElement {
+        ebml_id: 0x4001.into(),
+        value: 1,
+    }
+    .into();
+    let mut registry = SchemaRegistry::default();
+    registry.register("custom", custom);
+    let decode = |registry: &SchemaRegistry,
+                  buf: &[u8]|
+     -> Result<(Vec<Element>, Option<String>), DecodeError> {
+        let mut decoder = ebml::Decoder::with_registry(registry.clone(), DecoderOptions::default());
+        let mut elms = vec![];
+        // byte by byte, switching schema in the middle of the stream
+        for byte in buf {
+            elms.extend(decoder.decode(vec![*byte])?);
+        }
+        let doc_type = decoder.doc_type().map(ToString::to_string);
+        elms.extend(decoder.finish()?);
+        Ok((elms.into_iter().map(Into::into).collect(), doc_type))
+    };
+
+    // the EBML header elements are available with the custom schema
+    let mut elms = header(Some("custom"));
+    elms.push(foo.clone());
+    let custom_buf = ebml::Encoder::new(registry.get("custom").unwrap())
+        .encode(elms.clone())
+        .unwrap();
+    assert_eq!(
+        decode(&registry, &custom_buf).unwrap(),
+        (elms.clone(), Some("custom".to_string()))
+    );
+
+    let schema = DefaultSchema::default();
+    for doc_type in &[Some("webm"), None] {
+        let mut elms = header(*doc_type);
+        elms.extend(vec![start(ids::SEGMENT), end(ids::SEGMENT)]);
+        let buf = ebml::Encoder::new(&schema).encode(elms.clone()).unwrap();
+        // matroska by default
+        let expected = doc_type.unwrap_or("matroska").to_string();
+        assert_eq!(decode(&registry, &buf).unwrap(), (elms, Some(expected)));
+    }
+
+    // no default DocType
+    let mut no_doc_type = header(None);
+    no_doc_type.extend(vec![start(ids::SEGMENT), end(ids::SEGMENT)]);
+    let buf = ebml::Encoder::new(&schema)
+        .encode(no_doc_type.clone())
+        .unwrap();
+    let mut no_default = SchemaRegistry::empty();
+    no_default.register("webm", Arc::new(DefaultSchema::default()));
+    match decode(&no_default, &buf).unwrap_err().kind() {
+        DecodeError::MissingDocType => (),
+        o => panic!("{:?}", o),
+    }
+    no_default.set_default_doc_type(Some("webm".to_string()));
+    assert_eq!(
+        decode(&no_default, &buf).unwrap(),
+        (no_doc_type, Some("webm".to_string()))
+    );
+
+    // the schema is selected again on restore
+    let i = custom_buf.len() - 2;
+    let mut decoder = ebml::Decoder::with_registry(registry.clone(), DecoderOptions::default());
+    let mut decoded = decoder.decode(custom_buf[..i].to_vec()).unwrap();
+    let checkpoint = decoder.checkpoint();
+    let mut decoder = ebml::Decoder::with_registry(registry.clone(), DecoderOptions::default());
+    decoder.restore(checkpoint).unwrap();
+    decoded.extend(decoder.decode(custom_buf[i..].to_vec()).unwrap());
+    assert_eq!(
+        decoded
+            .into_iter()
+            .map(Into::into)
+            .collect::<Vec<Element>>(),
+        elms
+    );
+
+    // paths are resolved by the schema for the DocType
+    let options = DecoderOptions {
+        filter: ebml::decoder::ElementFilter {
+            include: vec![ebml::decoder::ElementSelector::Path("Foo".to_string())],
+            exclude: vec![],
+        },
+        ..DecoderOptions::default()
+    };
+    let mut decoder = ebml::Decoder::with_registry(registry.clone(), options);
+    let mut decoded = decoder.decode(custom_buf.clone()).unwrap();
+    decoded.extend(decoder.finish().unwrap());
+    assert_eq!(
+        decoded
+            .into_iter()
+            .map(Into::into)
+            .collect::<Vec<Element>>(),
+        vec![foo.clone()]
+    );
+
+    // unknown DocType
+    let registry = SchemaRegistry::default();
+    let err = decode(&registry, &custom_buf).unwrap_err();
+    match err.kind() {
+        DecodeError::UnknownDocType(doc_type) => assert_eq!(doc_type, "custom"),
+        o => panic!("{:?}", o),
+    }
+    assert_eq!(err.context().unwrap().name.as_deref(), Some("DocType"));
+    let mut registry = SchemaRegistry::empty();
+    registry.set_fallback(Some(Arc::new(DefaultSchema::default())));
+    match decode(&registry, &custom_buf).unwrap_err().kind() {
+        // Foo is not in the fallback schema
+        DecodeError::UnknownEbmlId(ebml_id) => assert_eq!(*ebml_id, 0x4001.into()),
+        o => panic!("{:?}", o),
+    }
+}
@@ -0,0 +1,278 @@
+use ebml::ebml::{BinaryElement, Element, StringElement, UnsignedIntegerElement, Utf8Element};
+use ebml::edit::{edit_in_place, plan, Edit, EditError};
+use ebml::ids;
+use ebml::schema::DefaultSchema;
+use std::io::Cursor;
+
+mod common;
+use common::{end, start};
+
+fn title(value: &str) -> Element {
+    Utf8Element {
+        ebml_id: ids::TITLE,
+        value: value.to_string(),
+    }
+    .into()
+}
+
+fn void(content_len: usize) -> Element {
+    BinaryElement {
+        ebml_id: ids::VOID,
+        value: vec![0; content_len],
+    }
+    .into()
+}
+
+fn track(number: u64, language: &str) -> Vec<Element> {
+    vec![
+        start(ids::TRACK_ENTRY),
+        UnsignedIntegerElement {
+            ebml_id: ids::TRACK_NUMBER,
+            value: number,
+        }
+        .into(),
+        StringElement {
+            ebml_id: ids::LANGUAGE,
+            value: language.as_bytes().to_vec(),
+        }
+        .into(),
+        end(ids::TRACK_ENTRY),
+    ]
+}
+
+fn mkv(info: Vec<Element>) -> Vec<Element> {
+    let mut elms = vec![start(ids::EBML), end(ids::EBML), start(ids::SEGMENT)];
+    elms.push(start(ids::INFO));
+    elms.extend(info);
+    elms.push(
+        UnsignedIntegerElement {
+            ebml_id: ids::TIMECODE_SCALE,
+            value: 1_000_000,
+        }
+        .into(),
+    );
+    elms.push(end(ids::INFO));
+    elms.push(start(ids::TRACKS));
+    elms.extend(track(1, "eng"));
+    elms.extend(track(2, "eng"));
+    elms.push(end(ids::TRACKS));
+    elms.push(end(ids::SEGMENT));
+    elms
+}
+
+fn encode(elms: Vec<Element>) -> Vec<u8> {
+    let schema = DefaultSchema::default();
+    ebml::Encoder::new(&schema).encode(elms).unwrap()
+}
+
+fn decode(buf: Vec<u8>) -> Vec<Element> {
+    let schema = DefaultSchema::default();
+    ebml::Decoder::new(&schema)
+        .decode(buf)
+        .unwrap()
+        .into_iter()
+        .map(Into::into)
+        .collect()
+}
+
+fn edit(path: &str, value: &str) -> Edit {
+    Edit {
+        path: path.to_string(),
+        value: value.into(),
+    }
+}
+
+#[test]
+fn test_edit_in_place() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    let schema = DefaultSchema::default();
+    // Title (8 bytes) and Void (10 bytes) make 18 bytes
+    let original = encode(mkv(vec![title("hello"), void(8)]));
+    let cases = vec![
+        // shorter; the rest becomes Void
+        ("hi", vec![title("hi"), void(11)]),
+        // longer, using the Void
+        ("hello world!!", vec![title("hello world!!"), void(0)]),
+        // 1 byte left; the size of Title gets 2 bytes long
+        ("hello world!!!", vec![title("hello world!!!")]),
+    ];
+    for (value, info) in cases {
+        let mut file = Cursor::new(original.clone());
+        let patches =
+            edit_in_place(&mut file, &schema, &[edit("Segment/Info/Title", value)]).unwrap();
+        assert_eq!(patches.len(), 1);
+        assert_eq!(patches[0].data.len(), 18);
+        let buf = file.into_inner();
+        assert_eq!(buf.len(), original.len());
+        assert_eq!(decode(buf), mkv(info));
+    }
+}
+
+#[test]
+fn test_edit_indexed() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    let schema = DefaultSchema::default();
+    let original = encode(mkv(vec![]));
+    let mut file = Cursor::new(original.clone());
+    edit_in_place(
+        &mut file,
+        &schema,
+        &[edit("Segment/Tracks/TrackEntry[1]/Language", "jpn")],
+    )
+    .unwrap();
+    let mut expected = mkv(vec![]);
+    let i = expected.len() - 4;
+    expected[i] = StringElement {
+        ebml_id: ids::LANGUAGE,
+        value: b"jpn".to_vec(),
+    }
+    .into();
+    assert_eq!(decode(file.into_inner()), expected);
+    // without index, all the matching elements are edited
+    let mut file = Cursor::new(original);
+    let patches = plan(
+        &mut file,
+        &schema,
+        &[edit("Segment/Tracks/TrackEntry/Language", "fre")],
+    )
+    .unwrap();
+    assert_eq!(patches.len(), 2);
+}
+
+#[test]
+fn test_edit_errors() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    let schema = DefaultSchema::default();
+    let original = encode(mkv(vec![title("hello"), void(8)]));
+    let mut file = Cursor::new(original.clone());
+    match plan(
+        &mut file,
+        &schema,
+        &[edit("Segment/Info/Title", "hello world!!!!!")],
+    ) {
+        Err(EditError::RewriteRequired {
+            needed, available, ..
+        }) => assert_eq!((needed, available), (19, 18)),
+        o => panic!("{:?}", o),
+    }
+    match plan(&mut file, &schema, &[edit("Segment/Info/MuxingApp", "foo")]) {
+        Err(EditError::NotFound(_)) => {}
+        o => panic!("{:?}", o),
+    }
+    match plan(&mut file, &schema, &[edit("Segment//Title", "foo")]) {
+        Err(EditError::InvalidPath(_)) => {}
+        o => panic!("{:?}", o),
+    }
+    // nothing is written when any edit fails
+    assert!(edit_in_place(
+        &mut file,
+        &schema,
+        &[
+            edit("Segment/Info/Title", "hi"),
+            edit("Segment/Info/MuxingApp", "foo"),
+        ],
+    )
+    .is_err());
+    assert_eq!(file.into_inner(), original);
+}
+
+#[test]
+fn test_edit_crc() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    use ebml::validate::{validate, FindingKind};
+    let schema = DefaultSchema::default();
+    let crc = || -> Element {
+        BinaryElement {
+            ebml_id: ids::CRC32,
+            value: vec![0; 4],
+        }
+        .into()
+    };
+    // CRC-32 of Segment covers the one of Info
+    let mut elms = mkv(vec![crc(), title("hello"), void(8)]);
+    elms.insert(3, crc());
+    let crc_findings = |buf: &[u8]| {
+        validate(&schema, buf)
+            .into_iter()
+            .filter(|o| o.kind == FindingKind::Crc32)
+            .count()
+    };
+    let original = encode(elms);
+    assert_eq!(crc_findings(&original), 2);
+    let mut file = Cursor::new(original);
+    let patches = edit_in_place(&mut file, &schema, &[edit("Segment/Info/Title", "hi")]).unwrap();
+    // Title, and CRC-32 of Info and Segment
+    assert_eq!(patches.len(), 3);
+    assert_eq!(crc_findings(&file.into_inner()), 0);
+}
+
+#[test]
+fn test_fill_void() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    use ebml::edit::fill_void;
+    let schema = DefaultSchema::default();
+    let mut encoder = ebml::Encoder::new(&schema);
+    let mut buf = encoder
+        .encode(vec![start(ids::EBML), end(ids::EBML), start(ids::SEGMENT)])
+        .unwrap();
+    encoder.reserve(30).unwrap();
+    buf.append(
+        &mut encoder
+            .encode(vec![
+                start(ids::CLUSTER),
+                UnsignedIntegerElement {
+                    ebml_id: ids::TIMECODE,
+                    value: 0,
+                }
+                .into(),
+                end(ids::CLUSTER),
+                end(ids::SEGMENT),
+            ])
+            .unwrap(),
+    );
+    // EBML 5 bytes, Segment header 5 bytes
+    let void_offset = 10;
+    let cluster_offset = void_offset + 30;
+    let seek_head = vec![
+        start(ids::SEEK_HEAD),
+        start(ids::SEEK),
+        BinaryElement {
+            ebml_id: ids::SEEK_ID,
+            value: ids::CLUSTER.into(),
+        }
+        .into(),
+        UnsignedIntegerElement {
+            ebml_id: ids::SEEK_POSITION,
+            value: cluster_offset - 10,
+        }
+        .into(),
+        end(ids::SEEK),
+        end(ids::SEEK_HEAD),
+    ];
+    let mut file = Cursor::new(buf.clone());
+    let patch = fill_void(&mut file, &schema, void_offset, seek_head.clone()).unwrap();
+    assert_eq!(patch.offset, void_offset);
+    assert_eq!(patch.data.len(), 30);
+    ebml::edit::apply(&mut file, &[patch]).unwrap();
+    let buf = file.into_inner();
+    assert_eq!(
+        &buf[cluster_offset as usize..][..4],
+        &[0x1F, 0x43, 0xB6, 0x75]
+    );
+    let elms = decode(buf.clone());
+    assert_eq!(&elms[3..9], &seek_head[..]);
+    // the rest of the reserved space
+    assert_eq!(elms[9], void(30 - 19 - 2));
+
+    // not a Void
+    let mut file = Cursor::new(buf);
+    match fill_void(&mut file, &schema, cluster_offset, seek_head) {
+        Err(EditError::NotVoid(_)) => {}
+        o => panic!("{:?}", o),
+    }
+}
@@ -8,7 +8,8 @@ fn test_encoder() {
     let mut encoder = ebml::Encoder::new(&schema);
     let mut decoder = ebml::Decoder::new(&schema);
     let elms: Vec<ebml::ebml::Element> = vec![ebml::ebml::Utf8Element {
-        ebml_id: 0.into(),
+        // Title
+        ebml_id: 0x7BA9.into(),
         value: "a".to_string(),
     }
     .into()];
@@ -22,3 +23,604 @@ fn test_encoder() {
             .collect::<Vec<ebml::ebml::Element>>()
     );
 }
+
+#[test]
+fn test_encode_void() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    use ebml::decoder::ReadEbmlExt;
+    use ebml::encoder::{encode_padded, encode_void};
+    // around the boundaries of the size length
+    let lens = (2..300).chain(16_380..16_390).chain(2_097_148..2_097_160);
+    for len in lens {
+        let buf = encode_void(len).unwrap();
+        assert_eq!(buf.len(), len);
+        let header = std::io::Cursor::new(&buf).read_element_header().unwrap();
+        assert_eq!(header.ebml_id, ebml::ids::VOID);
+        assert_eq!(header.header_size + header.content_size as usize, len);
+    }
+    assert!(encode_void(0).is_err());
+    assert!(encode_void(1).is_err());
+
+    let title: Vec<u8> = ebml::ebml::Utf8Element {
+        ebml_id: ebml::ids::TITLE,
+        value: "a".to_string(),
+    }
+    .into();
+    // exact
+    assert_eq!(encode_padded(title.clone(), 4).unwrap(), title);
+    // longer size field
+    assert_eq!(
+        encode_padded(title.clone(), 5).unwrap(),
+        vec![0x7B, 0xA9, 0x40, 0x01, b'a']
+    );
+    // Void after the element
+    assert_eq!(
+        encode_padded(title.clone(), 7).unwrap(),
+        vec![0x7B, 0xA9, 0x81, b'a', 0xEC, 0x81, 0x00]
+    );
+    assert!(encode_padded(title, 3).is_err());
+}
+
+#[test]
+fn test_encoder_reserve() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    use ebml::ebml::{MasterEndElement, MasterStartElement};
+    let schema = ebml::schema::DefaultSchema::default();
+    let mut encoder = ebml::Encoder::new(&schema);
+    let mut buf = encoder
+        .encode(vec![MasterStartElement {
+            ebml_id: ebml::ids::SEGMENT,
+            unknown_size: false,
+        }])
+        .unwrap();
+    encoder.reserve(200).unwrap();
+    buf.append(
+        &mut encoder
+            .encode(vec![MasterEndElement {
+                ebml_id: ebml::ids::SEGMENT,
+            }])
+            .unwrap(),
+    );
+    // Segment header and Void
+    assert_eq!(buf.len(), 4 + 2 + 200);
+    assert_eq!(&buf[..6], &[0x18, 0x53, 0x80, 0x67, 0x40, 0xC8]);
+    assert_eq!(&buf[6..8], &[0xEC, 0x40]);
+}
+
+#[test]
+fn test_error_context() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    use ebml::decoder::DecodeError;
+    use ebml::ebml::{MasterEndElement, MasterStartElement, UnsignedIntegerElement};
+    use ebml::encoder::EncodeError;
+    use ebml::ids;
+    let schema = ebml::schema::DefaultSchema::default();
+    let start = |ebml_id| MasterStartElement {
+        ebml_id,
+        unknown_size: false,
+    };
+    let mut buf = ebml::Encoder::new(&schema)
+        .encode(vec![start(ids::SEGMENT), start(ids::INFO)])
+        .unwrap();
+    assert!(buf.is_empty());
+    // Segment > Info > Duration of 3 bytes
+    buf = vec![0x18, 0x53, 0x80, 0x67, 0x88, 0x15, 0x49, 0xA9, 0x66, 0x86];
+    buf.extend_from_slice(&[0x44, 0x89, 0x83, 0x01, 0x02, 0x03]);
+    for chunk_size in &[1, 3, buf.len()] {
+        let mut decoder = ebml::Decoder::new(&schema);
+        let mut ret = Ok(vec![]);
+        for chunk in buf.chunks(*chunk_size) {
+            ret = decoder.decode(chunk.to_vec());
+            if ret.is_err() {
+                break;
+            }
+        }
+        let err = ret.unwrap_err();
+        match err.kind() {
+            DecodeError::ReadContent(_) => {}
+            o => panic!("{:?}", o),
+        }
+        let context = err.context().unwrap();
+        assert_eq!(context.offset, 10);
+        assert_eq!(context.ebml_id, Some(ids::DURATION));
+        assert_eq!(context.name.as_deref(), Some("Duration"));
+        assert_eq!(context.path, vec!["Segment", "Info"]);
+        assert_eq!(
+            format!("{}", err),
+            [
+                "invalid content size 3 for type f at offset 10 (0xA) in Segment > Info > Duration [4489]",
+                "  00000000: 18 53 80 67 88 15 49 A9 66 86 [44] 89 83 01 02 03",
+            ]
+            .join("\n")
+        );
+    }
+
+    // unknown id in Segment
+    let buf = vec![0x18, 0x53, 0x80, 0x67, 0x83, 0xFF, 0x80, 0x80];
+    let err = ebml::Decoder::new(&schema).decode(buf).unwrap_err();
+    let context = err.context().unwrap();
+    assert_eq!(context.offset, 5);
+    assert_eq!(context.ebml_id, Some(0xFF.into()));
+    assert_eq!(context.name, None);
+    assert_eq!(context.path, vec!["Segment"]);
+
+    // end of Segment in Info
+    let err = ebml::Encoder::new(&schema)
+        .encode::<ebml::ebml::Element>(vec![
+            start(ids::SEGMENT).into(),
+            start(ids::INFO).into(),
+            UnsignedIntegerElement {
+                ebml_id: ids::TIMECODE_SCALE,
+                value: 1_000_000,
+            }
+            .into(),
+            MasterEndElement {
+                ebml_id: ids::SEGMENT,
+            }
+            .into(),
+        ])
+        .unwrap_err();
+    match err.kind() {
+        EncodeError::Bloken => {}
+        o => panic!("{:?}", o),
+    }
+    let context = err.context().unwrap();
+    // Segment header, Info header and TimecodeScale
+    assert_eq!(context.offset, 5 + 5 + 7);
+    assert_eq!(context.name.as_deref(), Some("Segment"));
+    assert_eq!(context.path, vec!["Segment", "Info"]);
+    assert_eq!(
+        context.excerpt,
+        vec![0x2A, 0xD7, 0xB1, 0x83, 0x0F, 0x42, 0x40]
+    );
+}
+
+fn float_roundtrip(
+    value: f64,
+    width: ebml::ebml::FloatWidth,
+) -> (Vec<u8>, ebml::ebml::FloatElement) {
+    use ebml::ebml::{ChildElement, ElementDetail, FloatElement};
+    let schema = ebml::schema::DefaultSchema::default();
+    let buf: Vec<u8> = FloatElement {
+        ebml_id: ebml::ids::DURATION,
+        value,
+        width,
+    }
+    .into();
+    match ebml::Decoder::new(&schema)
+        .decode(buf.clone())
+        .unwrap()
+        .remove(0)
+    {
+        ElementDetail::ChildElement((ChildElement::FloatElement(o), _)) => (buf[3..].to_vec(), o),
+        o => panic!("{:?}", o),
+    }
+}
+
+#[test]
+fn test_float_width() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    use ebml::ebml::FloatWidth;
+    // float32 only when lossless
+    let (content, elm) = float_roundtrip(48000.0, FloatWidth::Auto);
+    assert_eq!(content, 48000f32.to_be_bytes());
+    assert_eq!((elm.value, elm.width), (48000.0, FloatWidth::F32));
+    let (content, elm) = float_roundtrip(0.1, FloatWidth::Auto);
+    assert_eq!(content, 0.1f64.to_be_bytes());
+    assert_eq!((elm.value, elm.width), (0.1, FloatWidth::F64));
+    let (content, _) = float_roundtrip(1.0, FloatWidth::F64);
+    assert_eq!(content.len(), 8);
+    // requested float32 rounds
+    let (_, elm) = float_roundtrip(0.1, FloatWidth::F32);
+    assert_eq!((elm.value, elm.width), (f64::from(0.1f32), FloatWidth::F32));
+
+    let (content, elm) = float_roundtrip(1.0, FloatWidth::F80);
+    assert_eq!(content, vec![0x3F, 0xFF, 0x80, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!((elm.value, elm.width), (1.0, FloatWidth::F80));
+    let values = [
+        0.0,
+        -0.0,
+        -2.5,
+        44100.0,
+        1e300,
+        // subnormal
+        1e-310,
+        f64::MAX,
+        f64::MIN_POSITIVE,
+        f64::INFINITY,
+        f64::NEG_INFINITY,
+    ];
+    for value in values.iter() {
+        let (content, elm) = float_roundtrip(*value, FloatWidth::F80);
+        assert_eq!(content.len(), 10);
+        assert_eq!(elm.value.to_bits(), value.to_bits());
+    }
+    assert!(float_roundtrip(f64::NAN, FloatWidth::F80).1.value.is_nan());
+    proptest::proptest!(|(value: f64)| {
+        let (_, elm) = float_roundtrip(value, FloatWidth::F80);
+        proptest::prop_assert!(elm.value.to_bits() == value.to_bits() || value.is_nan());
+    });
+
+    // 80-bit values beyond f64
+    let schema = ebml::schema::DefaultSchema::default();
+    let decode = |content: &[u8]| {
+        let mut buf = vec![0x44, 0x89, 0x8A];
+        buf.extend_from_slice(content);
+        let elm: ebml::ebml::Element = ebml::Decoder::new(&schema)
+            .decode(buf)
+            .unwrap()
+            .remove(0)
+            .into();
+        match elm {
+            ebml::ebml::Element::ChildElement(ebml::ebml::ChildElement::FloatElement(o)) => o.value,
+            o => panic!("{:?}", o),
+        }
+    };
+    assert_eq!(
+        decode(&[0x7F, 0xFE, 0x80, 0, 0, 0, 0, 0, 0, 0]),
+        f64::INFINITY
+    );
+    assert_eq!(decode(&[0x00, 0x01, 0x80, 0, 0, 0, 0, 0, 0, 0]), 0.0);
+    // 1 + 2^-63 rounds to 1
+    assert_eq!(decode(&[0x3F, 0xFF, 0x80, 0, 0, 0, 0, 0, 0, 1]), 1.0);
+}
+
+fn decode_date(content: &[u8]) -> ebml::ebml::DateElement {
+    let schema = ebml::schema::DefaultSchema::default();
+    // DateUTC
+    let mut buf = vec![0x44, 0x61, 0x80 | content.len() as u8];
+    buf.extend_from_slice(content);
+    let elm: ebml::ebml::Element = ebml::Decoder::new(&schema)
+        .decode(buf)
+        .unwrap()
+        .remove(0)
+        .into();
+    match elm {
+        ebml::ebml::Element::ChildElement(ebml::ebml::ChildElement::DateElement(o)) => o,
+        o => panic!("{:?}", o),
+    }
+}
+
+#[test]
+fn test_date() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    use chrono::{TimeZone, Utc};
+    use ebml::ebml::DateElement;
+    use proptest::prelude::*;
+    use std::convert::TryFrom;
+    let date = |nanos: i64| DateElement::from_nanos(0x4461.into(), nanos);
+    let cases = vec![
+        (0, Utc.ymd(2001, 1, 1).and_hms(0, 0, 0)),
+        (
+            -1,
+            Utc.ymd(2000, 12, 31).and_hms_nano(23, 59, 59, 999_999_999),
+        ),
+        (
+            1_500_000_001,
+            Utc.ymd(2001, 1, 1).and_hms_nano(0, 0, 1, 500_000_001),
+        ),
+        (
+            -978_307_200_000_000_000,
+            Utc.ymd(1970, 1, 1).and_hms(0, 0, 0),
+        ),
+        (
+            -3_187_296_000_000_000_000,
+            Utc.ymd(1900, 1, 1).and_hms(0, 0, 0),
+        ),
+        (
+            i64::MIN,
+            Utc.ymd(1708, 9, 22).and_hms_nano(0, 12, 43, 145_224_192),
+        ),
+        (
+            i64::MAX,
+            Utc.ymd(2293, 4, 11).and_hms_nano(23, 47, 16, 854_775_807),
+        ),
+    ];
+    for (nanos, value) in cases {
+        let elm = date(nanos);
+        assert_eq!(elm.value, value);
+        assert_eq!(elm.nanos(), Some(nanos));
+        let buf = Vec::try_from(elm).unwrap();
+        assert_eq!(&buf[3..], &nanos.to_be_bytes());
+        assert_eq!(decode_date(&buf[3..]), elm);
+    }
+    // zero-length date is the epoch
+    assert_eq!(decode_date(&[]), date(0));
+    // serde keeps the nanoseconds since the unix epoch of chrono
+    let json = serde_json::to_value(date(0)).unwrap();
+    assert_eq!(json["value"], serde_json::json!(978_307_200_000_000_000i64));
+    assert_eq!(
+        serde_json::from_value::<DateElement>(json).unwrap(),
+        date(0)
+    );
+    // out of the range of 8 octets
+    let elm = DateElement {
+        ebml_id: 0x4461.into(),
+        value: Utc.ymd(1700, 1, 1).and_hms(0, 0, 0),
+    };
+    assert_eq!(elm.nanos(), None);
+    assert!(Vec::try_from(elm).is_err());
+
+    proptest!(|(nanos: i64)| {
+        let elm = decode_date(&nanos.to_be_bytes());
+        prop_assert_eq!(elm.nanos(), Some(nanos));
+        prop_assert_eq!(&Vec::try_from(elm).unwrap()[3..], &nanos.to_be_bytes());
+    });
+    proptest!(|(elm: DateElement)| {
+        let elm = DateElement {
+            ebml_id: 0x4461.into(),
+            ..elm
+        };
+        let buf = Vec::try_from(elm).unwrap();
+        prop_assert_eq!(decode_date(&buf[3..]).value, elm.value);
+    });
+}
+
+fn decode_child(buf: Vec<u8>) -> Result<ebml::ebml::ChildElement, ebml::decoder::DecodeError> {
+    let schema = ebml::schema::DefaultSchema::default();
+    let elm: ebml::ebml::Element = ebml::Decoder::new(&schema).decode(buf)?.remove(0).into();
+    match elm {
+        ebml::ebml::Element::ChildElement(o) => Ok(o),
+        o => panic!("{:?}", o),
+    }
+}
+
+#[test]
+fn test_integers() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    use ebml::decoder::{DecodeError, ReadContentError};
+    use ebml::ebml::{ChildElement, FloatWidth, IntegerElement, UnsignedIntegerElement};
+    use proptest::prelude::*;
+    // TrackOffset and TrackNumber
+    let int = |value| -> Vec<u8> {
+        IntegerElement {
+            ebml_id: 0x537F.into(),
+            value,
+        }
+        .into()
+    };
+    let uint = |value| -> Vec<u8> {
+        UnsignedIntegerElement {
+            ebml_id: 0xD7.into(),
+            value,
+        }
+        .into()
+    };
+    let int_cases: Vec<(i64, Vec<u8>)> = vec![
+        (0, vec![0x00]),
+        (-1, vec![0xFF]),
+        (127, vec![0x7F]),
+        (128, vec![0x00, 0x80]),
+        (-128, vec![0x80]),
+        (-129, vec![0xFF, 0x7F]),
+        (
+            i64::MAX,
+            vec![0x7F, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF],
+        ),
+        (i64::MIN, vec![0x80, 0, 0, 0, 0, 0, 0, 0]),
+    ];
+    for (value, content) in int_cases {
+        assert_eq!(&int(value)[3..], &content[..]);
+    }
+    let uint_cases: Vec<(u64, Vec<u8>)> = vec![
+        (0, vec![0x00]),
+        (255, vec![0xFF]),
+        (256, vec![0x01, 0x00]),
+        (u64::MAX, vec![0xFF; 8]),
+    ];
+    for (value, content) in uint_cases {
+        assert_eq!(&uint(value)[2..], &content[..]);
+    }
+
+    // zero-length
+    let zero = |ebml_id: &[u8]| {
+        let mut buf = ebml_id.to_vec();
+        buf.push(0x80);
+        decode_child(buf).unwrap()
+    };
+    assert_eq!(
+        zero(&[0x53, 0x7F]),
+        IntegerElement::from((0x537F.into(), 0)).into()
+    );
+    assert_eq!(
+        zero(&[0xD7]),
+        UnsignedIntegerElement::from((0xD7.into(), 0)).into()
+    );
+    match zero(&[0x44, 0x89]) {
+        ChildElement::FloatElement(o) => {
+            assert_eq!((o.value, o.width), (0.0, FloatWidth::Auto))
+        }
+        o => panic!("{:?}", o),
+    }
+    // longer than 8 octets
+    for buf in [
+        vec![0xD7, 0x89, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+        vec![0x53, 0x7F, 0x89, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+    ] {
+        match decode_child(buf).unwrap_err().kind() {
+            DecodeError::ReadContent(ReadContentError::InvalidSize(_, 9)) => {}
+            o => panic!("{:?}", o),
+        }
+    }
+
+    proptest!(|(value: i64)| {
+        let buf = int(value);
+        let len = buf.len() - 3;
+        // minimal
+        prop_assert!(len == 1 || value >> (8 * (len - 1) - 1) != value >> 63);
+        prop_assert_eq!(decode_child(buf).unwrap(), IntegerElement::from((0x537F.into(), value)).into());
+    });
+    proptest!(|(value: u64)| {
+        let buf = uint(value);
+        let len = buf.len() - 2;
+        prop_assert!(len == 1 || value >> (8 * (len - 1)) != 0);
+        prop_assert_eq!(decode_child(buf).unwrap(), UnsignedIntegerElement::from((0xD7.into(), value)).into());
+    });
+}
+
+#[test]
+fn test_strings() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    use ebml::decoder::{DecodeError, DecoderOptions, ReadContentError};
+    use ebml::ebml::{ChildElement, Element, StringElement, Utf8Element};
+    let schema = ebml::schema::DefaultSchema::default();
+    let decode = |options: &DecoderOptions, buf: Vec<u8>| -> Result<ChildElement, DecodeError> {
+        let elm: Element = ebml::Decoder::with_options(&schema, options.clone())
+            .decode(buf)?
+            .remove(0)
+            .into();
+        match elm {
+            Element::ChildElement(o) => Ok(o),
+            o => panic!("{:?}", o),
+        }
+    };
+    let strict = DecoderOptions {
+        strict_strings: true,
+        ..Default::default()
+    };
+    let lossy = DecoderOptions {
+        lossy_utf8: true,
+        ..Default::default()
+    };
+    // Language
+    let language = |content: &[u8]| {
+        let mut buf = vec![0x22, 0xB5, 0x9C, 0x80 | content.len() as u8];
+        buf.extend_from_slice(content);
+        buf
+    };
+    let eng: ChildElement = StringElement {
+        ebml_id: ebml::ids::LANGUAGE,
+        value: b"eng".to_vec(),
+    }
+    .into();
+    for options in &[DecoderOptions::default(), strict.clone()] {
+        assert_eq!(decode(options, language(b"eng\0\0")).unwrap(), eng);
+    }
+    assert_eq!(
+        decode(&Default::default(), language(b"eng\0x")).unwrap(),
+        eng
+    );
+    assert!(decode(&strict, language(b"eng\0x")).is_err());
+    assert!(decode(&Default::default(), language(b"en\x01")).is_ok());
+    match decode(&strict, language(b"en\x01")).unwrap_err().kind() {
+        DecodeError::ReadContent(ReadContentError::String(_)) => {}
+        o => panic!("{:?}", o),
+    }
+
+    // Title
+    let title = |content: &[u8]| {
+        let mut buf = vec![0x7B, 0xA9, 0x80 | content.len() as u8];
+        buf.extend_from_slice(content);
+        buf
+    };
+    let utf8 = |value: &str| -> ChildElement {
+        Utf8Element {
+            ebml_id: ebml::ids::TITLE,
+            value: value.to_string(),
+        }
+        .into()
+    };
+    let mut content = "タイトル".as_bytes().to_vec();
+    content.extend_from_slice(&[0, 0]);
+    assert_eq!(
+        decode(&Default::default(), title(&content)).unwrap(),
+        utf8("タイトル")
+    );
+    match decode(&Default::default(), title(&[0xFF, b'a']))
+        .unwrap_err()
+        .kind()
+    {
+        DecodeError::ReadContent(ReadContentError::Utf8(_)) => {}
+        o => panic!("{:?}", o),
+    }
+    assert_eq!(
+        decode(&lossy, title(&[0xFF, b'a'])).unwrap(),
+        utf8("\u{FFFD}a")
+    );
+}
+
+#[test]
+fn test_string_bytesize() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    use ebml::ebml::StringElement;
+    use ebml::encoder::{EncodeError, EncodeTagError};
+    // fixed-size string element 0x4001
+    let schema: ebml::schema::DefaultSchema = serde_json::from_str(
+        r#"{ "16385": { "name": "Code", "type": "s", "level": 0, "description": "", "bytesize": 8 } }"#,
+    )
+    .unwrap();
+    let code = |value: &[u8]| StringElement {
+        ebml_id: 0x4001.into(),
+        value: value.to_vec(),
+    };
+    let buf = ebml::Encoder::new(&schema)
+        .encode(vec![code(b"abc")])
+        .unwrap();
+    assert_eq!(buf, b"\x40\x01\x88abc\0\0\0\0\0".to_vec());
+    let elms = ebml::Decoder::new(&schema).decode(buf).unwrap();
+    assert_eq!(
+        elms.into_iter()
+            .map(Into::into)
+            .collect::<Vec<ebml::ebml::Element>>(),
+        vec![code(b"abc").into()]
+    );
+    let err = ebml::Encoder::new(&schema)
+        .encode(vec![code(b"abcdefghi")])
+        .unwrap_err();
+    match err.kind() {
+        EncodeError::EncodeTag(EncodeTagError::TooLong {
+            len: 9,
+            bytesize: 8,
+        }) => {}
+        o => panic!("{:?}", o),
+    }
+}
+
+#[test]
+fn test_shared_schema() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    use ebml::ebml::{Element, UnsignedIntegerElement};
+    use ebml::schema::{DefaultSchema, SharedSchema};
+    use std::sync::Arc;
+    fn assert_send<T: Send + 'static>(o: T) -> T {
+        o
+    }
+    let schema: SharedSchema = Arc::new(DefaultSchema::default());
+    let elms: Vec<Element> = vec![UnsignedIntegerElement {
+        ebml_id: ebml::ids::EBML_VERSION,
+        value: 1,
+    }
+    .into()];
+    // owned schema
+    let mut encoder = assert_send(ebml::Encoder::new(DefaultSchema::default()));
+    let buf = encoder.encode(elms.clone()).unwrap();
+    let handles = (0..2)
+        .map(|_| {
+            let mut decoder = assert_send(ebml::Decoder::new(schema.clone()));
+            let buf = buf.clone();
+            std::thread::spawn(move || decoder.decode(buf).unwrap())
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        let decoded = handle.join().unwrap();
+        assert_eq!(
+            decoded
+                .into_iter()
+                .map(Into::into)
+                .collect::<Vec<Element>>(),
+            elms
+        );
+    }
+    // the built-in schema behind a reference
+    let schema = DefaultSchema::default();
+    assert_eq!(ebml::Encoder::new(&schema).encode(elms).unwrap(), buf);
+}
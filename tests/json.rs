@@ -0,0 +1,136 @@
+use chrono::{TimeZone, Utc};
+use ebml::ebml::{
+    BinaryElement, DateElement, EbmlId, Element, FloatElement, FloatWidth, IntegerElement,
+    StringElement, UnsignedIntegerElement, Utf8Element,
+};
+use ebml::ids;
+use ebml::json::{ebml_to_json, from_json, json_to_ebml, to_json, JsonElement};
+
+mod common;
+use common::{end, start, start_unknown};
+
+const DATE_UTC: EbmlId = EbmlId(0x4461);
+
+fn mkv() -> Vec<Element> {
+    vec![
+        start(ids::EBML),
+        StringElement {
+            ebml_id: ids::DOC_TYPE,
+            // non-ASCII byte in ASCII string
+            value: b"webm\xFF".to_vec(),
+        }
+        .into(),
+        end(ids::EBML),
+        start_unknown(ids::SEGMENT),
+        start(ids::INFO),
+        UnsignedIntegerElement {
+            ebml_id: ids::TIMECODE_SCALE,
+            value: 1_000_000,
+        }
+        .into(),
+        FloatElement {
+            ebml_id: ids::DURATION,
+            value: 0.1,
+            width: FloatWidth::F64,
+        }
+        .into(),
+        Utf8Element {
+            ebml_id: ids::TITLE,
+            value: "タイトル".to_string(),
+        }
+        .into(),
+        end(ids::INFO),
+        start(ids::CLUSTER),
+        start(ids::BLOCK_GROUP),
+        BinaryElement {
+            ebml_id: ids::BLOCK,
+            value: vec![0x81, 0, 0, 0, 0xFF],
+        }
+        .into(),
+        IntegerElement {
+            ebml_id: ids::REFERENCE_BLOCK,
+            value: -1,
+        }
+        .into(),
+        end(ids::BLOCK_GROUP),
+        end(ids::CLUSTER),
+        end(ids::SEGMENT),
+    ]
+}
+
+#[test]
+fn test_json_roundtrip() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    let schema = ebml::schema::DefaultSchema::default();
+    let buf = ebml::Encoder::new(&schema).encode(mkv()).unwrap();
+    let json = ebml_to_json(&schema, buf.clone()).unwrap();
+    assert_eq!(json_to_ebml(&schema, &json).unwrap(), buf);
+
+    let json: Vec<JsonElement> = serde_json::from_str(&json).unwrap();
+    assert_eq!(json.len(), 2);
+    assert_eq!(json[1].name, "Segment");
+    assert!(json[1].unknown_size);
+    let info = &json[1].children.as_ref().unwrap()[0];
+    assert_eq!(
+        serde_json::to_value(info).unwrap(),
+        serde_json::json!({
+            "name": "Info",
+            "children": [
+                { "name": "TimecodeScale", "value": 1_000_000 },
+                { "name": "Duration", "value": 0.1 },
+                { "name": "Title", "value": "タイトル" },
+            ]
+        })
+    );
+    let block = &json[1].children.as_ref().unwrap()[1]
+        .children
+        .as_ref()
+        .unwrap()[0]
+        .children
+        .as_ref()
+        .unwrap()[0];
+    assert_eq!(block.value, Some(serde_json::json!("gQAAAP8=")));
+}
+
+#[test]
+fn test_json_values() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    let schema = ebml::schema::DefaultSchema::default();
+    let elms = vec![
+        start(ids::INFO),
+        DateElement {
+            ebml_id: DATE_UTC,
+            value: Utc.ymd(2001, 1, 1).and_hms_nano(0, 0, 0, 1),
+        }
+        .into(),
+        FloatElement {
+            ebml_id: ids::DURATION,
+            value: f64::NEG_INFINITY,
+            width: FloatWidth::F32,
+        }
+        .into(),
+        end(ids::INFO),
+    ];
+    let json = to_json(&schema, elms.clone()).unwrap();
+    assert_eq!(
+        serde_json::to_value(&json).unwrap(),
+        serde_json::json!([{
+            "name": "Info",
+            "children": [
+                { "name": "DateUTC", "value": "2001-01-01T00:00:00.000000001Z" },
+                { "name": "Duration", "value": "-Infinity", "width": 4 },
+            ]
+        }])
+    );
+    assert_eq!(from_json(&schema, json).unwrap(), elms);
+
+    // hand-written JSON
+    let json = r#"[{ "name": "Info", "children": [{ "name": "Title", "value": 1 }] }]"#;
+    assert!(json_to_ebml(&schema, json).is_err());
+    let json = r#"[{ "name": "NoSuchElement", "value": 1 }]"#;
+    assert!(json_to_ebml(&schema, json).is_err());
+    let json = r#"[{ "name": "DocType", "value": "Ā" }]"#;
+    assert!(json_to_ebml(&schema, json).is_err());
+}
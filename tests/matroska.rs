@@ -0,0 +1,262 @@
+use ebml::cues::Cues;
+use ebml::ebml::{BinaryElement, Element, FloatElement, FloatWidth, IntegerElement, StringElement};
+use ebml::ids;
+use ebml::matroska::{Audio, Demuxer, Muxer, MuxerOptions, Packet, Track, Video};
+use ebml::segment::SegmentIndex;
+use std::io::{Cursor, Seek, SeekFrom};
+
+mod common;
+use common::{end, start, uint};
+
+fn binary(ebml_id: ebml::ebml::EbmlId, value: Vec<u8>) -> Element {
+    BinaryElement { ebml_id, value }.into()
+}
+
+fn block(track: u8, timecode: i16, flags: u8, payload: &[u8]) -> Vec<u8> {
+    let mut value = vec![0b_1000_0000 | track];
+    value.extend_from_slice(&timecode.to_be_bytes());
+    value.push(flags);
+    value.extend_from_slice(payload);
+    value
+}
+
+fn mkv() -> Vec<Element> {
+    vec![
+        start(ids::EBML),
+        end(ids::EBML),
+        start(ids::SEGMENT),
+        start(ids::INFO),
+        uint(ids::TIMECODE_SCALE, 100_000),
+        end(ids::INFO),
+        start(ids::TRACKS),
+        start(ids::TRACK_ENTRY),
+        uint(ids::TRACK_NUMBER, 1),
+        uint(ids::TRACK_UID, 11),
+        uint(ids::TRACK_TYPE, 1),
+        StringElement {
+            ebml_id: ids::CODEC_ID,
+            value: b"V_VP9".to_vec(),
+        }
+        .into(),
+        binary(ids::CODEC_PRIVATE, vec![1, 2, 3]),
+        start(ids::VIDEO),
+        uint(ids::PIXEL_WIDTH, 640),
+        uint(ids::PIXEL_HEIGHT, 480),
+        end(ids::VIDEO),
+        end(ids::TRACK_ENTRY),
+        start(ids::TRACK_ENTRY),
+        uint(ids::TRACK_NUMBER, 2),
+        uint(ids::TRACK_UID, 22),
+        uint(ids::TRACK_TYPE, 2),
+        StringElement {
+            ebml_id: ids::CODEC_ID,
+            value: b"A_OPUS".to_vec(),
+        }
+        .into(),
+        uint(ids::DEFAULT_DURATION, 20_000_000),
+        start(ids::AUDIO),
+        FloatElement {
+            ebml_id: ids::SAMPLING_FREQUENCY,
+            value: 48000.0,
+            width: FloatWidth::F32,
+        }
+        .into(),
+        uint(ids::CHANNELS, 2),
+        end(ids::AUDIO),
+        end(ids::TRACK_ENTRY),
+        end(ids::TRACKS),
+        start(ids::CLUSTER),
+        uint(ids::TIMECODE, 10_000),
+        binary(ids::SIMPLE_BLOCK, block(1, 0, 0b_1000_0000, &[0xA0])),
+        // Xiph-laced 2 frames
+        binary(
+            ids::SIMPLE_BLOCK,
+            block(2, 5, 0b_1000_0010, &[1, 1, 0xB0, 0xB1]),
+        ),
+        start(ids::BLOCK_GROUP),
+        binary(ids::BLOCK, block(1, 400, 0, &[0xA1])),
+        uint(ids::BLOCK_DURATION, 400),
+        IntegerElement {
+            ebml_id: ids::REFERENCE_BLOCK,
+            value: -400,
+        }
+        .into(),
+        end(ids::BLOCK_GROUP),
+        end(ids::CLUSTER),
+        start(ids::CLUSTER),
+        uint(ids::TIMECODE, 20_000),
+        start(ids::BLOCK_GROUP),
+        binary(ids::BLOCK, block(1, -1, 0, &[0xA2])),
+        end(ids::BLOCK_GROUP),
+        end(ids::CLUSTER),
+        end(ids::SEGMENT),
+    ]
+}
+
+fn packet(track: u64, pts_ns: i64, duration_ns: Option<u64>, keyframe: bool, b: u8) -> Packet {
+    Packet {
+        track,
+        pts_ns,
+        duration_ns,
+        keyframe,
+        data: vec![b],
+    }
+}
+
+#[test]
+fn test_demuxer() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    let schema = ebml::schema::DefaultSchema::default();
+    let buf = ebml::Encoder::new(&schema).encode(mkv()).unwrap();
+    let mut demuxer = Demuxer::new(&schema);
+    let mut packets = vec![];
+    for chunk in buf.chunks(7) {
+        packets.append(&mut demuxer.demux(chunk.to_vec()).unwrap());
+    }
+    assert_eq!(demuxer.timecode_scale(), 100_000);
+    let tracks = demuxer.tracks();
+    assert_eq!(tracks.len(), 2);
+    assert_eq!(tracks[0].codec_id, "V_VP9");
+    assert_eq!(tracks[0].codec_private, Some(vec![1, 2, 3]));
+    assert_eq!(tracks[0].video.as_ref().unwrap().pixel_width, 640);
+    assert_eq!(tracks[1].uid, 22);
+    assert_eq!(
+        tracks[1].audio.as_ref().unwrap().sampling_frequency,
+        48000.0
+    );
+    assert_eq!(tracks[1].audio.as_ref().unwrap().channels, 2);
+    assert_eq!(
+        packets,
+        vec![
+            packet(1, 1_000_000_000, None, true, 0xA0),
+            packet(2, 1_000_500_000, Some(20_000_000), true, 0xB0),
+            packet(2, 1_020_500_000, Some(20_000_000), true, 0xB1),
+            packet(1, 1_040_000_000, Some(40_000_000), false, 0xA1),
+            packet(1, 1_999_900_000, None, true, 0xA2),
+        ]
+    );
+    // ClusterTimecode beyond i64 saturates
+    let mut elms = mkv();
+    let i = elms
+        .iter()
+        .position(|o| *o == uint(ids::TIMECODE, 10_000))
+        .unwrap();
+    elms[i] = uint(ids::TIMECODE, u64::MAX);
+    let buf = ebml::Encoder::new(&schema).encode(elms).unwrap();
+    let packets = Demuxer::new(&schema).demux(buf).unwrap();
+    assert!(packets[..4].iter().all(|o| o.pts_ns == i64::MAX));
+}
+
+fn tracks() -> Vec<Track> {
+    vec![
+        Track {
+            number: 1,
+            uid: 11,
+            track_type: 1,
+            codec_id: "V_VP9".to_string(),
+            codec_private: Some(vec![1, 2, 3]),
+            language: Some("und".to_string()),
+            video: Some(Video {
+                pixel_width: 640,
+                pixel_height: 480,
+            }),
+            ..Default::default()
+        },
+        Track {
+            number: 2,
+            uid: 22,
+            track_type: 2,
+            codec_id: "A_OPUS".to_string(),
+            default_duration: Some(20_000_000),
+            name: Some("音声".to_string()),
+            audio: Some(Audio {
+                sampling_frequency: 48000.0,
+                channels: 2,
+                bit_depth: None,
+            }),
+            ..Default::default()
+        },
+    ]
+}
+
+#[test]
+fn test_muxer() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    let schema = ebml::schema::DefaultSchema::default();
+    let mut packets = vec![];
+    for i in 0..30 {
+        // keyframe every second
+        packets.push(packet(1, i * 100_000_000, None, i % 10 == 0, i as u8));
+        packets.push(packet(
+            2,
+            i * 100_000_000 + 20_000_000,
+            Some(20_000_000),
+            true,
+            i as u8,
+        ));
+    }
+    // duration other than DefaultDuration needs BlockGroup
+    packets.push(packet(1, 3_000_000_000, Some(40_000_000), false, 0xFF));
+    let options = MuxerOptions {
+        max_cluster_duration_ns: 500_000_000,
+        ..Default::default()
+    };
+    let mut muxer = Muxer::with_options(&schema, tracks(), options, Cursor::new(vec![]));
+    for packet in packets.iter() {
+        muxer.write(packet.clone()).unwrap();
+    }
+    let buf = muxer.finish().unwrap().into_inner();
+
+    let mut demuxer = Demuxer::new(&schema);
+    let demuxed = demuxer.demux(buf.clone()).unwrap();
+    assert_eq!(demuxer.tracks(), &tracks()[..]);
+    assert_eq!(demuxed, packets);
+
+    // clusters start at the video keyframes
+    let mut reader = Cursor::new(buf);
+    let cues = Cues::read(&mut reader, &schema).unwrap();
+    assert_eq!(
+        cues.points.iter().map(|o| o.time).collect::<Vec<_>>(),
+        vec![0, 1000, 2000]
+    );
+    let index = SegmentIndex::read(&mut reader, &schema).unwrap();
+    assert_eq!(index.positions(ids::CLUSTER).count(), 0);
+    assert!(index.position(ids::TRACKS).is_some());
+
+    // Duration is filled on finish
+    let elms = ebml::Decoder::new(&schema)
+        .decode(reader.into_inner())
+        .unwrap()
+        .into_iter()
+        .map(Element::from)
+        .collect::<Vec<_>>();
+    assert!(elms.contains(
+        &FloatElement {
+            ebml_id: ids::DURATION,
+            value: 3040.0,
+            width: FloatWidth::F64,
+        }
+        .into()
+    ));
+
+    // the output starts at the current position of the writer
+    let mut writer = Cursor::new(vec![0xAA; 3]);
+    writer.seek(SeekFrom::End(0)).unwrap();
+    let mut muxer = Muxer::new(&schema, tracks(), writer);
+    for packet in packets.iter() {
+        muxer.write(packet.clone()).unwrap();
+    }
+    let buf = muxer.finish().unwrap().into_inner();
+    assert_eq!(&buf[..3], &[0xAA; 3]);
+    let mut reader = Cursor::new(buf[3..].to_vec());
+    assert_eq!(
+        Demuxer::new(&schema).demux(buf[3..].to_vec()).unwrap(),
+        packets
+    );
+    assert!(SegmentIndex::read(&mut reader, &schema)
+        .unwrap()
+        .position(ids::CUES)
+        .is_some());
+}
@@ -0,0 +1,132 @@
+use ebml::decoder::DecodeError;
+use ebml::ebml::{EbmlId, Element, UnsignedIntegerElement, Utf8Element};
+use ebml::ids;
+use ebml::schema::{
+    DefaultSchema, DefaultSchemaEntry, LayeredSchema, SchemaBuilder, SchemaDict, SchemaError,
+};
+
+mod common;
+use common::{end, start};
+
+// private elements not in schema.json
+const PRIVATE: EbmlId = EbmlId(0x1F00_0001);
+const PRIVATE_NUMBER: EbmlId = EbmlId(0x4001);
+const PRIVATE_NAME: EbmlId = EbmlId(0x4002);
+
+fn private_schema() -> SchemaBuilder {
+    let mut number = DefaultSchemaEntry::new("PrivateNumber", 'u', 2);
+    number.multiple = Some(true);
+    SchemaBuilder::new()
+        .element(PRIVATE, "Private", 'm', 1)
+        .entry(PRIVATE_NUMBER, number)
+        .element(PRIVATE_NAME, "PrivateName", '8', 2)
+}
+
+fn mkv() -> Vec<Element> {
+    vec![
+        start(ids::SEGMENT),
+        start(PRIVATE),
+        UnsignedIntegerElement {
+            ebml_id: PRIVATE_NUMBER,
+            value: 1,
+        }
+        .into(),
+        Utf8Element {
+            ebml_id: PRIVATE_NAME,
+            value: "private".to_string(),
+        }
+        .into(),
+        end(PRIVATE),
+        start(ids::INFO),
+        UnsignedIntegerElement {
+            ebml_id: ids::TIMECODE_SCALE,
+            value: 1_000_000,
+        }
+        .into(),
+        end(ids::INFO),
+        end(ids::SEGMENT),
+    ]
+}
+
+#[test]
+fn test_layered_schema() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    let schema =
+        LayeredSchema::new(DefaultSchema::default(), private_schema().build().unwrap()).unwrap();
+    assert_eq!(
+        schema.get_by_name("PrivateNumber").map(|(o, _)| o),
+        Some(PRIVATE_NUMBER)
+    );
+    assert_eq!(schema.get(ids::TITLE).map(|o| o.name()), Some("Title"));
+
+    let buf = ebml::Encoder::new(&schema).encode(mkv()).unwrap();
+    let elms = ebml::Decoder::new(&schema).decode(buf.clone()).unwrap();
+    assert_eq!(
+        elms.into_iter().map(Into::into).collect::<Vec<Element>>(),
+        mkv()
+    );
+    // the private elements are unknown to the default schema
+    let schema = DefaultSchema::default();
+    match ebml::Decoder::new(&schema).decode(buf).unwrap_err().kind() {
+        DecodeError::UnknownEbmlId(ebml_id) => assert_eq!(*ebml_id, PRIVATE),
+        o => panic!("{:?}", o),
+    }
+
+    // the overlay may be written in the form of schema.json
+    let overlay: DefaultSchema = serde_json::from_str(
+        r#"{ "16385": { "name": "PrivateNumber", "type": "u", "level": 2, "description": "" } }"#,
+    )
+    .unwrap();
+    assert!(LayeredSchema::new(DefaultSchema::default(), overlay).is_ok());
+}
+
+#[test]
+fn test_schema_conflicts() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    let layered = |builder: SchemaBuilder| {
+        LayeredSchema::new(DefaultSchema::default(), builder.build().unwrap()).map(|_| ())
+    };
+    // same ID or same name as the base
+    match layered(private_schema().element(ids::TITLE, "MyTitle", '8', 2)) {
+        Err(SchemaError::Conflict(ebml_id, _)) => assert_eq!(ebml_id, ids::TITLE),
+        o => panic!("{:?}", o),
+    }
+    match layered(private_schema().element(EbmlId(0x4003), "Title", '8', 2)) {
+        Err(SchemaError::Conflict(_, name)) => assert_eq!(name, "Title"),
+        o => panic!("{:?}", o),
+    }
+    // same ID or same name in the builder
+    match private_schema()
+        .element(PRIVATE_NAME, "PrivateName2", '8', 2)
+        .build()
+    {
+        Err(SchemaError::Conflict(ebml_id, _)) => assert_eq!(ebml_id, PRIVATE_NAME),
+        o => panic!("{:?}", o),
+    }
+    match private_schema()
+        .element(EbmlId(0x4003), "PrivateName", '8', 2)
+        .build()
+    {
+        Err(SchemaError::Conflict(_, name)) => assert_eq!(name, "PrivateName"),
+        o => panic!("{:?}", o),
+    }
+    // without the vint marker
+    match SchemaBuilder::new()
+        .element(EbmlId(0x1234), "Foo", 'u', 0)
+        .build()
+    {
+        Err(SchemaError::InvalidId(_)) => {}
+        o => panic!("{:?}", o),
+    }
+    for (name, r#type, level) in &[("", 'u', 0), ("Foo", 'x', 0), ("Foo", 'u', -2)] {
+        match SchemaBuilder::new()
+            .element(EbmlId(0x4003), name, *r#type, *level)
+            .build()
+        {
+            Err(SchemaError::InvalidEntry(_, _)) => {}
+            o => panic!("{:?}", o),
+        }
+    }
+}
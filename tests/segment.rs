@@ -0,0 +1,145 @@
+use ebml::ebml::{BinaryElement, Element, Utf8Element};
+use ebml::ids;
+use ebml::segment::SegmentIndex;
+use std::io::Cursor;
+
+mod common;
+use common::{end, start, uint};
+
+fn utf8(ebml_id: ebml::ebml::EbmlId, value: &str) -> Element {
+    Utf8Element {
+        ebml_id,
+        value: value.to_string(),
+    }
+    .into()
+}
+
+/// Segment with Tags after the clusters, without SeekHead
+fn mkv() -> Vec<Element> {
+    let mut elms = vec![
+        start(ids::EBML),
+        end(ids::EBML),
+        start(ids::SEGMENT),
+        start(ids::INFO),
+        uint(ids::TIMECODE_SCALE, 1_000_000),
+        end(ids::INFO),
+    ];
+    for cluster in 0..3 {
+        elms.push(start(ids::CLUSTER));
+        elms.push(uint(ids::TIMECODE, cluster * 1000));
+        elms.push(
+            BinaryElement {
+                ebml_id: ids::SIMPLE_BLOCK,
+                value: vec![0x81, 0x00, 0x00, 0x80, 0, 0, 0, 0],
+            }
+            .into(),
+        );
+        elms.push(end(ids::CLUSTER));
+    }
+    elms.extend(vec![
+        start(ids::TAGS),
+        start(ids::TAG),
+        start(ids::SIMPLE_TAG),
+        utf8(ids::TAG_NAME, "TITLE"),
+        utf8(ids::TAG_STRING, "ebml-rs"),
+        end(ids::SIMPLE_TAG),
+        end(ids::TAG),
+        end(ids::TAGS),
+        end(ids::SEGMENT),
+    ]);
+    elms
+}
+
+fn tags(elms: Vec<ebml::ebml::ElementDetail>) -> Vec<Element> {
+    elms.into_iter().map(Into::into).collect()
+}
+
+#[test]
+fn test_segment_index_seek_head() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    let schema = ebml::schema::DefaultSchema::default();
+    let buf = ebml::Encoder::new(&schema).encode(mkv()).unwrap();
+    let buf = ebml::webm::fixup(&schema, buf).unwrap();
+    let mut reader = Cursor::new(buf);
+    let index = SegmentIndex::read(&mut reader, &schema).unwrap();
+    // SeekHead does not list the clusters
+    assert_eq!(
+        index.entries.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+        vec![ids::SEEK_HEAD, ids::INFO, ids::TAGS, ids::CUES]
+    );
+    let elms = index
+        .load(&mut reader, &schema, ids::TAGS)
+        .unwrap()
+        .unwrap();
+    assert_eq!(tags(elms), mkv()[18..26].to_vec());
+    assert!(index
+        .load(&mut reader, &schema, ids::ATTACHMENTS)
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn test_segment_index_scan() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    let schema = ebml::schema::DefaultSchema::default();
+    let buf = ebml::Encoder::new(&schema).encode(mkv()).unwrap();
+    let mut reader = Cursor::new(buf);
+    let index = SegmentIndex::read(&mut reader, &schema).unwrap();
+    assert_eq!(index, SegmentIndex::scan(&mut reader).unwrap());
+    assert_eq!(
+        index.entries.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+        vec![
+            ids::INFO,
+            ids::CLUSTER,
+            ids::CLUSTER,
+            ids::CLUSTER,
+            ids::TAGS
+        ]
+    );
+    assert_eq!(index.positions(ids::CLUSTER).count(), 3);
+    assert_eq!(index.segment_end, Some(reader.get_ref().len() as u64));
+    let elms = index
+        .load(&mut reader, &schema, ids::TAGS)
+        .unwrap()
+        .unwrap();
+    assert_eq!(tags(elms), mkv()[18..26].to_vec());
+}
+
+#[test]
+fn test_segment_index_invalid_size() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    use ebml::segment::{read_element, SegmentError};
+    let schema = ebml::schema::DefaultSchema::default();
+    // Tags claiming 2^48 bytes
+    let buf = vec![
+        0x12, 0x54, 0xC3, 0x67, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+    match read_element(&mut Cursor::new(buf), &schema, 0) {
+        Err(SegmentError::Truncated(ebml_id, 0)) => assert_eq!(ebml_id, ids::TAGS),
+        o => panic!("{:?}", o),
+    }
+    let elms = vec![
+        start(ids::EBML),
+        end(ids::EBML),
+        start(ids::SEGMENT),
+        start(ids::SEEK_HEAD),
+        start(ids::SEEK),
+        BinaryElement {
+            ebml_id: ids::SEEK_ID,
+            value: ids::TAGS.into(),
+        }
+        .into(),
+        uint(ids::SEEK_POSITION, u64::MAX),
+        end(ids::SEEK),
+        end(ids::SEEK_HEAD),
+        end(ids::SEGMENT),
+    ];
+    let buf = ebml::Encoder::new(&schema).encode(elms).unwrap();
+    match SegmentIndex::read(&mut Cursor::new(buf), &schema) {
+        Err(SegmentError::InvalidSeekPosition(u64::MAX)) => {}
+        o => panic!("{:?}", o),
+    }
+}
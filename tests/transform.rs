@@ -0,0 +1,171 @@
+use ebml::ebml::{BinaryElement, Element, Utf8Element};
+use ebml::ids;
+use ebml::schema::DefaultSchema;
+use ebml::transform::{filter, insert_after, map, replace_subtree, run, Transform};
+use std::io::{Cursor, Read};
+
+mod common;
+use common::{end, start, start_unknown, uint};
+
+fn title(value: &str) -> Element {
+    Utf8Element {
+        ebml_id: ids::TITLE,
+        value: value.to_string(),
+    }
+    .into()
+}
+
+fn void() -> Element {
+    BinaryElement {
+        ebml_id: ids::VOID,
+        value: vec![0; 4],
+    }
+    .into()
+}
+
+fn cluster(timecode: u64) -> Vec<Element> {
+    vec![
+        start_unknown(ids::CLUSTER),
+        uint(ids::TIMECODE, timecode),
+        BinaryElement {
+            ebml_id: ids::SIMPLE_BLOCK,
+            value: vec![0x81, 0, 0, 0x80, 0],
+        }
+        .into(),
+        void(),
+        end(ids::CLUSTER),
+    ]
+}
+
+fn mkv(info: Vec<Element>, clusters: Vec<Vec<Element>>) -> Vec<Element> {
+    let mut elms = vec![start_unknown(ids::SEGMENT), start(ids::INFO)];
+    elms.extend(info);
+    elms.push(end(ids::INFO));
+    for cluster in clusters {
+        elms.extend(cluster);
+    }
+    elms.push(end(ids::SEGMENT));
+    elms
+}
+
+/// returns a few bytes at a time
+struct SlowReader(Cursor<Vec<u8>>);
+
+impl Read for SlowReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(3);
+        self.0.read(&mut buf[..len])
+    }
+}
+
+fn transform<T: Transform>(input: Vec<Element>, transform: &mut T) -> Vec<Element> {
+    let schema = DefaultSchema::default();
+    let buf = ebml::Encoder::new(&schema).encode(input).unwrap();
+    let mut output = vec![];
+    run(
+        &mut ebml::Decoder::new(&schema),
+        &mut ebml::Encoder::new(&schema),
+        transform,
+        &mut SlowReader(Cursor::new(buf)),
+        &mut output,
+    )
+    .unwrap();
+    let mut decoder = ebml::Decoder::new(&schema);
+    let mut elms = decoder.decode(output).unwrap();
+    elms.extend(decoder.finish().unwrap());
+    elms.into_iter().map(Into::into).collect()
+}
+
+#[test]
+fn test_transform() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    let input = mkv(vec![title("foo")], vec![cluster(0), cluster(1000)]);
+    // nothing changed
+    let mut identity = map(|o| o);
+    assert_eq!(transform(input.clone(), &mut identity), input);
+
+    let mut set_title = map(|o| match o {
+        Element::ChildElement(ref c) if c.ebml_id() == ids::TITLE => title("bar"),
+        o => o,
+    });
+    assert_eq!(
+        transform(input.clone(), &mut set_title),
+        mkv(vec![title("bar")], vec![cluster(0), cluster(1000)])
+    );
+
+    // drops the subtrees of the masters
+    let mut no_cluster = filter(|o| o.ebml_id() != ids::CLUSTER);
+    assert_eq!(
+        transform(input.clone(), &mut no_cluster),
+        mkv(vec![title("foo")], vec![])
+    );
+
+    let mut replace_info = replace_subtree(ids::INFO, |_| {
+        vec![
+            start(ids::INFO),
+            uint(ids::TIMECODE_SCALE, 1_000_000),
+            end(ids::INFO),
+        ]
+    });
+    assert_eq!(
+        transform(input.clone(), &mut replace_info),
+        mkv(
+            vec![uint(ids::TIMECODE_SCALE, 1_000_000)],
+            vec![cluster(0), cluster(1000)]
+        )
+    );
+
+    let mut timecode_scale = insert_after(ids::TITLE, vec![uint(ids::TIMECODE_SCALE, 1_000_000)]);
+    assert_eq!(
+        transform(input.clone(), &mut timecode_scale),
+        mkv(
+            vec![title("foo"), uint(ids::TIMECODE_SCALE, 1_000_000)],
+            vec![cluster(0), cluster(1000)]
+        )
+    );
+}
+
+#[test]
+fn test_transform_chain() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    let input = mkv(vec![title("foo")], vec![cluster(0), cluster(1000)]);
+    let without_void = |timecode| {
+        let mut elms = cluster(timecode);
+        elms.retain(|o| *o != void());
+        elms
+    };
+    // the second stage sees the output of the first one
+    let mut chain = filter(|o| o.ebml_id() != ids::VOID)
+        .then(replace_subtree(ids::CLUSTER, |mut elms| {
+            // moves the Void of the Cluster, which has been removed, after Timecode
+            elms.insert(2, void());
+            elms
+        }))
+        // Void would be a child of the unknown-size Cluster
+        .then(insert_after(
+            ids::CLUSTER,
+            vec![start(ids::TAGS), end(ids::TAGS)],
+        ));
+    let mut expected = vec![start_unknown(ids::SEGMENT), start(ids::INFO)];
+    expected.extend(vec![title("foo"), end(ids::INFO)]);
+    for timecode in &[0, 1000] {
+        let mut cluster = without_void(*timecode);
+        cluster.insert(2, void());
+        expected.extend(cluster);
+        expected.extend(vec![start(ids::TAGS), end(ids::TAGS)]);
+    }
+    expected.push(end(ids::SEGMENT));
+    assert_eq!(transform(input.clone(), &mut chain), expected);
+
+    // boxed transforms
+    let mut boxed: Box<dyn Transform> = Box::new(filter(|o| o.ebml_id() != ids::VOID));
+    assert_eq!(
+        transform(input, &mut boxed),
+        mkv(
+            vec![title("foo")],
+            vec![without_void(0), without_void(1000)]
+        )
+    );
+}
@@ -0,0 +1,298 @@
+use ebml::ebml::{BinaryElement, Element, Utf8Element};
+use ebml::ids;
+use ebml::matroska::{Muxer, Packet, Track};
+use ebml::validate::{validate, Finding, FindingKind};
+
+mod common;
+use common::{end, start, uint};
+
+fn utf8(ebml_id: ebml::ebml::EbmlId, value: &str) -> Element {
+    Utf8Element {
+        ebml_id,
+        value: value.to_string(),
+    }
+    .into()
+}
+
+fn info(mut elms: Vec<Element>) -> Vec<Element> {
+    let mut info = vec![
+        start(ids::INFO),
+        utf8(ids::MUXING_APP, "ebml-rs"),
+        utf8(ids::WRITING_APP, "ebml-rs"),
+    ];
+    info.append(&mut elms);
+    info.push(end(ids::INFO));
+    info
+}
+
+fn mkv(mut body: Vec<Element>) -> Vec<Element> {
+    let mut elms = vec![start(ids::EBML), end(ids::EBML), start(ids::SEGMENT)];
+    elms.append(&mut body);
+    elms.push(end(ids::SEGMENT));
+    elms
+}
+
+fn kinds(findings: &[Finding]) -> Vec<(FindingKind, &str)> {
+    findings.iter().map(|o| (o.kind, o.name.as_str())).collect()
+}
+
+#[test]
+fn test_validate_muxer_output() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    let schema = ebml::schema::DefaultSchema::default();
+    let track = Track {
+        number: 1,
+        uid: 1,
+        track_type: 2,
+        codec_id: "A_OPUS".to_string(),
+        ..Default::default()
+    };
+    let mut muxer = Muxer::new(&schema, vec![track], std::io::Cursor::new(vec![]));
+    for i in 0..10 {
+        muxer
+            .write(Packet {
+                track: 1,
+                pts_ns: i * 20_000_000,
+                duration_ns: None,
+                keyframe: true,
+                data: vec![0; 10],
+            })
+            .unwrap();
+    }
+    let buf = muxer.finish().unwrap().into_inner();
+    assert_eq!(validate(&schema, &buf), vec![]);
+}
+
+#[test]
+fn test_validate_findings() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    let schema = ebml::schema::DefaultSchema::default();
+    let encode = |elms| ebml::Encoder::new(&schema).encode(elms).unwrap();
+
+    let buf = encode(mkv(info(vec![])));
+    assert_eq!(validate(&schema, &buf), vec![]);
+
+    // TrackNumber is missing and TrackType is out of range
+    let buf = encode(mkv(vec![
+        start(ids::TRACKS),
+        start(ids::TRACK_ENTRY),
+        uint(ids::TRACK_UID, 1),
+        uint(ids::TRACK_TYPE, 0),
+        ebml::ebml::StringElement {
+            ebml_id: ids::CODEC_ID,
+            value: b"A_OPUS".to_vec(),
+        }
+        .into(),
+        end(ids::TRACK_ENTRY),
+        end(ids::TRACKS),
+    ]));
+    let findings = validate(&schema, &buf);
+    assert_eq!(
+        kinds(&findings),
+        vec![
+            (FindingKind::Range, "TrackType"),
+            (FindingKind::Mandatory, "TrackEntry"),
+            (FindingKind::Mandatory, "Segment"),
+        ]
+    );
+    // offset of TrackEntry
+    assert_eq!(findings[1].offset, 5 + 5 + 5);
+
+    // TimecodeScale twice, Cluster in Info; Timecode is in the right parent
+    let buf = encode(mkv(info(vec![
+        uint(ids::TIMECODE_SCALE, 1),
+        uint(ids::TIMECODE_SCALE, 1),
+        start(ids::CLUSTER),
+        uint(ids::TIMECODE, 0),
+        end(ids::CLUSTER),
+    ])));
+    assert_eq!(
+        kinds(&validate(&schema, &buf)),
+        vec![
+            (FindingKind::Multiple, "TimecodeScale"),
+            (FindingKind::Parent, "Cluster"),
+        ]
+    );
+    // Title in Tracks is at the level of Info
+    let mut elms = info(vec![]);
+    elms.extend(vec![
+        start(ids::TRACKS),
+        utf8(ids::TITLE, "foo"),
+        end(ids::TRACKS),
+    ]);
+    let findings = validate(&schema, &encode(mkv(elms)));
+    assert_eq!(
+        kinds(&findings),
+        vec![
+            (FindingKind::Parent, "Title"),
+            (FindingKind::Mandatory, "Tracks"),
+        ]
+    );
+    assert_eq!(findings[0].message, "found in Tracks but must be in Info");
+
+    // CRC-32 of Info
+    let crc = |value: u32| -> Vec<Element> {
+        vec![BinaryElement {
+            ebml_id: ids::CRC32,
+            value: value.to_le_bytes().to_vec(),
+        }
+        .into()]
+    };
+    let buf = encode(mkv(info(crc(1))));
+    let findings = validate(&schema, &buf);
+    assert_eq!(kinds(&findings), vec![(FindingKind::Crc32, "Info")]);
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&encode(info(vec![]))[5..]);
+    let checksum = hasher.finalize();
+    let buf = encode(mkv(info(crc(checksum))));
+    // CRC-32 should be the first child
+    assert_eq!(
+        kinds(&validate(&schema, &buf)),
+        vec![(FindingKind::Crc32, "Info")]
+    );
+    let mut elms = info(vec![]);
+    elms.insert(1, crc(checksum).remove(0));
+    assert_eq!(validate(&schema, &encode(mkv(elms))), vec![]);
+
+    // truncated
+    let buf = encode(mkv(info(vec![])));
+    let findings = validate(&schema, &buf[..buf.len() - 3]);
+    assert_eq!(kinds(&findings), vec![(FindingKind::Decode, "")]);
+}
+
+/// returns a few bytes at a time
+struct SlowReader<'a>(&'a [u8]);
+
+impl std::io::Read for SlowReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(self.0.len()).min(1000);
+        buf[..len].copy_from_slice(&self.0[..len]);
+        self.0 = &self.0[len..];
+        Ok(len)
+    }
+}
+
+#[test]
+fn test_validate_reader() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    use ebml::validate::validate_reader;
+    let schema = ebml::schema::DefaultSchema::default();
+    let encode = |elms| ebml::Encoder::new(&schema).encode(elms).unwrap();
+    // CRC-32 over many chunks
+    let title = utf8(ids::TITLE, &"a".repeat(10_000));
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&encode(info(vec![title.clone()]))[6..]);
+    let mut elms = info(vec![title]);
+    elms.insert(
+        1,
+        BinaryElement {
+            ebml_id: ids::CRC32,
+            value: hasher.finalize().to_le_bytes().to_vec(),
+        }
+        .into(),
+    );
+    let buf = encode(mkv(elms));
+    assert_eq!(
+        validate_reader(&schema, &mut SlowReader(&buf)).unwrap(),
+        vec![]
+    );
+    let mut broken = buf.clone();
+    let len = broken.len();
+    broken[len - 10] = b'b';
+    assert_eq!(
+        kinds(&validate_reader(&schema, &mut SlowReader(&broken)).unwrap()),
+        vec![(FindingKind::Crc32, "Info")]
+    );
+}
+
+#[test]
+fn test_validate_layered_schema() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    use ebml::schema::{DefaultSchema, DefaultSchemaEntry, LayeredSchema, SchemaBuilder};
+    // private master in Segment with a mandatory child
+    let private = ebml::ebml::EbmlId(0x1F00_0001);
+    let private_number = ebml::ebml::EbmlId(0x4001);
+    let mut master = DefaultSchemaEntry::new("Private", 'm', 1);
+    master.path = Some("\\Segment\\Private".to_string());
+    let mut number = DefaultSchemaEntry::new("PrivateNumber", 'u', 2);
+    number.path = Some("\\Segment\\Private\\PrivateNumber".to_string());
+    number.mandatory = Some(true);
+    let overlay = SchemaBuilder::new()
+        .entry(private, master)
+        .entry(private_number, number)
+        .build()
+        .unwrap();
+    let schema = LayeredSchema::new(DefaultSchema::default(), overlay).unwrap();
+    let encode = |elms| ebml::Encoder::new(&schema).encode(elms).unwrap();
+    let mut elms = info(vec![]);
+    elms.extend(vec![start(private), uint(private_number, 1), end(private)]);
+    assert_eq!(validate(&schema, &encode(mkv(elms))), vec![]);
+    let mut elms = info(vec![]);
+    elms.extend(vec![start(private), end(private)]);
+    assert_eq!(
+        kinds(&validate(&schema, &encode(mkv(elms)))),
+        vec![(FindingKind::Mandatory, "Private")]
+    );
+    // in the wrong parent
+    let elms = info(vec![start(private), uint(private_number, 1), end(private)]);
+    assert_eq!(
+        kinds(&validate(&schema, &encode(mkv(elms)))),
+        vec![(FindingKind::Parent, "Private")]
+    );
+}
+
+#[test]
+fn test_validate_negative_range() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    use ebml::ebml::{FloatElement, FloatWidth, IntegerElement};
+    use ebml::schema::{DefaultSchema, DefaultSchemaEntry, LayeredSchema, SchemaBuilder};
+    let private_offset = ebml::ebml::EbmlId(0x4001);
+    let private_gain = ebml::ebml::EbmlId(0x4002);
+    let mut offset = DefaultSchemaEntry::new("PrivateOffset", 'i', 2);
+    offset.path = Some("\\Segment\\Info\\PrivateOffset".to_string());
+    offset.range = Some("-8--1".to_string());
+    let mut gain = DefaultSchemaEntry::new("PrivateGain", 'f', 2);
+    gain.path = Some("\\Segment\\Info\\PrivateGain".to_string());
+    gain.range = Some(">= -1.5".to_string());
+    let overlay = SchemaBuilder::new()
+        .entry(private_offset, offset)
+        .entry(private_gain, gain)
+        .build()
+        .unwrap();
+    let schema = LayeredSchema::new(DefaultSchema::default(), overlay).unwrap();
+    let findings = |offset: i64, gain: f64| {
+        let elms = info(vec![
+            IntegerElement {
+                ebml_id: private_offset,
+                value: offset,
+            }
+            .into(),
+            FloatElement {
+                ebml_id: private_gain,
+                value: gain,
+                width: FloatWidth::F64,
+            }
+            .into(),
+        ]);
+        let buf = ebml::Encoder::new(&schema).encode(mkv(elms)).unwrap();
+        validate(&schema, &buf)
+    };
+    assert_eq!(kinds(&findings(-8, -1.5)), vec![]);
+    assert_eq!(kinds(&findings(-1, 0.0)), vec![]);
+    assert_eq!(
+        kinds(&findings(0, -2.0)),
+        vec![
+            (FindingKind::Range, "PrivateOffset"),
+            (FindingKind::Range, "PrivateGain")
+        ]
+    );
+    assert_eq!(
+        kinds(&findings(-9, -1.0)),
+        vec![(FindingKind::Range, "PrivateOffset")]
+    );
+}
@@ -3,20 +3,20 @@ use ebml::vint::{read_vint, write_vint};
 #[test]
 fn test_ebml_id() {
     let data = vec![
-        (0, 1),
-        (i64::pow(2, 7) - 1, 1),
-        (i64::pow(2, 7), 2),
-        (i64::pow(2, 14) - 1, 2),
-        (i64::pow(2, 14), 3),
-        (i64::pow(2, 21) - 1, 3),
-        (i64::pow(2, 21), 4),
-        (i64::pow(2, 28) - 1, 4),
+        (0x80, 1),
+        (0xFE, 1),
+        (0x4000, 2),
+        (0x7FFE, 2),
+        (0x20_0000, 3),
+        (0x3F_FFFE, 3),
+        (0x1000_0000, 4),
+        (0x1FFF_FFFE, 4),
     ];
     for (value, lenfth) in data {
         let id: ebml::ebml::EbmlId = value.into();
         let buf: Vec<u8> = id.into();
         let o = ebml::vint::read_vint(&buf, 0).unwrap().unwrap();
-        assert_eq!(o.value, value);
+        assert_eq!(o.value | 1 << (7 * lenfth), value);
         assert_eq!(o.length, lenfth);
     }
 }
@@ -0,0 +1,203 @@
+use ebml::ebml::{BinaryElement, Element, ElementDetail, MasterElement, StringElement};
+use ebml::ids;
+
+mod common;
+use common::{end, start, start_unknown, uint};
+
+fn simple_block(track: u8, timecode: i16, keyframe: bool) -> Element {
+    let mut value = vec![0b_1000_0000 | track];
+    value.extend_from_slice(&timecode.to_be_bytes());
+    value.push(if keyframe { 0b_1000_0000 } else { 0 });
+    value.extend_from_slice(&[0, 1, 2, 3]);
+    BinaryElement {
+        ebml_id: ids::SIMPLE_BLOCK,
+        value,
+    }
+    .into()
+}
+
+/// MediaRecorder style webm: unknown-size Segment and Clusters without Duration, SeekHead and Cues
+fn live_webm() -> Vec<u8> {
+    let schema = ebml::schema::DefaultSchema::default();
+    let mut encoder = ebml::Encoder::new(&schema);
+    let mut elms = vec![
+        start(ids::EBML),
+        StringElement {
+            ebml_id: ids::DOC_TYPE,
+            value: b"webm".to_vec(),
+        }
+        .into(),
+        end(ids::EBML),
+        start_unknown(ids::SEGMENT),
+        start(ids::INFO),
+        uint(ids::TIMECODE_SCALE, 1_000_000),
+        end(ids::INFO),
+        start(ids::TRACKS),
+        start(ids::TRACK_ENTRY),
+        uint(ids::TRACK_NUMBER, 1),
+        end(ids::TRACK_ENTRY),
+        end(ids::TRACKS),
+    ];
+    for cluster in 0..3 {
+        elms.push(start_unknown(ids::CLUSTER));
+        elms.push(uint(ids::TIMECODE, cluster * 1000));
+        for i in 0..10 {
+            elms.push(simple_block(1, i * 100, i == 0));
+        }
+    }
+    // unknown-size elements are never closed in live streams
+    encoder.encode(elms).unwrap()
+}
+
+#[test]
+fn test_fixup() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    let schema = ebml::schema::DefaultSchema::default();
+    let buf = ebml::webm::fixup(&schema, live_webm()).unwrap();
+    let mut decoder = ebml::Decoder::new(&schema);
+    let elms = decoder.decode(buf).unwrap();
+
+    let segment = elms
+        .iter()
+        .find_map(|o| match o {
+            ElementDetail::MasterElement((MasterElement::MasterStartElement(o), pos))
+                if o.ebml_id == ids::SEGMENT =>
+            {
+                Some((*o, *pos))
+            }
+            _ => None,
+        })
+        .unwrap();
+    assert!(!segment.0.unknown_size);
+    // SeekHead is the first element of the Segment
+    let data_start = elms
+        .iter()
+        .find_map(|o| match o {
+            ElementDetail::MasterElement((MasterElement::MasterStartElement(o), pos))
+                if o.ebml_id == ids::SEEK_HEAD =>
+            {
+                Some(pos.tag_start)
+            }
+            _ => None,
+        })
+        .unwrap();
+    let top_level_start = |ebml_id| {
+        elms.iter()
+            .find_map(|o| match o {
+                ElementDetail::MasterElement((MasterElement::MasterStartElement(o), pos))
+                    if o.ebml_id == ebml_id =>
+                {
+                    Some(pos.tag_start - data_start)
+                }
+                _ => None,
+            })
+            .unwrap() as u64
+    };
+    let clusters = elms
+        .iter()
+        .filter_map(|o| match o {
+            ElementDetail::MasterElement((MasterElement::MasterStartElement(o), pos))
+                if o.ebml_id == ids::CLUSTER =>
+            {
+                assert!(!o.unknown_size);
+                Some((pos.tag_start - data_start) as u64)
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(clusters.len(), 3);
+    // every unknown-size master is closed
+    let ends = elms
+        .iter()
+        .filter(|o| match o {
+            ElementDetail::MasterElement((MasterElement::MasterEndElement(o), _)) => {
+                o.ebml_id == ids::CLUSTER || o.ebml_id == ids::SEGMENT
+            }
+            _ => false,
+        })
+        .count();
+    assert_eq!(ends, 4);
+
+    let duration = elms.iter().find_map(|o| match o {
+        ElementDetail::ChildElement((ebml::ebml::ChildElement::FloatElement(o), _))
+            if o.ebml_id == ids::DURATION =>
+        {
+            Some(o.value)
+        }
+        _ => None,
+    });
+    assert_eq!(duration, Some(2900.0));
+
+    let uints = |ebml_id| {
+        elms.iter()
+            .filter_map(|o| match o {
+                ElementDetail::ChildElement((
+                    ebml::ebml::ChildElement::UnsignedIntegerElement(o),
+                    _,
+                )) if o.ebml_id == ebml_id => Some(o.value),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(uints(ids::CUE_TIME), vec![0, 1000, 2000]);
+    assert_eq!(uints(ids::CUE_TRACK), vec![1, 1, 1]);
+    assert_eq!(uints(ids::CUE_CLUSTER_POSITION), clusters);
+
+    let seek_ids = elms
+        .iter()
+        .filter_map(|o| match o {
+            ElementDetail::ChildElement((ebml::ebml::ChildElement::BinaryElement(o), _))
+                if o.ebml_id == ids::SEEK_ID =>
+            {
+                Some(o.value.clone())
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let seek_positions = uints(ids::SEEK_POSITION);
+    let expected = [ids::INFO, ids::TRACKS, ids::CUES];
+    assert_eq!(
+        seek_ids,
+        expected
+            .iter()
+            .map(|&o| Vec::<u8>::from(o))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        seek_positions,
+        expected
+            .iter()
+            .map(|&o| top_level_start(o))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_fixup_idempotent() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    let schema = ebml::schema::DefaultSchema::default();
+    let buf = ebml::webm::fixup(&schema, live_webm()).unwrap();
+    let buf2 = ebml::webm::fixup(&schema, buf.clone()).unwrap();
+    assert_eq!(buf, buf2);
+}
+
+#[test]
+fn test_fixup_large_timecode() {
+    dotenv::dotenv().ok();
+    env_logger::try_init().ok();
+    let schema = ebml::schema::DefaultSchema::default();
+    let elms = vec![
+        start_unknown(ids::SEGMENT),
+        start(ids::INFO),
+        uint(ids::TIMECODE_SCALE, 1_000_000),
+        end(ids::INFO),
+        start_unknown(ids::CLUSTER),
+        uint(ids::TIMECODE, u64::MAX),
+        simple_block(1, 100, true),
+    ];
+    let buf = ebml::Encoder::new(&schema).encode(elms).unwrap();
+    // block timecodes beyond i64 saturate
+    assert!(ebml::webm::fixup(&schema, buf).is_ok());
+}